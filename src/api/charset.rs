@@ -0,0 +1,118 @@
+//! # JSON charset
+//!
+//! This module provides a fairing to add the charset parameter to the JSON responses,
+//! as `application/json; charset=utf-8`, for strict clients expecting it.
+
+use config::EnvironmentConfigurable;
+use ErrorResult;
+use rocket::{Request, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::ContentType;
+use std::env;
+
+/*   -------------------------------------------------------------
+     JSON charset
+
+     :: Fairing
+     :: EnvironmentConfigurable
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A fairing to add the charset parameter to the JSON responses, when enabled.
+///
+/// The JSON responses with another charset parameter are left untouched.
+pub struct JsonCharset {
+    enabled: bool,
+}
+
+impl JsonCharset {
+    pub fn new (enabled: bool) -> Self {
+        Self {
+            enabled,
+        }
+    }
+
+    pub fn is_enabled (&self) -> bool {
+        self.enabled
+    }
+
+    /// Gets the content type to use instead of the response one, or None to keep it.
+    fn get_content_type (&self, content_type: &ContentType) -> Option<ContentType> {
+        if !self.enabled || *content_type != ContentType::JSON || content_type.params().next().is_some() {
+            return None;
+        }
+
+        Some(ContentType::with_params("application", "json", ("charset", "utf-8")))
+    }
+}
+
+impl Fairing for JsonCharset {
+    fn info(&self) -> Info {
+        Info {
+            name: "JSON charset",
+            kind: Kind::Response,
+        }
+    }
+
+    fn on_response(&self, _request: &Request, response: &mut Response) {
+        let content_type = match response.content_type() {
+            Some(content_type) => content_type,
+            None => return,
+        };
+
+        if let Some(content_type) = self.get_content_type(&content_type) {
+            response.set_header(content_type);
+        }
+    }
+}
+
+/// Configures the fairing from the following environment variable:
+///
+///   - `LF_JSON_CHARSET` (facultative): if set, the JSON responses are sent
+///     as `application/json; charset=utf-8`
+impl EnvironmentConfigurable for JsonCharset {
+    fn parse_environment() -> ErrorResult<Self> {
+        Ok(Self::new(env::var("LF_JSON_CHARSET").is_ok()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::{Data, Route};
+    use rocket::handler::Outcome as HandlerOutcome;
+    use rocket::http::Method;
+    use rocket::local::Client;
+    use rocket::response::content::Json;
+
+    fn get_json<'r>(request: &'r Request, _data: Data) -> HandlerOutcome<'r> {
+        HandlerOutcome::from(request, Json("{}"))
+    }
+
+    fn get_content_type_header (enabled: bool) -> Option<String> {
+        let server = ::rocket::ignite()
+            .attach(JsonCharset::new(enabled))
+            .mount("/", vec![Route::new(Method::Get, "/", get_json)]);
+        let client = Client::untracked(server).unwrap();
+
+        let response = client.get("/").dispatch();
+        response.headers().get_one("Content-Type").map(|value| value.to_string())
+    }
+
+    #[test]
+    fn test_content_type_when_enabled () {
+        assert_eq!(Some(String::from("application/json; charset=utf-8")), get_content_type_header(true));
+    }
+
+    #[test]
+    fn test_content_type_when_disabled () {
+        assert_eq!(Some(String::from("application/json")), get_content_type_header(false));
+    }
+
+    #[test]
+    fn test_content_type_ignores_other_types () {
+        let fairing = JsonCharset::new(true);
+
+        assert_eq!(None, fairing.get_content_type(&ContentType::Plain));
+        assert_eq!(None, fairing.get_content_type(&ContentType::HTML));
+    }
+}
@@ -0,0 +1,160 @@
+//! # Client errors logging
+//!
+//! This module provides a fairing to log the 4xx responses at debug level,
+//! to spot recurring client errors, like a broken client sending invalid requests.
+
+use config::EnvironmentConfigurable;
+use ErrorResult;
+use log::{Level, Log, Record};
+use rocket::{Request, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Method, Status};
+use std::env;
+
+/*   -------------------------------------------------------------
+     Client errors logger
+
+     :: Fairing
+     :: EnvironmentConfigurable
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A fairing to log the responses with a client error status, when enabled.
+pub struct ClientErrorLogger {
+    enabled: bool,
+    logger: Option<&'static dyn Log>,
+}
+
+impl ClientErrorLogger {
+    pub fn new (enabled: bool) -> Self {
+        Self {
+            enabled,
+            logger: None,
+        }
+    }
+
+    /// Allows to log the client errors to another logger than the global one.
+    pub fn with_logger (mut self, logger: &'static dyn Log) -> Self {
+        self.logger = Some(logger);
+
+        self
+    }
+
+    pub fn is_enabled (&self) -> bool {
+        self.enabled
+    }
+
+    /// Describes the response to log, or returns None if it shouldn't be logged.
+    fn get_log_line (&self, method: Method, path: &str, status: Status) -> Option<String> {
+        if !self.enabled || status.code < 400 || status.code >= 500 {
+            return None;
+        }
+
+        Some(format!("{} {} - {} {}", method, path, status.code, status.reason))
+    }
+
+    /// Logs the line to the configured logger, or to the global one if debug level is enabled.
+    fn log(&self, line: &str) {
+        let logger = match self.logger {
+            Some(logger) => logger,
+            None if Level::Debug <= ::log::max_level() => ::log::logger(),
+            None => return,
+        };
+
+        logger.log(&Record::builder()
+            .args(format_args!("{}", line))
+            .level(Level::Debug)
+            .target("api")
+            .build());
+    }
+}
+
+impl Fairing for ClientErrorLogger {
+    fn info(&self) -> Info {
+        Info {
+            name: "Client errors logger",
+            kind: Kind::Response,
+        }
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        if let Some(line) = self.get_log_line(request.method(), request.uri().path(), response.status()) {
+            self.log(&line);
+        }
+    }
+}
+
+/// Configures the fairing from the following environment variable:
+///
+///   - `LF_LOG_CLIENT_ERRORS` (facultative): if set, the 4xx responses are logged at debug level
+impl EnvironmentConfigurable for ClientErrorLogger {
+    fn parse_environment() -> ErrorResult<Self> {
+        Ok(Self::new(env::var("LF_LOG_CLIENT_ERRORS").is_ok()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use logger::CapturingLogger;
+    use rocket::{Data, Route};
+    use rocket::handler::Outcome;
+    use rocket::local::Client;
+
+    fn conflict_handler<'r>(_request: &'r Request, _data: Data) -> Outcome<'r> {
+        Outcome::failure(Status::Conflict)
+    }
+
+    #[test]
+    fn test_client_error_is_logged () {
+        let logger: &'static CapturingLogger = Box::leak(Box::new(CapturingLogger::new()));
+
+        let server = ::rocket::ignite()
+            .attach(ClientErrorLogger::new(true).with_logger(logger))
+            .mount("/", vec![Route::new(Method::Post, "/players", conflict_handler)]);
+        let client = Client::untracked(server).unwrap();
+
+        let response = client.post("/players").dispatch();
+        assert_eq!(Status::Conflict, response.status());
+
+        assert_eq!(vec!["DEBUG api: POST /players - 409 Conflict"], logger.get_records());
+    }
+
+    #[test]
+    fn test_client_error_is_not_logged_when_disabled () {
+        let logger: &'static CapturingLogger = Box::leak(Box::new(CapturingLogger::new()));
+
+        let server = ::rocket::ignite()
+            .attach(ClientErrorLogger::new(false).with_logger(logger))
+            .mount("/", vec![Route::new(Method::Post, "/players", conflict_handler)]);
+        let client = Client::untracked(server).unwrap();
+
+        client.post("/players").dispatch();
+
+        assert!(logger.get_records().is_empty());
+    }
+
+    #[test]
+    fn test_log_line_when_enabled () {
+        let logger = ClientErrorLogger::new(true);
+
+        assert_eq!(
+            Some(String::from("POST /players - 409 Conflict")),
+            logger.get_log_line(Method::Post, "/players", Status::Conflict)
+        );
+    }
+
+    #[test]
+    fn test_log_line_when_disabled () {
+        let logger = ClientErrorLogger::new(false);
+
+        assert_eq!(None, logger.get_log_line(Method::Post, "/players", Status::Conflict));
+    }
+
+    #[test]
+    fn test_log_line_ignores_other_statuses () {
+        let logger = ClientErrorLogger::new(true);
+
+        assert_eq!(None, logger.get_log_line(Method::Get, "/players", Status::Ok));
+        assert_eq!(None, logger.get_log_line(Method::Get, "/players", Status::InternalServerError));
+    }
+}
@@ -2,63 +2,1050 @@
 //!
 //! This module provides reusable guards to use with Rocket.
 
-use rocket::data::{FromDataSimple, Outcome};
-use rocket::{Data, Request};
-use rocket::http::Status;
-use rocket::Outcome::{Failure, Success};
+use rocket::data::{DataStream, FromDataSimple, Outcome};
+use rocket::{Data, Request, State};
+use rocket::http::{ContentType, Status};
+use rocket::Outcome::{Failure, Forward, Success};
+use rocket::request::{FromRequest, Outcome as RequestOutcome};
+#[cfg(feature = "serialization")]
+use rocket::response::status::Custom;
+#[cfg(feature = "serialization")]
+use rocket_contrib::json::Json;
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "serialization")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "serialization")]
+use serde_json;
+#[cfg(feature = "serialization")]
+use serde_urlencoded;
 
-use std::io::Read;
+use std::io::{Error as IoError, ErrorKind, Read};
+use std::marker::PhantomData;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::OnceLock;
 
-/// The maximum number of characters to read, to avoid DoS
-const REQUEST_BODY_LIMIT: u64 = 1_000_000;
+/// The default maximum number of characters to read, to avoid DoS
+pub const REQUEST_BODY_LIMIT: u64 = 1_000_000;
+
+/// The effective maximum number of characters to read, when overridden by the configuration
+static EFFECTIVE_REQUEST_BODY_LIMIT: OnceLock<u64> = OnceLock::new();
+
+/// Sets the effective request body limit, returning false if it has already been set.
+pub fn set_request_body_limit (limit: u64) -> bool {
+    EFFECTIVE_REQUEST_BODY_LIMIT.set(limit).is_ok()
+}
+
+/// Gets the effective request body limit, or the `REQUEST_BODY_LIMIT` default value.
+pub fn get_request_body_limit () -> u64 {
+    *EFFECTIVE_REQUEST_BODY_LIMIT.get().unwrap_or(&REQUEST_BODY_LIMIT)
+}
+
+/// The request body limits overriding the effective limit for some content types
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ContentTypeBodyLimits {
+    /// The limits, keyed by media type without parameters, e.g. `application/json`
+    limits: Vec<(String, u64)>,
+}
+
+impl ContentTypeBodyLimits {
+    pub fn new () -> Self {
+        Self::default()
+    }
+
+    pub fn with_limit (mut self, media_type: &str, limit: u64) -> Self {
+        self.limits.push((media_type.trim().to_lowercase(), limit));
+
+        self
+    }
+
+    /// Parses a comma-separated list of limits by media type,
+    /// e.g. `application/json=1000000,application/octet-stream=50000000`.
+    ///
+    /// Returns None if an entry isn't a media type followed by a positive limit.
+    pub fn parse (value: &str) -> Option<Self> {
+        value
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .try_fold(Self::new(), |limits, entry| {
+                let mut parts = entry.splitn(2, '=');
+                let media_type = parts.next()?.trim();
+                let limit = parts.next()?.trim().parse::<u64>().ok()?;
+
+                if !media_type.contains('/') || limit == 0 {
+                    return None;
+                }
+
+                Some(limits.with_limit(media_type, limit))
+            })
+    }
+
+    pub fn is_empty (&self) -> bool {
+        self.limits.is_empty()
+    }
+
+    /// Gets the limit for a content type, or None if the content type isn't listed.
+    pub fn get_limit (&self, content_type: &ContentType) -> Option<u64> {
+        let media_type = format!("{}/{}", content_type.top(), content_type.sub()).to_lowercase();
+
+        self.limits
+            .iter()
+            .find(|(listed_type, _)| *listed_type == media_type)
+            .map(|(_, limit)| *limit)
+    }
+}
+
+/// The request body limits by content type, when set by the configuration
+static CONTENT_TYPE_BODY_LIMITS: OnceLock<ContentTypeBodyLimits> = OnceLock::new();
+
+/// Sets the request body limits by content type, returning false if they have already been set.
+pub fn set_content_type_body_limits (limits: ContentTypeBodyLimits) -> bool {
+    CONTENT_TYPE_BODY_LIMITS.set(limits).is_ok()
+}
+
+/// Gets the request body limit for the content type of a request,
+/// falling back to the effective request body limit for the unlisted types.
+pub fn get_request_body_limit_for (content_type: Option<&ContentType>) -> u64 {
+    CONTENT_TYPE_BODY_LIMITS
+        .get()
+        .and_then(|limits| content_type.and_then(|content_type| limits.get_limit(content_type)))
+        .unwrap_or_else(get_request_body_limit)
+}
 
 /// A String representation of the request body. Useful when you need to pass it through as is.
+///
+/// The body is rejected with a 413 Payload Too Large if it exceeds the request body limit
+/// for the content type of the request.
 #[derive(Serialize, Deserialize, PartialOrd, PartialEq, Eq, Ord)]
 pub struct RequestBody {
     /// The UTF-8 content of the request body
     pub content: String,
 }
 
-impl RequestBody {
-    pub fn new () -> Self {
+impl RequestBody {
+    pub fn new () -> Self {
+        Self {
+            content: String::new(),
+        }
+    }
+
+    /// Builds a request body from bytes, replacing the invalid UTF-8 sequences
+    /// by the U+FFFD replacement character.
+    pub fn from_bytes_lossy (bytes: &[u8]) -> Self {
+        Self {
+            content: String::from_utf8_lossy(bytes).into_owned(),
+        }
+    }
+
+    /// Convert the request body into a string
+    pub fn into_string (self) -> String {
+        self.content
+    }
+
+    /// Convert the request body into a string, or None if it's empty
+    pub fn into_optional_string (self) -> Option<String> {
+        if self.content.is_empty() {
+            None
+        } else {
+            Some(self.content)
+        }
+    }
+}
+
+impl FromDataSimple for RequestBody {
+    type Error = String;
+
+    fn from_data(request: &Request, data: Data) -> Outcome<Self, Self::Error> {
+        match read_bounded_request_body(data.open(), get_request_body_limit_for(request.content_type())) {
+            Ok(content) => Success(Self { content }),
+            Err(failure) => Failure(failure),
+        }
+    }
+}
+
+/// A reader failing with an InvalidData error when more than `limit` bytes are read.
+///
+/// Contrary to `Read::take`, an oversized stream isn't silently truncated.
+pub struct LimitedReader<R: Read> {
+    inner: R,
+    limit: u64,
+    read: u64,
+}
+
+impl<R: Read> LimitedReader<R> {
+    pub fn new (inner: R, limit: u64) -> Self {
+        Self {
+            inner,
+            limit,
+            read: 0,
+        }
+    }
+
+    /// Gets the number of bytes read so far
+    pub fn get_read_count (&self) -> u64 {
+        self.read
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // Reads one byte more than the limit allows, to detect an oversized stream
+        let allowed = self.limit.saturating_sub(self.read).saturating_add(1);
+        let max = (buf.len() as u64).min(allowed) as usize;
+
+        let count = self.inner.read(&mut buf[..max])?;
+        self.read += count as u64;
+
+        if self.read > self.limit {
+            return Err(IoError::new(
+                ErrorKind::InvalidData,
+                format!("The request body exceeds the limit of {} bytes.", self.limit),
+            ));
+        }
+
+        Ok(count)
+    }
+}
+
+/// A String representation of the request body, rejected with a 413 Payload Too Large
+/// if it exceeds `LIMIT` bytes, instead of being truncated.
+///
+/// The limit is set by route, e.g. to accept larger bodies on an upload endpoint,
+/// and to reject anything but tiny ones on a webhook. By default, it's `REQUEST_BODY_LIMIT`.
+///
+/// # Examples
+///
+/// ```
+/// #[post("/webhook", data="<body>")]
+/// pub fn post_webhook(body: BoundedRequestBody<4096>) -> Status {
+///     // ...
+/// }
+/// ```
+#[derive(Debug, PartialEq, Eq)]
+pub struct BoundedRequestBody<const LIMIT: u64 = REQUEST_BODY_LIMIT> {
+    /// The UTF-8 content of the request body
+    pub content: String,
+}
+
+impl<const LIMIT: u64> BoundedRequestBody<LIMIT> {
+    /// Gets the maximum size of the request body, in bytes
+    pub fn get_limit () -> u64 {
+        LIMIT
+    }
+
+    /// Convert the request body into a string
+    pub fn into_string (self) -> String {
+        self.content
+    }
+}
+
+impl<const LIMIT: u64> FromDataSimple for BoundedRequestBody<LIMIT> {
+    type Error = String;
+
+    fn from_data(_request: &Request, data: Data) -> Outcome<Self, Self::Error> {
+        match read_bounded_request_body(data.open(), LIMIT) {
+            Ok(content) => Success(Self { content }),
+            Err(failure) => Failure(failure),
+        }
+    }
+}
+
+/// This trait allows to restrict the media types accepted by `TypedRequestBody`.
+pub trait AcceptedMediaTypes {
+    /// Determines if a request body with this content type should be accepted.
+    fn accepts(content_type: &ContentType) -> bool;
+}
+
+/// Accepts only `text/plain` request bodies.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PlainText;
+
+impl AcceptedMediaTypes for PlainText {
+    fn accepts(content_type: &ContentType) -> bool {
+        *content_type == ContentType::Plain
+    }
+}
+
+/// A String representation of the request body, rejected with a 415 Unsupported Media Type
+/// if the Content-Type of the request isn't accepted by `M`, by default `text/plain`.
+///
+/// The size and encoding are checked like for `BoundedRequestBody`, with the request body
+/// limit for the content type of the request.
+///
+/// # Examples
+///
+/// ```
+/// struct Csv;
+///
+/// impl AcceptedMediaTypes for Csv {
+///     fn accepts(content_type: &ContentType) -> bool {
+///         content_type.top() == "text" && content_type.sub() == "csv"
+///     }
+/// }
+///
+/// #[post("/import", data="<body>")]
+/// pub fn import(body: TypedRequestBody<Csv>) -> Status {
+///     // ...
+/// }
+/// ```
+#[derive(Debug, PartialEq, Eq)]
+pub struct TypedRequestBody<M: AcceptedMediaTypes = PlainText> {
+    /// The UTF-8 content of the request body
+    pub content: String,
+
+    media_types: PhantomData<M>,
+}
+
+impl<M: AcceptedMediaTypes> TypedRequestBody<M> {
+    /// Convert the request body into a string
+    pub fn into_string (self) -> String {
+        self.content
+    }
+}
+
+impl<M: AcceptedMediaTypes> FromDataSimple for TypedRequestBody<M> {
+    type Error = String;
+
+    fn from_data(request: &Request, data: Data) -> Outcome<Self, Self::Error> {
+        let content_type = match request.content_type() {
+            Some(content_type) if M::accepts(content_type) => content_type,
+            _ => return Failure((Status::UnsupportedMediaType, String::from("The request body hasn't an accepted content type."))),
+        };
+
+        match read_bounded_request_body(data.open(), get_request_body_limit_for(Some(content_type))) {
+            Ok(content) => Success(Self { content, media_types: PhantomData }),
+            Err(failure) => Failure(failure),
+        }
+    }
+}
+
+/// Reads a request body stream, failing with a 413 Payload Too Large if it exceeds `limit` bytes.
+fn read_bounded_request_bytes<R: Read> (stream: R, limit: u64) -> Result<Vec<u8>, (Status, String)> {
+    let mut bytes = Vec::new();
+
+    if let Err(e) = LimitedReader::new(stream, limit).read_to_end(&mut bytes) {
+        return match e.kind() {
+            ErrorKind::InvalidData => Err((Status::PayloadTooLarge, e.to_string())),
+            _ => Err((Status::InternalServerError, format!("{:?}", e))),
+        };
+    }
+
+    Ok(bytes)
+}
+
+/// Reads a request body stream as UTF-8, failing with a 413 Payload Too Large
+/// if it exceeds `limit` bytes, or with a 400 Bad request if it isn't valid UTF-8.
+fn read_bounded_request_body<R: Read> (stream: R, limit: u64) -> Result<String, (Status, String)> {
+    let bytes = read_bounded_request_bytes(stream, limit)?;
+
+    String::from_utf8(bytes)
+        .map_err(|_| (Status::BadRequest, String::from("The request body isn't valid UTF-8.")))
+}
+
+/// The request body as raw bytes, for binary payloads like images or protobuf messages.
+///
+/// The body is rejected with a 413 Payload Too Large if it exceeds the request body limit
+/// for the content type of the request.
+///
+/// # Examples
+///
+/// ```
+/// #[post("/avatar", data="<body>")]
+/// pub fn post_avatar(body: RawBody) -> Status {
+///     let image = decode_image(&body.0);
+///
+///     // ...
+/// }
+/// ```
+#[derive(Debug, PartialEq, Eq)]
+pub struct RawBody(pub Vec<u8>);
+
+impl RawBody {
+    /// Consumes the wrapper and returns the bytes
+    pub fn into_bytes (self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl FromDataSimple for RawBody {
+    type Error = String;
+
+    fn from_data(request: &Request, data: Data) -> Outcome<Self, Self::Error> {
+        match read_bounded_request_bytes(data.open(), get_request_body_limit_for(request.content_type())) {
+            Ok(bytes) => Success(RawBody(bytes)),
+            Err(failure) => Failure(failure),
+        }
+    }
+}
+
+/// The request body as a stream, to process a large upload incrementally
+/// without buffering it, e.g. to hash it or to read it line by line.
+///
+/// Reading more than the request body limit for the content type of the request
+/// fails with an InvalidData error.
+///
+/// # Examples
+///
+/// ```
+/// #[post("/upload", data="<body>")]
+/// pub fn upload(body: StreamedBody) -> Result<String, Status> {
+///     let mut hasher = Sha256::new();
+///     io::copy(&mut body.into_reader(), &mut hasher).map_err(|_| Status::PayloadTooLarge)?;
+///
+///     Ok(format!("{:x}", hasher.finalize()))
+/// }
+/// ```
+pub struct StreamedBody {
+    stream: LimitedReader<DataStream>,
+}
+
+impl StreamedBody {
+    pub fn into_reader (self) -> LimitedReader<DataStream> {
+        self.stream
+    }
+}
+
+impl Read for StreamedBody {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl FromDataSimple for StreamedBody {
+    type Error = ();
+
+    fn from_data(request: &Request, data: Data) -> Outcome<Self, Self::Error> {
+        Success(Self {
+            stream: LimitedReader::new(data.open(), get_request_body_limit_for(request.content_type())),
+        })
+    }
+}
+
+/// A request body deserialized from JSON, with the size and encoding checks
+/// of `BoundedRequestBody`.
+///
+/// The limit is the request body limit for the content type of the request.
+/// An oversized body is rejected with a 413 Payload Too Large, a body which isn't
+/// valid UTF-8 or isn't a valid JSON document with a 400 Bad request.
+///
+/// Contrary to `Json` of Rocket contrib, trailing content after the document is rejected.
+///
+/// # Examples
+///
+/// ```
+/// #[post("/register", data="<user>")]
+/// pub fn register(connection: DatabaseConnection, user: JsonBody<UserToRegister>) -> ApiJsonResponse<Player> {
+///     let user: UserToRegister = user.into_inner();
+///
+///     // ...
+/// }
+/// ```
+#[cfg(feature = "serialization")]
+#[derive(Debug, PartialEq)]
+pub struct JsonBody<T>(pub T);
+
+#[cfg(feature = "serialization")]
+impl<T> JsonBody<T> {
+    /// Consumes the wrapper and returns the deserialized value
+    pub fn into_inner (self) -> T {
+        self.0
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl<T: DeserializeOwned> FromDataSimple for JsonBody<T> {
+    type Error = String;
+
+    fn from_data(request: &Request, data: Data) -> Outcome<Self, Self::Error> {
+        let limit = get_request_body_limit_for(request.content_type());
+
+        let result = read_bounded_request_body(data.open(), limit)
+            .and_then(|content| deserialize_strict_json(&content));
+
+        match result {
+            Ok(value) => Success(JsonBody(value)),
+            Err(failure) => Failure(failure),
+        }
+    }
+}
+
+/// A request body deserialized from a JSON object, with the checks of `JsonBody`.
+///
+/// A top-level array or scalar is rejected with a 400 Bad request
+/// "expected JSON object", before any deserialization.
+///
+/// # Examples
+///
+/// ```
+/// #[put("/settings", data="<settings>")]
+/// pub fn put_settings(connection: DatabaseConnection, settings: JsonObject<serde_json::Value>) -> ApiJsonResponse<Settings> {
+///     let settings: serde_json::Value = settings.into_inner();
+///
+///     // ...
+/// }
+/// ```
+#[cfg(feature = "serialization")]
+#[derive(Debug, PartialEq)]
+pub struct JsonObject<T>(pub T);
+
+#[cfg(feature = "serialization")]
+impl<T> JsonObject<T> {
+    /// Consumes the wrapper and returns the deserialized value
+    pub fn into_inner (self) -> T {
+        self.0
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl<T: DeserializeOwned> FromDataSimple for JsonObject<T> {
+    type Error = String;
+
+    fn from_data(request: &Request, data: Data) -> Outcome<Self, Self::Error> {
+        let limit = get_request_body_limit_for(request.content_type());
+
+        let result = read_bounded_request_body(data.open(), limit)
+            .and_then(|content| deserialize_json_object(&content));
+
+        match result {
+            Ok(value) => Success(JsonObject(value)),
+            Err(failure) => Failure(failure),
+        }
+    }
+}
+
+/// A request body deserialized from JSON or from an URL-encoded form,
+/// according the Content-Type of the request.
+///
+/// Any other content type is rejected with a 415 Unsupported Media Type,
+/// and a body exceeding the request body limit with a 413 Payload Too Large.
+///
+/// # Examples
+///
+/// ```
+/// #[post("/register", data="<user>")]
+/// pub fn register(connection: DatabaseConnection, user: JsonOrForm<UserToRegister>) -> ApiJsonResponse<Player> {
+///     let user: UserToRegister = user.into_inner();
+///
+///     // ...
+/// }
+/// ```
+#[cfg(feature = "serialization")]
+#[derive(Debug, PartialEq)]
+pub struct JsonOrForm<T>(pub T);
+
+#[cfg(feature = "serialization")]
+impl<T> JsonOrForm<T> {
+    /// Consumes the wrapper and returns the deserialized value
+    pub fn into_inner (self) -> T {
+        self.0
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl<T: DeserializeOwned> FromDataSimple for JsonOrForm<T> {
+    type Error = String;
+
+    fn from_data(request: &Request, data: Data) -> Outcome<Self, Self::Error> {
+        let content = match read_bounded_request_body(data.open(), get_request_body_limit_for(request.content_type())) {
+            Ok(content) => content,
+            Err(failure) => return Failure(failure),
+        };
+
+        match deserialize_json_or_form(request.content_type(), &content) {
+            Ok(value) => Success(JsonOrForm(value)),
+            Err(failure) => Failure(failure),
+        }
+    }
+}
+
+/// Deserializes a JSON or an URL-encoded form body, according the content type.
+#[cfg(feature = "serialization")]
+fn deserialize_json_or_form<T: DeserializeOwned> (content_type: Option<&ContentType>, content: &str) -> Result<T, (Status, String)> {
+    match content_type {
+        Some(content_type) if content_type.is_json() => deserialize_strict_json(content),
+        Some(content_type) if content_type.is_form() => {
+            serde_urlencoded::from_str(content)
+                .map_err(|error| (Status::BadRequest, error.to_string()))
+        },
+        _ => Err((Status::UnsupportedMediaType, String::from("Expected a JSON or a form body"))),
+    }
+}
+
+/// Deserializes a JSON document, rejecting any trailing non-whitespace content after it.
+///
+/// A malformed document, or a document followed by garbage, gives a 400 Bad request.
+#[cfg(feature = "serialization")]
+pub fn deserialize_strict_json<T: DeserializeOwned> (content: &str) -> Result<T, (Status, String)> {
+    let mut deserializer = serde_json::Deserializer::from_str(content);
+
+    let value = T::deserialize(&mut deserializer)
+        .map_err(|error| (Status::BadRequest, error.to_string()))?;
+    deserializer.end()
+        .map_err(|error| (Status::BadRequest, error.to_string()))?;
+
+    Ok(value)
+}
+
+/// Deserializes a JSON document like `deserialize_strict_json`, only accepting
+/// an object at the top level.
+///
+/// An array or a scalar gives a 400 Bad request.
+#[cfg(feature = "serialization")]
+pub fn deserialize_json_object<T: DeserializeOwned> (content: &str) -> Result<T, (Status, String)> {
+    if !content.trim_start().starts_with('{') {
+        return Err((Status::BadRequest, String::from("expected JSON object")));
+    }
+
+    deserialize_strict_json(content)
+}
+
+/// The raw query string of the request, as sent by the client, without decoding.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RawQuery {
+    /// The undecoded query string, empty if the request hasn't any
+    pub query: String,
+}
+
+impl RawQuery {
+    /// Convert the raw query into a string
+    pub fn into_string (self) -> String {
+        self.query
+    }
+
+    /// Parse the query string into a typed structure.
+    ///
+    /// On failure, returns a 400 Bad request response with the parse error as JSON body.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// #[get("/search")]
+    /// pub fn search(query: RawQuery) -> Result<Json<Vec<Player>>, Custom<Json<String>>> {
+    ///     let criteria: SearchCriteria = query.parse()?;
+    ///
+    ///     Ok(Json(find_players(criteria)))
+    /// }
+    /// ```
+    #[cfg(feature = "serialization")]
+    pub fn parse<T: DeserializeOwned> (&self) -> Result<T, Custom<Json<String>>> {
+        serde_urlencoded::from_str(&self.query)
+            .map_err(|error| Custom(Status::BadRequest, Json(error.to_string())))
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for RawQuery {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> RequestOutcome<Self, Self::Error> {
+        let query = request.uri().query().unwrap_or("").to_string();
+
+        Success(Self { query })
+    }
+}
+
+/*   -------------------------------------------------------------
+     Bounded query
+
+     :: FromRequest
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The default maximum number of query parameters
+pub const QUERY_PARAMETERS_LIMIT: usize = 100;
+
+/// The maximum number of query parameters, when managed as a state to override the default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueryParametersLimit(pub usize);
+
+/// A guard rejecting requests with too many query parameters with a 400 Bad request,
+/// before they are parsed.
+///
+/// The limit is `QUERY_PARAMETERS_LIMIT`, or the managed `QueryParametersLimit` state.
+///
+/// # Examples
+///
+/// ```
+/// #[get("/search?<criteria..>")]
+/// pub fn search(_query: BoundedQuery, criteria: Form<SearchCriteria>) -> Json<Vec<Player>> {
+///     Json(find_players(criteria.into_inner()))
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BoundedQuery {
+    /// The number of query parameters
+    pub count: usize,
+}
+
+/// Counts the parameters of a query string.
+fn count_query_parameters (query: &str) -> usize {
+    query
+        .split('&')
+        .filter(|parameter| !parameter.is_empty())
+        .count()
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for BoundedQuery {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> RequestOutcome<Self, Self::Error> {
+        let limit = match request.guard::<State<QueryParametersLimit>>() {
+            Success(limit) => limit.0,
+            _ => QUERY_PARAMETERS_LIMIT,
+        };
+
+        let count = count_query_parameters(request.uri().query().unwrap_or(""));
+        if count > limit {
+            return Failure((Status::BadRequest, ()));
+        }
+
+        Success(Self { count })
+    }
+}
+
+/*   -------------------------------------------------------------
+     Range header
+
+     :: FromRequest
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A byte range, as requested in a Range header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteRange {
+    /// The bytes from the first position to the last one, inclusive, e.g. `0-499`
+    Bounded(u64, u64),
+
+    /// The bytes from the position to the end, e.g. `500-`
+    From(u64),
+
+    /// The last bytes, e.g. `-500`
+    Suffix(u64),
+}
+
+impl ByteRange {
+    pub fn parse (value: &str) -> Option<Self> {
+        let value = value.trim();
+        let separator = value.find('-')?;
+        let (start, end) = (&value[..separator], &value[separator + 1..]);
+
+        match (start.is_empty(), end.is_empty()) {
+            (true, true) => None,
+            (true, false) => end.parse().ok().map(ByteRange::Suffix),
+            (false, true) => start.parse().ok().map(ByteRange::From),
+            (false, false) => {
+                let start = start.parse().ok()?;
+                let end = end.parse().ok()?;
+
+                if start > end {
+                    return None;
+                }
+
+                Some(ByteRange::Bounded(start, end))
+            },
+        }
+    }
+
+    /// Resolves the range for a content of `length` bytes, as first and last positions,
+    /// or None if the range is unsatisfiable.
+    pub fn resolve (&self, length: u64) -> Option<(u64, u64)> {
+        if length == 0 {
+            return None;
+        }
+
+        match *self {
+            ByteRange::Bounded(start, end) if start < length => Some((start, end.min(length - 1))),
+            ByteRange::From(start) if start < length => Some((start, length - 1)),
+            ByteRange::Suffix(suffix) if suffix > 0 => Some((length - suffix.min(length), length - 1)),
+            _ => None,
+        }
+    }
+}
+
+/// A guard parsing the `Range: bytes=...` header, for partial content and resumable downloads.
+///
+/// The guard forwards if the header is absent or malformed, as the server should then
+/// ignore it and send the full content: use it as `Option<RangeHeader>`.
+///
+/// # Examples
+///
+/// ```
+/// #[get("/blobs/<id>")]
+/// pub fn get_blob(id: String, range: Option<RangeHeader>) -> Result<PartialContent, RangeNotSatisfiable> {
+///     let content = read_blob(&id);
+///
+///     match range {
+///         Some(range) => PartialContent::from_range(content, &range),
+///         None => Ok(PartialContent::full(content)),
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RangeHeader {
+    pub ranges: Vec<ByteRange>,
+}
+
+impl RangeHeader {
+    pub fn parse (value: &str) -> Option<Self> {
+        let value = value.trim();
+        if !value.starts_with("bytes=") {
+            return None;
+        }
+
+        let ranges = value["bytes=".len()..]
+            .split(',')
+            .map(ByteRange::parse)
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Self { ranges })
+    }
+
+    /// Resolves the first range for a content of `length` bytes,
+    /// or returns a 416 Range Not Satisfiable status.
+    ///
+    /// The multipart/byteranges responses aren't supported: when several ranges
+    /// are requested, only the first one is resolved and sent. The Content-Range
+    /// header of the response tells the client which range it got.
+    pub fn resolve (&self, length: u64) -> Result<(u64, u64), Status> {
+        self.ranges
+            .first()
+            .and_then(|range| range.resolve(length))
+            .ok_or(Status::RangeNotSatisfiable)
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for RangeHeader {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> RequestOutcome<Self, Self::Error> {
+        match request.headers().get_one("Range").and_then(RangeHeader::parse) {
+            Some(range) => Success(range),
+            None => Forward(()),
+        }
+    }
+}
+
+/*   -------------------------------------------------------------
+     Peer address
+
+     :: FromRequest
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The socket address of the peer connected to the server.
+///
+/// Behind a reverse proxy, this is the address of the proxy, not of the client.
+///
+/// Forwards if the address isn't known, e.g. for a local request built without remote address.
+///
+/// # Examples
+///
+/// ```
+/// #[post("/reports", data="<report>")]
+/// pub fn post_report(peer: PeerAddr, report: Json<Report>) -> Status {
+///     info!("Report received from {}", peer.ip());
+///
+///     // ...
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PeerAddr(pub SocketAddr);
+
+impl PeerAddr {
+    pub fn ip (&self) -> IpAddr {
+        self.0.ip()
+    }
+
+    pub fn port (&self) -> u16 {
+        self.0.port()
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for PeerAddr {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> RequestOutcome<Self, Self::Error> {
+        match request.remote() {
+            Some(address) => Success(PeerAddr(address)),
+            None => Forward(()),
+        }
+    }
+}
+
+/*   -------------------------------------------------------------
+     Prefer header
+
+     :: FromRequest
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The return preference of a Prefer header, as defined by RFC 7240.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReturnPreference {
+    /// The client only needs a minimal response, like a 204 No Content
+    Minimal,
+
+    /// The client wants the full representation of the resource
+    Representation,
+}
+
+impl ReturnPreference {
+    pub fn parse (value: &str) -> Option<Self> {
+        match value.trim() {
+            "return=minimal" => Some(ReturnPreference::Minimal),
+            "return=representation" => Some(ReturnPreference::Representation),
+            _ => None,
+        }
+    }
+
+    /// Gets the value to send in the Preference-Applied header
+    pub fn as_str (&self) -> &'static str {
+        match *self {
+            ReturnPreference::Minimal => "return=minimal",
+            ReturnPreference::Representation => "return=representation",
+        }
+    }
+}
+
+/// The preferences of the client, read from the Prefer headers.
+///
+/// The guard always succeeds: without Prefer header, there isn't any preference.
+///
+/// # Examples
+///
+/// ```
+/// #[put("/players/<name>", data="<player>")]
+/// pub fn put_player(connection: DatabaseConnection, name: String, player: Json<Player>, prefer: Prefer) -> PreferredResponse<Player> {
+///     let player = update_player(&*connection, &name, player.into_inner());
+///
+///     PreferredResponse::new(player, &prefer)
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Prefer {
+    pub return_preference: Option<ReturnPreference>,
+}
+
+impl Prefer {
+    /// Parses the preferences from the values of the Prefer headers.
+    pub fn parse<'a, I> (values: I) -> Self
+        where I: IntoIterator<Item = &'a str>
+    {
+        let return_preference = values
+            .into_iter()
+            .flat_map(|value| value.split(','))
+            .filter_map(ReturnPreference::parse)
+            .next();
+
+        Self { return_preference }
+    }
+
+    pub fn is_minimal (&self) -> bool {
+        self.return_preference == Some(ReturnPreference::Minimal)
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Prefer {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> RequestOutcome<Self, Self::Error> {
+        Success(Prefer::parse(request.headers().get("Prefer")))
+    }
+}
+
+/*   -------------------------------------------------------------
+     API version header
+
+     :: FromRequest
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The headers read to negotiate the API version, by order of preference
+pub const API_VERSION_HEADERS: [&'static str; 2] = ["Accept-Version", "X-Api-Version"];
+
+/// The API versions supported by the service, to manage as a state
+/// when the version is negotiated through a header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SupportedApiVersions {
+    versions: Vec<String>,
+
+    /// The version to use when the request doesn't provide one,
+    /// or None to reject such requests
+    default: Option<String>,
+}
+
+impl SupportedApiVersions {
+    pub fn new<S> (versions: &[S]) -> Self where S: AsRef<str> {
         Self {
-            content: String::new(),
+            versions: versions.iter().map(|version| version.as_ref().to_string()).collect(),
+            default: None,
         }
     }
 
-    /// Convert the request body into a string
-    pub fn into_string (self) -> String {
-        self.content
+    /// Allows the requests without version header, using the specified version for them.
+    pub fn with_default (mut self, version: &str) -> Self {
+        self.default = Some(version.to_string());
+
+        self
     }
 
-    /// Convert the request body into a string, or None if it's empty
-    pub fn into_optional_string (self) -> Option<String> {
-        if self.content.is_empty() {
-            None
-        } else {
-            Some(self.content)
+    pub fn is_supported (&self, version: &str) -> bool {
+        self.versions.iter().any(|supported| supported == version)
+    }
+
+    /// Negotiates the version to use from the version requested by the client.
+    ///
+    /// Returns a 400 Bad request if the version is missing and there isn't a default one,
+    /// or a 406 Not Acceptable if the version isn't supported.
+    pub fn negotiate (&self, requested: Option<&str>) -> Result<String, Status> {
+        match requested.map(str::trim) {
+            Some(version) if self.is_supported(version) => Ok(version.to_string()),
+            Some(_) => Err(Status::NotAcceptable),
+            None => self.default.clone().ok_or(Status::BadRequest),
         }
     }
 }
 
-impl FromDataSimple for RequestBody {
-    type Error = String;
+/// A guard requiring the API version requested in the `Accept-Version`
+/// or `X-Api-Version` header to be one of the managed `SupportedApiVersions` state.
+///
+/// # Examples
+///
+/// ```
+/// #[get("/players")]
+/// pub fn get_players(version: ApiVersion) -> Json<Vec<Player>> {
+///     match version.as_str() {
+///         "1" => ...,
+///         _ => ...,
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApiVersion(pub String);
 
-    fn from_data(_request: &Request, data: Data) -> Outcome<Self, Self::Error> {
-        let mut content = String::new();
+impl ApiVersion {
+    pub fn as_str (&self) -> &str {
+        &self.0
+    }
+}
 
-        if let Err(e) = data.open().take(REQUEST_BODY_LIMIT).read_to_string(&mut content) {
-            return Failure((Status::InternalServerError, format!("{:?}", e)));
-        }
+impl<'a, 'r> FromRequest<'a, 'r> for ApiVersion {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> RequestOutcome<Self, Self::Error> {
+        let supported_versions = request.guard::<State<SupportedApiVersions>>()?;
+
+        let requested = API_VERSION_HEADERS
+            .iter()
+            .filter_map(|header| request.headers().get_one(header))
+            .next();
 
-        Success(Self { content })
+        match supported_versions.negotiate(requested) {
+            Ok(version) => Success(ApiVersion(version)),
+            Err(status) => Failure((status, ())),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rocket::local::Client;
+    #[cfg(feature = "serialization")]
+    use std::collections::HashMap;
 
     #[test]
     fn test_request_body_new () {
@@ -89,4 +1076,533 @@ mod tests {
         let body = RequestBody::new();
         assert_eq!(None, body.into_optional_string());
     }
+
+    /// A reader giving its content in small chunks
+    struct ChunkedReader {
+        content: Vec<u8>,
+        position: usize,
+    }
+
+    impl Read for ChunkedReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let end = (self.position + 3).min(self.content.len());
+            let count = (end - self.position).min(buf.len());
+
+            buf[..count].copy_from_slice(&self.content[self.position..self.position + count]);
+            self.position += count;
+
+            Ok(count)
+        }
+    }
+
+    fn build_chunked_reader (content: &str) -> ChunkedReader {
+        ChunkedReader {
+            content: content.as_bytes().to_vec(),
+            position: 0,
+        }
+    }
+
+    #[test]
+    fn test_limited_reader_under_limit () {
+        let mut reader = LimitedReader::new(build_chunked_reader("quux quux"), 9);
+
+        let mut content = String::new();
+        reader.read_to_string(&mut content).unwrap();
+
+        assert_eq!("quux quux", content);
+        assert_eq!(9, reader.get_read_count());
+    }
+
+    #[test]
+    fn test_limited_reader_aborts_oversized_stream () {
+        let mut reader = LimitedReader::new(build_chunked_reader("quux quux"), 8);
+
+        let mut content = String::new();
+        let error = reader.read_to_string(&mut content).unwrap_err();
+
+        assert_eq!(ErrorKind::InvalidData, error.kind());
+        assert_eq!(9, reader.get_read_count());
+    }
+
+    /// Allows the body guards to be answered as is by `echo_body`.
+    trait IntoEchoedBody {
+        fn into_echoed_body(self) -> String;
+    }
+
+    impl<const LIMIT: u64> IntoEchoedBody for BoundedRequestBody<LIMIT> {
+        fn into_echoed_body(self) -> String { self.into_string() }
+    }
+
+    impl<M: AcceptedMediaTypes> IntoEchoedBody for TypedRequestBody<M> {
+        fn into_echoed_body(self) -> String { self.into_string() }
+    }
+
+    impl IntoEchoedBody for RequestBody {
+        fn into_echoed_body(self) -> String { self.into_string() }
+    }
+
+    #[cfg(feature = "serialization")]
+    impl IntoEchoedBody for JsonOrForm<HashMap<String, String>> {
+        fn into_echoed_body(self) -> String { self.0["quux"].clone() }
+    }
+
+    /// Answers the body read by the guard `T`, or the status and message of its failure.
+    fn echo_body<'r, T>(request: &'r Request, data: Data) -> ::rocket::handler::Outcome<'r>
+        where T: FromDataSimple<Error = String> + IntoEchoedBody
+    {
+        use rocket::handler::Outcome as HandlerOutcome;
+
+        match T::from_data(request, data) {
+            Success(body) => HandlerOutcome::from(request, body.into_echoed_body()),
+            Failure((status, message)) => HandlerOutcome::from(request, ::rocket::response::status::Custom(status, message)),
+            Forward(data) => HandlerOutcome::Forward(data),
+        }
+    }
+
+    /// Builds a client for a server answering POST /webhook with the body read by the guard `T`.
+    fn build_body_guard_client<T> () -> Client
+        where T: FromDataSimple<Error = String> + IntoEchoedBody + 'static
+    {
+        use rocket::Route;
+        use rocket::http::Method;
+
+        let server = ::rocket::ignite().mount("/", vec![Route::new(Method::Post, "/webhook", echo_body::<T>)]);
+
+        Client::untracked(server).unwrap()
+    }
+
+    #[test]
+    fn test_bounded_request_body_under_limit () {
+        let client = build_body_guard_client::<BoundedRequestBody<8>>();
+        let mut response = client.post("/webhook").body("quux").dispatch();
+
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(Some(String::from("quux")), response.body_string());
+    }
+
+    #[test]
+    fn test_bounded_request_body_over_limit () {
+        let client = build_body_guard_client::<BoundedRequestBody<8>>();
+        let mut response = client.post("/webhook").body("quux quux").dispatch();
+
+        assert_eq!(Status::PayloadTooLarge, response.status());
+        assert_eq!(
+            Some(String::from("The request body exceeds the limit of 8 bytes.")),
+            response.body_string()
+        );
+    }
+
+    #[test]
+    fn test_request_body_under_limit () {
+        let client = build_body_guard_client::<RequestBody>();
+        let mut response = client.post("/webhook").header(ContentType::Plain).body("quux").dispatch();
+
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(Some(String::from("quux")), response.body_string());
+    }
+
+    #[test]
+    fn test_request_body_over_limit () {
+        let limit = get_request_body_limit_for(Some(&ContentType::Plain)) as usize;
+        let client = build_body_guard_client::<RequestBody>();
+
+        let response = client.post("/webhook").header(ContentType::Plain).body("x".repeat(limit + 1)).dispatch();
+        assert_eq!(Status::PayloadTooLarge, response.status());
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_json_or_form_over_limit () {
+        let limit = get_request_body_limit_for(Some(&ContentType::JSON)) as usize;
+        let client = build_body_guard_client::<JsonOrForm<HashMap<String, String>>>();
+
+        // {"quux": "xxx…"} of exactly limit bytes, then limit + 1 bytes
+        let build_body = |size: usize| format!("{{\"quux\": \"{}\"}}", "x".repeat(size - 12));
+        assert_eq!(limit, build_body(limit).len());
+
+        let response = client.post("/webhook").header(ContentType::JSON).body(build_body(limit)).dispatch();
+        assert_eq!(Status::Ok, response.status());
+
+        let response = client.post("/webhook").header(ContentType::JSON).body(build_body(limit + 1)).dispatch();
+        assert_eq!(Status::PayloadTooLarge, response.status());
+    }
+
+    #[test]
+    fn test_typed_request_body_accepted () {
+        let client = build_body_guard_client::<TypedRequestBody>();
+        let mut response = client.post("/webhook").header(ContentType::Plain).body("quux").dispatch();
+
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(Some(String::from("quux")), response.body_string());
+    }
+
+    #[test]
+    fn test_typed_request_body_unsupported_media_type () {
+        let client = build_body_guard_client::<TypedRequestBody>();
+
+        let response = client.post("/webhook").header(ContentType::Binary).body("quux").dispatch();
+        assert_eq!(Status::UnsupportedMediaType, response.status());
+
+        let response = client.post("/webhook").body("quux").dispatch();
+        assert_eq!(Status::UnsupportedMediaType, response.status());
+    }
+
+    #[test]
+    fn test_bounded_request_body_default_limit () {
+        assert_eq!(REQUEST_BODY_LIMIT, <BoundedRequestBody>::get_limit());
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_json_body_valid () {
+        let content = "{\"quux\": 42}";
+        let value: HashMap<String, u32> = read_bounded_request_body(content.as_bytes(), 64)
+            .and_then(|content| deserialize_strict_json(&content))
+            .unwrap();
+
+        assert_eq!(Some(&42), value.get("quux"));
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_json_body_malformed () {
+        let result: Result<HashMap<String, u32>, _> = read_bounded_request_body("{\"quux\": ".as_bytes(), 64)
+            .and_then(|content| deserialize_strict_json(&content));
+
+        assert_eq!(Some(Status::BadRequest), result.err().map(|failure| failure.0));
+    }
+
+    #[test]
+    fn test_read_bounded_request_body_oversized () {
+        let result = read_bounded_request_body("{\"quux\": 42}".as_bytes(), 8);
+
+        assert_eq!(Some(Status::PayloadTooLarge), result.err().map(|failure| failure.0));
+    }
+
+    #[test]
+    fn test_read_bounded_request_bytes_not_utf8 () {
+        let bytes = read_bounded_request_bytes(&[0x89, 0x50, 0x4E, 0x47, 0xFF][..], 8).unwrap();
+        assert_eq!(vec![0x89, 0x50, 0x4E, 0x47, 0xFF], bytes);
+
+        let result = read_bounded_request_bytes(&[0xFF; 9][..], 8);
+        assert_eq!(Some(Status::PayloadTooLarge), result.err().map(|failure| failure.0));
+    }
+
+    #[test]
+    fn test_request_body_from_bytes_lossy () {
+        let body = RequestBody::from_bytes_lossy(&[0x71, 0x75, 0xFF, 0x78]);
+
+        assert_eq!("qu\u{FFFD}x", body.into_string());
+    }
+
+    #[test]
+    fn test_read_bounded_request_body_not_utf8 () {
+        let result = read_bounded_request_body(&[0xC3, 0x28][..], 8);
+
+        assert_eq!(Some(Status::BadRequest), result.err().map(|failure| failure.0));
+    }
+
+    #[test]
+    fn test_range_header_with_single_range () {
+        let range = RangeHeader::parse("bytes=0-499").unwrap();
+
+        assert_eq!(vec![ByteRange::Bounded(0, 499)], range.ranges);
+        assert_eq!(Ok((0, 499)), range.resolve(1000));
+        assert_eq!(Ok((0, 99)), range.resolve(100));
+    }
+
+    #[test]
+    fn test_range_header_with_open_ended_range () {
+        let range = RangeHeader::parse("bytes=500-").unwrap();
+        assert_eq!(Ok((500, 999)), range.resolve(1000));
+
+        let range = RangeHeader::parse("bytes=-300").unwrap();
+        assert_eq!(Ok((700, 999)), range.resolve(1000));
+    }
+
+    #[test]
+    fn test_range_header_with_multiple_ranges () {
+        let range = RangeHeader::parse("bytes=0-49, 100-").unwrap();
+
+        assert_eq!(vec![ByteRange::Bounded(0, 49), ByteRange::From(100)], range.ranges);
+        assert_eq!(Ok((0, 49)), range.resolve(1000), "Only the first range should be resolved");
+    }
+
+    #[test]
+    fn test_range_header_when_unsatisfiable () {
+        let range = RangeHeader::parse("bytes=1000-1999").unwrap();
+
+        assert_eq!(Err(Status::RangeNotSatisfiable), range.resolve(1000));
+        assert_eq!(Err(Status::RangeNotSatisfiable), RangeHeader::parse("bytes=-0").unwrap().resolve(1000));
+    }
+
+    #[test]
+    fn test_range_header_when_malformed () {
+        assert_eq!(None, RangeHeader::parse("bytes=500-100"));
+        assert_eq!(None, RangeHeader::parse("bytes=-"));
+        assert_eq!(None, RangeHeader::parse("items=0-9"));
+    }
+
+    #[test]
+    fn test_range_header_guard () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+
+        let request = client.get("/blobs/quux").header(::rocket::http::Header::new("Range", "bytes=0-9"));
+        assert_eq!(vec![ByteRange::Bounded(0, 9)], request.inner().guard::<RangeHeader>().unwrap().ranges);
+
+        let request = client.get("/blobs/quux");
+        assert!(request.inner().guard::<RangeHeader>().is_forward());
+    }
+
+    #[test]
+    fn test_prefer_parse () {
+        assert_eq!(Some(ReturnPreference::Minimal), Prefer::parse(vec!["return=minimal"]).return_preference);
+        assert_eq!(
+            Some(ReturnPreference::Representation),
+            Prefer::parse(vec!["respond-async, return=representation"]).return_preference
+        );
+        assert_eq!(None, Prefer::parse(vec!["respond-async"]).return_preference);
+        assert_eq!(None, Prefer::parse(Vec::new()).return_preference);
+    }
+
+    #[test]
+    fn test_prefer_guard () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+
+        let request = client.put("/players/quux").header(::rocket::http::Header::new("Prefer", "return=minimal"));
+        assert!(request.inner().guard::<Prefer>().unwrap().is_minimal());
+
+        let request = client.put("/players/quux");
+        assert_eq!(Prefer::default(), request.inner().guard::<Prefer>().unwrap());
+    }
+
+    #[test]
+    fn test_peer_addr () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+
+        let request = client.get("/players").remote("192.0.2.42:51234".parse().unwrap());
+        let peer = request.inner().guard::<PeerAddr>().unwrap();
+        assert_eq!("192.0.2.42".parse::<IpAddr>().unwrap(), peer.ip());
+        assert_eq!(51234, peer.port());
+
+        let request = client.get("/players");
+        assert!(request.inner().guard::<PeerAddr>().is_forward());
+    }
+
+    #[test]
+    fn test_api_version_supported () {
+        let client = Client::untracked(::rocket::ignite().manage(SupportedApiVersions::new(&["1", "2"]))).unwrap();
+
+        let request = client.get("/players").header(::rocket::http::Header::new("Accept-Version", "2"));
+        assert_eq!("2", request.inner().guard::<ApiVersion>().unwrap().as_str());
+
+        let request = client.get("/players").header(::rocket::http::Header::new("X-Api-Version", "1"));
+        assert_eq!("1", request.inner().guard::<ApiVersion>().unwrap().as_str());
+    }
+
+    #[test]
+    fn test_api_version_unsupported () {
+        let client = Client::untracked(::rocket::ignite().manage(SupportedApiVersions::new(&["1", "2"]))).unwrap();
+        let request = client.get("/players").header(::rocket::http::Header::new("Accept-Version", "3"));
+
+        let outcome = request.inner().guard::<ApiVersion>();
+        assert_eq!(Some(Status::NotAcceptable), outcome.failed().map(|failure| failure.0));
+    }
+
+    #[test]
+    fn test_api_version_missing () {
+        let client = Client::untracked(::rocket::ignite().manage(SupportedApiVersions::new(&["1", "2"]))).unwrap();
+        let request = client.get("/players");
+
+        let outcome = request.inner().guard::<ApiVersion>();
+        assert_eq!(Some(Status::BadRequest), outcome.failed().map(|failure| failure.0));
+
+        let client = Client::untracked(::rocket::ignite().manage(SupportedApiVersions::new(&["1", "2"]).with_default("1"))).unwrap();
+        let request = client.get("/players");
+        assert_eq!("1", request.inner().guard::<ApiVersion>().unwrap().as_str());
+    }
+
+    #[test]
+    fn test_content_type_body_limits_parse () {
+        let limits = ContentTypeBodyLimits::parse("application/json=1000, application/octet-stream=50000").unwrap();
+
+        assert_eq!(Some(1000), limits.get_limit(&ContentType::JSON));
+        assert_eq!(Some(50000), limits.get_limit(&ContentType::Binary));
+        assert_eq!(None, limits.get_limit(&ContentType::Plain));
+
+        assert!(ContentTypeBodyLimits::parse("").unwrap().is_empty());
+        assert_eq!(None, ContentTypeBodyLimits::parse("application/json"));
+        assert_eq!(None, ContentTypeBodyLimits::parse("application/json=big"));
+        assert_eq!(None, ContentTypeBodyLimits::parse("json=1000"));
+        assert_eq!(None, ContentTypeBodyLimits::parse("application/json=0"));
+    }
+
+    #[test]
+    fn test_content_type_body_limits_ignore_parameters () {
+        let limits = ContentTypeBodyLimits::new().with_limit("Application/JSON", 1000);
+        let content_type = ContentType::with_params("application", "json", ("charset", "utf-8"));
+
+        assert_eq!(Some(1000), limits.get_limit(&content_type));
+    }
+
+    #[test]
+    fn test_content_type_body_limits_applied_to_reader () {
+        let limits = ContentTypeBodyLimits::new()
+            .with_limit("application/json", 8)
+            .with_limit("application/octet-stream", 64);
+        let body = [0u8; 16];
+
+        let limit = limits.get_limit(&ContentType::JSON).unwrap();
+        let mut content = Vec::new();
+        let error = LimitedReader::new(&body[..], limit).read_to_end(&mut content).unwrap_err();
+        assert_eq!(ErrorKind::InvalidData, error.kind());
+
+        let limit = limits.get_limit(&ContentType::Binary).unwrap();
+        let mut content = Vec::new();
+        assert_eq!(16, LimitedReader::new(&body[..], limit).read_to_end(&mut content).unwrap());
+    }
+
+    #[test]
+    fn test_count_query_parameters () {
+        assert_eq!(0, count_query_parameters(""));
+        assert_eq!(2, count_query_parameters("name=Foo&level=4"));
+        assert_eq!(2, count_query_parameters("name=Foo&&level=4&"));
+    }
+
+    #[test]
+    fn test_bounded_query_at_limit () {
+        let client = Client::untracked(::rocket::ignite().manage(QueryParametersLimit(2))).unwrap();
+        let request = client.get("/players?name=Foo&level=4");
+
+        let query = request.inner().guard::<BoundedQuery>().unwrap();
+        assert_eq!(2, query.count);
+    }
+
+    #[test]
+    fn test_bounded_query_over_limit () {
+        let client = Client::untracked(::rocket::ignite().manage(QueryParametersLimit(2))).unwrap();
+        let request = client.get("/players?name=Foo&level=4&page=2");
+
+        let outcome = request.inner().guard::<BoundedQuery>();
+        assert_eq!(Some(Status::BadRequest), outcome.failed().map(|failure| failure.0));
+    }
+
+    #[test]
+    fn test_bounded_query_with_default_limit () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let query = vec!["a=1"; QUERY_PARAMETERS_LIMIT + 1].join("&");
+        let request = client.get(format!("/players?{}", query));
+
+        assert!(request.inner().guard::<BoundedQuery>().failed().is_some());
+    }
+
+    #[test]
+    fn test_raw_query () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.get("/players?name=Foo%20Bar&level=4");
+
+        let query = request.inner().guard::<RawQuery>().unwrap();
+        assert_eq!("name=Foo%20Bar&level=4", query.into_string());
+    }
+
+    #[test]
+    fn test_raw_query_when_empty () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.get("/players");
+
+        let query = request.inner().guard::<RawQuery>().unwrap();
+        assert_eq!("", query.into_string());
+    }
+
+    #[cfg(feature = "serialization")]
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct SearchCriteria {
+        name: String,
+        level: u32,
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_raw_query_parse () {
+        let query = RawQuery { query: "name=Foo%20Bar&level=4".to_string() };
+
+        let expected = SearchCriteria { name: "Foo Bar".to_string(), level: 4 };
+        assert_eq!(expected, query.parse::<SearchCriteria>().unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_raw_query_parse_when_invalid () {
+        let query = RawQuery { query: "name=Foo&level=high".to_string() };
+
+        let error = query.parse::<SearchCriteria>().unwrap_err();
+        assert_eq!(Status::BadRequest, error.0);
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_deserialize_json_or_form_from_json () {
+        let criteria: SearchCriteria = deserialize_json_or_form(
+            Some(&ContentType::JSON), r#"{"name": "Foo Bar", "level": 4}"#
+        ).unwrap();
+
+        let expected = SearchCriteria { name: "Foo Bar".to_string(), level: 4 };
+        assert_eq!(expected, criteria);
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_deserialize_json_or_form_from_form () {
+        let criteria: SearchCriteria = deserialize_json_or_form(
+            Some(&ContentType::Form), "name=Foo+Bar&level=4"
+        ).unwrap();
+
+        let expected = SearchCriteria { name: "Foo Bar".to_string(), level: 4 };
+        assert_eq!(expected, criteria);
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_deserialize_json_or_form_when_unsupported () {
+        let result = deserialize_json_or_form::<SearchCriteria>(Some(&ContentType::XML), "<level>4</level>");
+        assert_eq!(Status::UnsupportedMediaType, result.unwrap_err().0);
+
+        let result = deserialize_json_or_form::<SearchCriteria>(None, "level=4");
+        assert_eq!(Status::UnsupportedMediaType, result.unwrap_err().0);
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_deserialize_strict_json () {
+        let value: HashMap<String, u32> = deserialize_strict_json("{\"a\":1}\n").unwrap();
+        assert_eq!(Some(&1), value.get("a"));
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_deserialize_strict_json_with_trailing_data () {
+        let result = deserialize_strict_json::<HashMap<String, u32>>("{\"a\":1}garbage");
+        assert_eq!(Status::BadRequest, result.unwrap_err().0);
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_deserialize_json_object () {
+        let value: HashMap<String, u32> = deserialize_json_object(" \n{\"a\":1}").unwrap();
+        assert_eq!(Some(&1), value.get("a"));
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_deserialize_json_object_when_array () {
+        let result = deserialize_json_object::<serde_json::Value>("[1, 2]");
+        assert_eq!(Err((Status::BadRequest, String::from("expected JSON object"))), result);
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_deserialize_json_object_when_scalar () {
+        let result = deserialize_json_object::<serde_json::Value>("42");
+        assert_eq!(Err((Status::BadRequest, String::from("expected JSON object"))), result);
+    }
 }
@@ -0,0 +1,255 @@
+//! # JWT
+//!
+//! This module provides a guard to authenticate requests with a bearer JSON Web Token,
+//! signed with a shared secret (HS256) or a RSA key pair (RS256).
+//!
+//! The verification key is read from the `LF_JWT_SECRET` or `LF_JWT_PUBLIC_KEY` variable.
+
+use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use jsonwebtoken::errors::ErrorKind;
+use rocket::http::Status;
+use rocket::Outcome;
+use rocket::Request;
+use rocket::request::{FromRequest, Outcome as RequestOutcome};
+use serde::de::DeserializeOwned;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::ops::Deref;
+use std::sync::OnceLock;
+use ErrorResult;
+
+/*   -------------------------------------------------------------
+     JWT verifier
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// Verifies the signature and the validity period of the tokens.
+pub struct JwtVerifier {
+    key: DecodingKey,
+    algorithm: Algorithm,
+}
+
+impl JwtVerifier {
+    /// Builds a verifier for tokens signed with HS256.
+    pub fn from_secret (secret: &[u8]) -> Self {
+        Self {
+            key: DecodingKey::from_secret(secret),
+            algorithm: Algorithm::HS256,
+        }
+    }
+
+    /// Builds a verifier for tokens signed with RS256, from a PEM-encoded public key.
+    pub fn from_rsa_public_key (pem: &[u8]) -> ErrorResult<Self> {
+        Ok(Self {
+            key: DecodingKey::from_rsa_pem(pem)?,
+            algorithm: Algorithm::RS256,
+        })
+    }
+
+    /// Verifies a token, checking the `exp` and `nbf` claims, and deserializes its claims.
+    pub fn verify<T: DeserializeOwned> (&self, token: &str) -> Result<T, JwtRejection> {
+        let mut validation = Validation::new(self.algorithm);
+        validation.validate_nbf = true;
+
+        decode::<T>(token, &self.key, &validation)
+            .map(|data| data.claims)
+            .map_err(|error| match *error.kind() {
+                ErrorKind::ExpiredSignature => JwtRejection::Expired,
+                ErrorKind::ImmatureSignature => JwtRejection::NotYetValid,
+                ErrorKind::InvalidSignature => JwtRejection::InvalidSignature,
+                _ => JwtRejection::Invalid(error.to_string()),
+            })
+    }
+}
+
+/// The verifier of the tokens, when set by the configuration
+static JWT_VERIFIER: OnceLock<JwtVerifier> = OnceLock::new();
+
+/// Sets the verifier of the tokens, returning false if it has already been set.
+pub fn set_jwt_verifier (verifier: JwtVerifier) -> bool {
+    JWT_VERIFIER.set(verifier).is_ok()
+}
+
+/// Gets the verifier of the tokens, or None if no key is configured.
+pub fn get_jwt_verifier () -> Option<&'static JwtVerifier> {
+    JWT_VERIFIER.get()
+}
+
+/*   -------------------------------------------------------------
+     JWT rejection
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The reason a token has been rejected, to be logged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JwtRejection {
+    /// No verification key is configured
+    NotConfigured,
+
+    /// The request hasn't any bearer token
+    Missing,
+
+    Expired,
+
+    /// The token isn't valid yet, according its `nbf` claim
+    NotYetValid,
+
+    InvalidSignature,
+
+    /// The token is malformed, or its claims can't be deserialized
+    Invalid(String),
+}
+
+impl Display for JwtRejection {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match *self {
+            JwtRejection::NotConfigured => write!(f, "no JWT verification key is configured"),
+            JwtRejection::Missing => write!(f, "no bearer token"),
+            JwtRejection::Expired => write!(f, "the token has expired"),
+            JwtRejection::NotYetValid => write!(f, "the token isn't valid yet"),
+            JwtRejection::InvalidSignature => write!(f, "the token signature is invalid"),
+            JwtRejection::Invalid(ref error) => write!(f, "the token is invalid: {}", error),
+        }
+    }
+}
+
+/*   -------------------------------------------------------------
+     Claims
+
+     :: FromRequest
+     :: Deref
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The claims of the bearer JWT of the request, once verified.
+///
+/// A request without valid token is rejected with a 401 Unauthorized,
+/// and the reason is logged.
+///
+/// # Examples
+///
+/// ```
+/// #[derive(Deserialize)]
+/// pub struct UserClaims {
+///     pub sub: String,
+///     pub admin: bool,
+/// }
+///
+/// #[delete("/player/<name>")]
+/// pub fn delete_player(connection: DatabaseConnection, name: String, claims: Claims<UserClaims>) -> Result<Json<()>, ApiError> {
+///     if !claims.admin {
+///         return Err(api_error(Status::Forbidden, "no access"));
+///     }
+///
+///     // ...
+/// }
+/// ```
+#[derive(Debug, PartialEq)]
+pub struct Claims<T>(pub T);
+
+impl<T> Claims<T> {
+    pub fn into_inner (self) -> T {
+        self.0
+    }
+}
+
+impl<'a, 'r, T: DeserializeOwned> FromRequest<'a, 'r> for Claims<T> {
+    type Error = JwtRejection;
+
+    fn from_request(request: &'a Request<'r>) -> RequestOutcome<Self, Self::Error> {
+        let authorization = request.headers().get_one("Authorization");
+
+        match verify_authorization(authorization, get_jwt_verifier()) {
+            Ok(claims) => Outcome::Success(Claims(claims)),
+            Err(rejection) => {
+                info!(target: "request", "Bearer token rejected: {}", rejection);
+
+                Outcome::Failure((Status::Unauthorized, rejection))
+            },
+        }
+    }
+}
+
+impl<T> Deref for Claims<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Verifies the bearer token of an Authorization header value.
+fn verify_authorization<T: DeserializeOwned> (authorization: Option<&str>, verifier: Option<&JwtVerifier>) -> Result<T, JwtRejection> {
+    let verifier = verifier.ok_or(JwtRejection::NotConfigured)?;
+    let token = authorization
+        .and_then(parse_bearer_token)
+        .ok_or(JwtRejection::Missing)?;
+
+    verifier.verify(token)
+}
+
+/// Parses the token of an Authorization header value using the Bearer scheme.
+fn parse_bearer_token (authorization: &str) -> Option<&str> {
+    let mut parts = authorization.splitn(2, ' ');
+    let scheme = parts.next()?;
+    let token = parts.next()?.trim();
+
+    if !scheme.eq_ignore_ascii_case("Bearer") || token.is_empty() {
+        return None;
+    }
+
+    Some(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, get_current_timestamp, EncodingKey, Header};
+    use std::collections::HashMap;
+
+    type TestClaims = HashMap<String, ::serde_json::Value>;
+
+    fn build_token (secret: &[u8], exp: u64) -> String {
+        let claims = ::serde_json::json!({ "sub": "quux", "exp": exp });
+
+        encode(&Header::default(), &claims, &EncodingKey::from_secret(secret)).unwrap()
+    }
+
+    #[test]
+    fn test_verify_valid_token () {
+        let token = build_token(b"secret", get_current_timestamp() + 3600);
+
+        let claims: TestClaims = JwtVerifier::from_secret(b"secret").verify(&token).unwrap();
+        assert_eq!(Some(&::serde_json::json!("quux")), claims.get("sub"));
+    }
+
+    #[test]
+    fn test_verify_expired_token () {
+        let token = build_token(b"secret", get_current_timestamp() - 3600);
+
+        let result = JwtVerifier::from_secret(b"secret").verify::<TestClaims>(&token);
+        assert_eq!(Err(JwtRejection::Expired), result);
+    }
+
+    #[test]
+    fn test_verify_bad_signature () {
+        let token = build_token(b"other", get_current_timestamp() + 3600);
+
+        let result = JwtVerifier::from_secret(b"secret").verify::<TestClaims>(&token);
+        assert_eq!(Err(JwtRejection::InvalidSignature), result);
+    }
+
+    #[test]
+    fn test_verify_authorization () {
+        let verifier = JwtVerifier::from_secret(b"secret");
+        let header = format!("Bearer {}", build_token(b"secret", get_current_timestamp() + 3600));
+
+        assert!(verify_authorization::<TestClaims>(Some(&header), Some(&verifier)).is_ok());
+        assert_eq!(Err(JwtRejection::Missing), verify_authorization::<TestClaims>(None, Some(&verifier)));
+        assert_eq!(Err(JwtRejection::NotConfigured), verify_authorization::<TestClaims>(Some(&header), None));
+    }
+
+    #[test]
+    fn test_parse_bearer_token () {
+        assert_eq!(Some("abc"), parse_bearer_token("Bearer abc"));
+        assert_eq!(Some("abc"), parse_bearer_token("bearer abc"));
+        assert_eq!(None, parse_bearer_token("Basic abc"));
+        assert_eq!(None, parse_bearer_token("Bearer "));
+    }
+}
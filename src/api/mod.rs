@@ -6,5 +6,12 @@
      Public submodules offered by this module
      - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
 
+pub mod charset;
+pub mod client_errors;
 pub mod guards;
+#[cfg(feature = "jwt")]
+pub mod jwt;
+pub mod pagination;
+pub mod rate_limit;
 pub mod replies;
+pub mod request_id;
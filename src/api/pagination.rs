@@ -0,0 +1,573 @@
+//! # Pagination
+//!
+//! This module provides helpers to paginate API results.
+//!
+//! Keyset pagination (also called seek method) uses the last seen value of a sortable
+//! column as boundary for the next page, instead of an offset. That allows to keep
+//! performances constant on large tables, as the database doesn't need to skip rows.
+//!
+//! Offset pagination is also offered for listings where clients need the total
+//! count and can jump to a numbered page.
+//!
+//! With the `cursor-signing` feature, when a cursor secret is configured,
+//! the cursors are signed with HMAC-SHA256, so clients can't forge them.
+
+use api::replies::FailureResponse;
+use base64;
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+use diesel::dsl::Gt;
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+use diesel::expression::AsExpression;
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+use diesel::query_dsl::methods::{FilterDsl, LimitDsl, OrderDsl};
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+use diesel::ExpressionMethods;
+#[cfg(feature = "cursor-signing")]
+use hmac::{Hmac, Mac};
+use rocket::http::Status;
+use rocket::Outcome;
+use rocket::Request;
+use rocket::request::{FromRequest, Outcome as RequestOutcome};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "cursor-signing")]
+use sha2::Sha256;
+use std::str::FromStr;
+#[cfg(feature = "cursor-signing")]
+use std::sync::OnceLock;
+
+/*   -------------------------------------------------------------
+     Cursor secret
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The secret to sign the cursors with, when set by the configuration
+#[cfg(feature = "cursor-signing")]
+static CURSOR_SECRET: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Sets the secret to sign the cursors with, returning false if it has already been set.
+#[cfg(feature = "cursor-signing")]
+pub fn set_cursor_secret (secret: &[u8]) -> bool {
+    CURSOR_SECRET.set(secret.to_vec()).is_ok()
+}
+
+/// Gets the secret to sign the cursors with, or None if the cursors aren't signed.
+#[cfg(feature = "cursor-signing")]
+pub fn get_cursor_secret () -> Option<&'static [u8]> {
+    CURSOR_SECRET.get().map(|secret| secret.as_slice())
+}
+
+/// Computes the HMAC-SHA256 signature of a payload.
+#[cfg(feature = "cursor-signing")]
+fn sign (payload: &[u8], secret: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret)
+        .expect("HMAC accepts keys of any size");
+    mac.update(payload);
+
+    mac
+}
+
+/*   -------------------------------------------------------------
+     Cursor
+
+     :: FromRequest
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// An opaque cursor representing the boundary value of a page.
+///
+/// The cursor is read from the `cursor` query parameter, encoded as URL-safe base64.
+/// When the parameter is absent, the cursor hasn't any boundary: the first page is requested.
+///
+/// With the `cursor-signing` feature, when a cursor secret is set, the encoded boundary is followed by a dot and its signature,
+/// and a cursor with an invalid signature is rejected with a 400 Bad request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cursor {
+    boundary: Option<String>,
+}
+
+impl Cursor {
+    /// Builds a cursor to request the first page
+    pub fn start () -> Self {
+        Self {
+            boundary: None,
+        }
+    }
+
+    /// Builds a cursor from a boundary value
+    pub fn new<K: ToString> (boundary: &K) -> Self {
+        Self {
+            boundary: Some(boundary.to_string()),
+        }
+    }
+
+    /// Decodes an opaque cursor, or returns None if it isn't valid.
+    ///
+    /// The signature is checked if a cursor secret is set.
+    #[cfg(feature = "cursor-signing")]
+    pub fn decode (encoded: &str) -> Option<Self> {
+        Self::decode_with(encoded, get_cursor_secret())
+    }
+
+    /// Decodes an opaque cursor, or returns None if it isn't valid.
+    #[cfg(not(feature = "cursor-signing"))]
+    pub fn decode (encoded: &str) -> Option<Self> {
+        Self::decode_payload(encoded)
+    }
+
+    /// Decodes an opaque cursor, checking its signature against the secret if any,
+    /// or returns None if it isn't valid.
+    #[cfg(feature = "cursor-signing")]
+    pub fn decode_with (encoded: &str, secret: Option<&[u8]>) -> Option<Self> {
+        let payload = match secret {
+            None => encoded,
+            Some(secret) => {
+                let separator = encoded.rfind('.')?;
+                let payload = &encoded[..separator];
+                let signature = base64::decode_config(&encoded[separator + 1..], base64::URL_SAFE_NO_PAD).ok()?;
+
+                sign(payload.as_bytes(), secret).verify_slice(&signature).ok()?;
+
+                payload
+            },
+        };
+
+        Self::decode_payload(payload)
+    }
+
+    /// Decodes the base64 payload of a cursor.
+    fn decode_payload (payload: &str) -> Option<Self> {
+        let bytes = base64::decode_config(payload, base64::URL_SAFE_NO_PAD).ok()?;
+        let boundary = String::from_utf8(bytes).ok()?;
+
+        Some(Self {
+            boundary: Some(boundary),
+        })
+    }
+
+    /// Encodes the cursor as an opaque string, or returns None for the first page cursor.
+    ///
+    /// The cursor is signed if a cursor secret is set.
+    #[cfg(feature = "cursor-signing")]
+    pub fn encode (&self) -> Option<String> {
+        self.encode_with(get_cursor_secret())
+    }
+
+    /// Encodes the cursor as an opaque string, or returns None for the first page cursor.
+    #[cfg(not(feature = "cursor-signing"))]
+    pub fn encode (&self) -> Option<String> {
+        self.encode_payload()
+    }
+
+    /// Encodes the cursor as an opaque string, signed with the secret if any,
+    /// or returns None for the first page cursor.
+    #[cfg(feature = "cursor-signing")]
+    pub fn encode_with (&self, secret: Option<&[u8]>) -> Option<String> {
+        let payload = self.encode_payload()?;
+
+        match secret {
+            None => Some(payload),
+            Some(secret) => {
+                let signature = sign(payload.as_bytes(), secret).finalize().into_bytes();
+
+                Some(format!("{}.{}", payload, base64::encode_config(&signature, base64::URL_SAFE_NO_PAD)))
+            },
+        }
+    }
+
+    /// Encodes the boundary as base64, or returns None for the first page cursor.
+    fn encode_payload (&self) -> Option<String> {
+        self.boundary.as_ref().map(|boundary| base64::encode_config(boundary, base64::URL_SAFE_NO_PAD))
+    }
+
+    /// Parses the boundary value into the type of the sortable column.
+    ///
+    /// Returns Ok(None) for the first page, or a 400 Bad request status if the
+    /// boundary can't be parsed.
+    pub fn parse<K: FromStr> (&self) -> Result<Option<K>, Status> {
+        match self.boundary {
+            None => Ok(None),
+            Some(ref boundary) => boundary.parse::<K>()
+                .map(Some)
+                .map_err(|_| Status::BadRequest),
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Cursor {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> RequestOutcome<Self, Self::Error> {
+        let encoded = match request.get_query_value::<String>("cursor") {
+            None => return Outcome::Success(Cursor::start()),
+            Some(Ok(encoded)) => encoded,
+            Some(Err(_)) => return Outcome::Failure((Status::BadRequest, ())),
+        };
+
+        match Cursor::decode(&encoded) {
+            Some(cursor) => Outcome::Success(cursor),
+            None => Outcome::Failure((Status::BadRequest, ())),
+        }
+    }
+}
+
+/*   -------------------------------------------------------------
+     Keyset pagination
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A page of items, with the cursor to fetch the next one.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeysetPage<T> {
+    pub items: Vec<T>,
+
+    /// The opaque cursor to request the next page, or None if this page is the last one
+    pub next_cursor: Option<String>,
+}
+
+/// Loads a page of items using keyset pagination.
+///
+/// The loader receives the boundary value and the number of items to load, and should
+/// run a `WHERE column > boundary ORDER BY column LIMIT n` query, which can be built
+/// by `filter_by_keyset`. One extra item is requested to know if there is a next page.
+///
+/// Returns a 400 Bad Request status if the number of items by page is 0,
+/// as such pages would never reach the end of the listing. The loader errors
+/// are converted into a failure response.
+///
+/// # Examples
+///
+/// ```
+/// #[get("/players")]
+/// pub fn get_players(connection: DatabaseConnection, cursor: Cursor) -> Result<Json<KeysetPage<Player>>, Status> {
+///     let boundary = cursor.parse::<i32>()?;
+///
+///     paginate_by_keyset(boundary, 50, |boundary, limit| {
+///         filter_by_keyset(players.into_boxed(), id, boundary, limit)
+///             .load::<Player>(&*connection)
+///     }, |player| player.id)
+///     .map(Json)
+/// }
+/// ```
+pub fn paginate_by_keyset<T, K, E, F, G>(boundary: Option<K>, per_page: u32, load: F, key: G) -> Result<KeysetPage<T>, Status>
+    where F: FnOnce(Option<K>, i64) -> Result<Vec<T>, E>,
+          G: Fn(&T) -> K,
+          K: ToString,
+          E: FailureResponse
+{
+    if per_page == 0 {
+        return Err(Status::BadRequest);
+    }
+
+    let per_page = per_page as usize;
+    let mut items = load(boundary, per_page as i64 + 1).map_err(|error| error.into_failure_response())?;
+
+    let next_cursor = if items.len() > per_page {
+        items.truncate(per_page);
+        items.last().and_then(|item| Cursor::new(&key(item)).encode())
+    } else {
+        None
+    };
+
+    Ok(KeysetPage {
+        items,
+        next_cursor,
+    })
+}
+
+/// Builds the keyset pagination query of a loader, adding to a boxed query
+/// `WHERE column > boundary` if there is a boundary, then `ORDER BY column LIMIT limit`.
+///
+/// # Examples
+///
+/// ```
+/// let query = filter_by_keyset(players.into_boxed(), id, Some(42), 51);
+/// ```
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+pub fn filter_by_keyset<Q, C, K>(query: Q, column: C, boundary: Option<K>, limit: i64) -> Q
+    where Q: FilterDsl<Gt<C, K>, Output = Q> + OrderDsl<C, Output = Q> + LimitDsl<Output = Q>,
+          C: ExpressionMethods + Copy,
+          K: AsExpression<C::SqlType>
+{
+    let query = match boundary {
+        Some(boundary) => FilterDsl::filter(query, column.gt(boundary)),
+        None => query,
+    };
+
+    LimitDsl::limit(OrderDsl::order(query, column), limit)
+}
+
+/*   -------------------------------------------------------------
+     Offset pagination
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A numbered page of items, with the total count of items.
+///
+/// Pages are numbered from 1. The page is serialized as
+/// `{ "items": [...], "total": n, "page": p, "per_page": n }`.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Paginated<T> {
+    pub items: Vec<T>,
+
+    /// The total count of items, in all pages
+    pub total: u64,
+
+    pub page: u32,
+    pub per_page: u32,
+}
+
+impl<T> Paginated<T> {
+    pub fn new (items: Vec<T>, total: u64, page: u32, per_page: u32) -> Self {
+        Self {
+            items,
+            total,
+            page,
+            per_page,
+        }
+    }
+
+    /// Gets the number of the next page, or None if this page is the last one.
+    ///
+    /// Returns None too for empty pages, which never reach the end of the listing,
+    /// or if the page number would overflow.
+    pub fn next_page (&self) -> Option<u32> {
+        if self.per_page > 0 && (self.page as u64) * (self.per_page as u64) < self.total {
+            self.page.checked_add(1)
+        } else {
+            None
+        }
+    }
+
+    /// Gets the number of the previous page, or None if this page is the first one.
+    pub fn prev_page (&self) -> Option<u32> {
+        if self.page > 1 {
+            Some(self.page - 1)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_round_trip () {
+        let cursor = Cursor::new(&42);
+        let encoded = cursor.encode().unwrap();
+
+        assert_eq!(Some(cursor), Cursor::decode(&encoded));
+        assert_eq!(Ok(Some(42)), Cursor::decode(&encoded).unwrap().parse::<i32>());
+    }
+
+    #[test]
+    fn test_cursor_start () {
+        assert_eq!(None, Cursor::start().encode());
+        assert_eq!(Ok(None), Cursor::start().parse::<i32>());
+    }
+
+    #[test]
+    fn test_cursor_when_invalid () {
+        assert_eq!(None, Cursor::decode("not base64!"));
+        assert_eq!(Err(Status::BadRequest), Cursor::new(&"quux").parse::<i32>());
+    }
+
+    #[test]
+    #[cfg(feature = "cursor-signing")]
+    fn test_signed_cursor_round_trip () {
+        let secret = Some(&b"quux"[..]);
+
+        let cursor = Cursor::new(&42);
+        let encoded = cursor.encode_with(secret).unwrap();
+
+        assert!(encoded.contains('.'), "A signed cursor should contain a signature");
+        assert_eq!(Some(cursor), Cursor::decode_with(&encoded, secret));
+    }
+
+    #[test]
+    #[cfg(feature = "cursor-signing")]
+    fn test_signed_cursor_when_tampered () {
+        let secret = Some(&b"quux"[..]);
+        let encoded = Cursor::new(&42).encode_with(secret).unwrap();
+        let signature = &encoded[encoded.rfind('.').unwrap()..];
+
+        // A cursor forged for another boundary, reusing the signature
+        let forged = format!("{}{}", Cursor::new(&43).encode_with(None).unwrap(), signature);
+        assert_eq!(None, Cursor::decode_with(&forged, secret));
+
+        // A cursor signed with another secret, or not signed at all
+        let foreign = Cursor::new(&42).encode_with(Some(&b"other"[..])).unwrap();
+        assert_eq!(None, Cursor::decode_with(&foreign, secret));
+        assert_eq!(None, Cursor::decode_with(&Cursor::new(&42).encode_with(None).unwrap(), secret));
+    }
+
+    #[test]
+    fn test_paginate_by_keyset_walks_all_pages () {
+        let table: Vec<i32> = (1..=11).collect();
+        let load = |boundary: Option<i32>, limit: i64| -> Result<Vec<i32>, Status> {
+            Ok(table.iter()
+                .cloned()
+                .filter(|id| boundary.map_or(true, |boundary| *id > boundary))
+                .take(limit as usize)
+                .collect())
+        };
+
+        let mut seen = Vec::new();
+        let mut cursor = Cursor::start();
+        loop {
+            let page = paginate_by_keyset(cursor.parse::<i32>().unwrap(), 5, load, |id| *id).unwrap();
+            seen.extend(page.items);
+
+            match page.next_cursor {
+                Some(encoded) => cursor = Cursor::decode(&encoded).unwrap(),
+                None => break,
+            }
+        }
+
+        assert_eq!(table, seen, "Pages should cover all items without gaps or duplicates");
+    }
+
+    #[test]
+    fn test_paginate_by_keyset_when_per_page_is_zero () {
+        let result = paginate_by_keyset(None, 0, |_, _| -> Result<Vec<i32>, Status> {
+            panic!("The loader shouldn't be called for empty pages")
+        }, |id| *id);
+
+        assert_eq!(Err(Status::BadRequest), result);
+    }
+
+    #[test]
+    fn test_paginate_by_keyset_when_load_fails () {
+        let result = paginate_by_keyset(None, 5, |_, _| -> Result<Vec<i32>, Status> {
+            Err(Status::ServiceUnavailable)
+        }, |id| *id);
+
+        assert_eq!(Err(Status::ServiceUnavailable), result);
+    }
+
+    #[cfg(feature = "sqlite")]
+    table! {
+        lf_pagination_test (id) {
+            id -> Integer,
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn test_filter_by_keyset () {
+        use diesel::{Connection, QueryDsl, RunQueryDsl};
+        use diesel::sqlite::SqliteConnection;
+        use self::lf_pagination_test::dsl::{id, lf_pagination_test};
+
+        let connection = SqliteConnection::establish(":memory:").unwrap();
+        ::diesel::sql_query("CREATE TABLE lf_pagination_test (id INTEGER PRIMARY KEY)")
+            .execute(&connection)
+            .unwrap();
+        for value in (1..=11).rev() {
+            ::diesel::sql_query(format!("INSERT INTO lf_pagination_test VALUES ({})", value))
+                .execute(&connection)
+                .unwrap();
+        }
+
+        let load = |boundary: Option<i32>, limit: i64| {
+            filter_by_keyset(lf_pagination_test.select(id).into_boxed(), id, boundary, limit)
+                .load::<i32>(&connection)
+        };
+
+        let first_page = load(None, 3).unwrap();
+        assert_eq!(vec![1, 2, 3], first_page);
+
+        let next_page = load(Some(3), 3).unwrap();
+        assert_eq!(vec![4, 5, 6], next_page);
+
+        let mut seen = Vec::new();
+        let mut cursor = Cursor::start();
+        loop {
+            let page = paginate_by_keyset(cursor.parse::<i32>().unwrap(), 5, load, |item| *item).unwrap();
+            seen.extend(page.items);
+
+            match page.next_cursor {
+                Some(encoded) => cursor = Cursor::decode(&encoded).unwrap(),
+                None => break,
+            }
+        }
+
+        assert_eq!((1..=11).collect::<Vec<i32>>(), seen);
+    }
+
+    #[test]
+    #[cfg(feature = "pgsql")]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    fn test_paginate_by_keyset_with_diesel () {
+        use diesel::{Connection, PgConnection, RunQueryDsl};
+        use diesel::dsl::sql;
+        use diesel::sql_types::{BigInt, Integer};
+
+        // Requires a live database, e.g. TEST_DATABASE_URL=postgres://localhost/test
+        let database_url = ::std::env::var("TEST_DATABASE_URL")
+            .expect("The TEST_DATABASE_URL variable must be set to run this test");
+
+        let connection = PgConnection::establish(&database_url).unwrap();
+        ::diesel::sql_query("CREATE TEMPORARY TABLE lf_pagination_test (id INTEGER PRIMARY KEY)")
+            .execute(&connection)
+            .unwrap();
+        ::diesel::sql_query("INSERT INTO lf_pagination_test SELECT generate_series(1, 11)")
+            .execute(&connection)
+            .unwrap();
+
+        let load = |boundary: Option<i32>, limit: i64| {
+            ::diesel::select(sql::<Integer>("id FROM lf_pagination_test WHERE id > ")
+                .bind::<Integer, _>(boundary.unwrap_or(0))
+                .sql(" ORDER BY id LIMIT ")
+                .bind::<BigInt, _>(limit))
+                .load::<i32>(&connection)
+        };
+
+        let mut seen = Vec::new();
+        let mut cursor = Cursor::start();
+        loop {
+            let page = paginate_by_keyset(cursor.parse::<i32>().unwrap(), 5, load, |id| *id).unwrap();
+            seen.extend(page.items);
+
+            match page.next_cursor {
+                Some(encoded) => cursor = Cursor::decode(&encoded).unwrap(),
+                None => break,
+            }
+        }
+
+        assert_eq!((1..=11).collect::<Vec<i32>>(), seen);
+    }
+
+    #[test]
+    fn test_paginated_serialization () {
+        let page = Paginated::new(vec![1u32, 2, 3], 7, 1, 3);
+        let value = ::serde_json::to_value(&page).unwrap();
+
+        assert_eq!(::serde_json::json!({
+            "items": [1, 2, 3],
+            "total": 7,
+            "page": 1,
+            "per_page": 3,
+        }), value);
+    }
+
+    #[test]
+    fn test_paginated_navigation () {
+        let first = Paginated::new(vec![1u32, 2, 3], 7, 1, 3);
+        assert_eq!(None, first.prev_page());
+        assert_eq!(Some(2), first.next_page());
+
+        let last = Paginated::new(vec![7u32], 7, 3, 3);
+        assert_eq!(Some(2), last.prev_page());
+        assert_eq!(None, last.next_page());
+
+        let exact = Paginated::new(vec![4u32, 5, 6], 6, 2, 3);
+        assert_eq!(None, exact.next_page());
+    }
+
+    #[test]
+    fn test_paginated_navigation_at_bounds () {
+        let empty = Paginated::new(Vec::<u32>::new(), 7, 1, 0);
+        assert_eq!(None, empty.next_page());
+
+        let overflowing = Paginated::new(vec![1u32], u64::MAX, u32::MAX, 1);
+        assert_eq!(None, overflowing.next_page());
+    }
+}
@@ -0,0 +1,403 @@
+//! # Rate limiting
+//!
+//! This module provides a token bucket rate limiter, and a guard to protect
+//! endpoints by authenticating the client then applying a per-principal rate limit.
+
+use api::replies::TooManyRequests;
+use ErrorResult;
+use rocket::http::Status;
+use rocket::Outcome;
+use rocket::Request;
+use rocket::State;
+use rocket::request::{FromRequest, Outcome as RequestOutcome};
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/*   -------------------------------------------------------------
+     Rate limiter
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A token bucket for a principal
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    /// Gets the number of tokens available at the specified instant.
+    fn get_tokens_at (&self, now: Instant, rate: f64) -> f64 {
+        let elapsed = now.duration_since(self.last_refill);
+        let elapsed_seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+
+        self.tokens + elapsed_seconds * rate
+    }
+}
+
+/// The token buckets of the principals
+struct Buckets {
+    by_principal: HashMap<String, Bucket>,
+    last_prune: Instant,
+}
+
+/// The state of the rate limit for a principal, to inform the client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimitInfo {
+    /// The maximum number of requests in a burst
+    pub limit: u32,
+
+    /// The number of requests the principal can still do immediately
+    pub remaining: u32,
+
+    /// The number of seconds before the next request is allowed
+    pub reset: u64,
+}
+
+/// A rate limiter giving each principal a token bucket.
+///
+/// Each request consumes a token. Tokens are refilled at `rate` per second,
+/// up to `burst` tokens.
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<Buckets>,
+    prune_interval: Duration,
+    rate_limit_headers: bool,
+}
+
+impl RateLimiter {
+    /// The default interval between two prunings of the idle buckets
+    pub const DEFAULT_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+    /// Builds a rate limiter refilling `rate` tokens per second, up to `burst` tokens.
+    ///
+    /// Returns an error if the rate isn't a positive number, as the buckets would never be refilled.
+    pub fn new (rate: f64, burst: u32) -> ErrorResult<Self> {
+        if !(rate > 0.0) || rate.is_infinite() {
+            return Err(format!("The rate limit must be a positive number of requests per second, not {}.", rate).into());
+        }
+
+        Ok(Self {
+            rate,
+            burst: burst as f64,
+            buckets: Mutex::new(Buckets {
+                by_principal: HashMap::new(),
+                last_prune: Instant::now(),
+            }),
+            prune_interval: Self::DEFAULT_PRUNE_INTERVAL,
+            rate_limit_headers: true,
+        })
+    }
+
+    /// Allows to change the interval between two prunings of the idle buckets.
+    pub fn with_prune_interval (mut self, interval: Duration) -> Self {
+        self.prune_interval = interval;
+
+        self
+    }
+
+    /// Allows to disable the X-RateLimit-* headers of the 429 responses,
+    /// as some proxies dislike them. The Retry-After header is always sent.
+    pub fn with_rate_limit_headers (mut self, enabled: bool) -> Self {
+        self.rate_limit_headers = enabled;
+
+        self
+    }
+
+    pub fn has_rate_limit_headers (&self) -> bool {
+        self.rate_limit_headers
+    }
+
+    /// Consumes a token for the principal, returning false if the rate limit is exceeded.
+    pub fn try_acquire (&self, principal: &str) -> bool {
+        self.acquire(principal).is_ok()
+    }
+
+    /// Consumes a token for the principal.
+    ///
+    /// Returns the state of the rate limit, as an error if the rate limit is exceeded.
+    pub fn acquire (&self, principal: &str) -> Result<RateLimitInfo, RateLimitInfo> {
+        let now = Instant::now();
+        let mut buckets = match self.buckets.lock() {
+            Ok(buckets) => buckets,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        if now.duration_since(buckets.last_prune) >= self.prune_interval {
+            self.prune_idle_buckets(&mut buckets.by_principal, now);
+            buckets.last_prune = now;
+        }
+
+        let bucket = buckets.by_principal
+            .entry(principal.to_string())
+            .or_insert_with(|| Bucket { tokens: self.burst, last_refill: now });
+
+        bucket.tokens = bucket.get_tokens_at(now, self.rate).min(self.burst);
+        bucket.last_refill = now;
+
+        if bucket.tokens < 1.0 {
+            return Err(RateLimitInfo {
+                limit: self.burst as u32,
+                remaining: 0,
+                reset: ((1.0 - bucket.tokens) / self.rate).ceil() as u64,
+            });
+        }
+
+        bucket.tokens -= 1.0;
+        Ok(RateLimitInfo {
+            limit: self.burst as u32,
+            remaining: bucket.tokens.floor() as u32,
+            reset: 0,
+        })
+    }
+
+    /// Removes the buckets refilled to the burst size, so the map doesn't grow
+    /// with each principal ever seen. Such buckets are the same as new ones.
+    ///
+    /// As it scans every bucket, it only runs once by prune interval.
+    fn prune_idle_buckets (&self, buckets: &mut HashMap<String, Bucket>, now: Instant) {
+        buckets.retain(|_, bucket| bucket.get_tokens_at(now, self.rate) < self.burst);
+    }
+}
+
+/*   -------------------------------------------------------------
+     Rate limited guard
+
+     :: FromRequest
+     :: Deref
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// This trait allows an authenticated principal to be rate limited.
+pub trait RateLimitKey {
+    /// Gets the key identifying the principal in the rate limiter
+    fn get_rate_limit_key(&self) -> String;
+}
+
+/// A guard authenticating the client as a principal `P`, then applying
+/// the rate limit of the managed `RateLimiter` state to this principal.
+///
+/// If the authentication fails, returns a 401 Unauthorized. If the principal guard forwards,
+/// e.g. to let another route serve anonymous clients, the request is forwarded too.
+/// If the principal exceeds the rate limit, returns a 429 Too Many Requests,
+/// answered with Retry-After and X-RateLimit-* headers by the catcher registered by the kernel.
+///
+/// # Examples
+///
+/// ```
+/// #[post("/messages", data="<message>")]
+/// pub fn post_message(user: RateLimited<User>, message: Json<Message>) -> ApiJsonResponse<Message> {
+///     // user derefs to the authenticated User
+/// }
+/// ```
+pub struct RateLimited<P>(pub P);
+
+impl<P> RateLimited<P> {
+    /// Consumes the guard and returns the authenticated principal
+    pub fn into_inner(self) -> P {
+        self.0
+    }
+}
+
+impl<'a, 'r, P> FromRequest<'a, 'r> for RateLimited<P>
+    where P: FromRequest<'a, 'r> + RateLimitKey
+{
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> RequestOutcome<Self, Self::Error> {
+        let principal = match request.guard::<P>() {
+            Outcome::Success(principal) => principal,
+            Outcome::Failure(_) => return Outcome::Failure((Status::Unauthorized, ())),
+            Outcome::Forward(_) => return Outcome::Forward(()),
+        };
+
+        let rate_limiter = request.guard::<State<RateLimiter>>()?;
+        if let Err(info) = rate_limiter.acquire(&principal.get_rate_limit_key()) {
+            let response = TooManyRequests::new(info)
+                .with_rate_limit_headers(rate_limiter.has_rate_limit_headers());
+            request.local_cache(|| Some(response));
+
+            return Outcome::Failure((Status::TooManyRequests, ()));
+        }
+
+        Outcome::Success(RateLimited(principal))
+    }
+}
+
+impl<P> Deref for RateLimited<P> {
+    type Target = P;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::http::Header;
+    use rocket::local::Client;
+
+    struct ApiKey(String);
+
+    impl<'a, 'r> FromRequest<'a, 'r> for ApiKey {
+        type Error = ();
+
+        fn from_request(request: &'a Request<'r>) -> RequestOutcome<Self, Self::Error> {
+            match request.headers().get_one("X-Api-Key") {
+                Some(key) => Outcome::Success(ApiKey(key.to_string())),
+                None => Outcome::Failure((Status::Unauthorized, ())),
+            }
+        }
+    }
+
+    impl RateLimitKey for ApiKey {
+        fn get_rate_limit_key(&self) -> String {
+            self.0.clone()
+        }
+    }
+
+    struct Anonymous;
+
+    impl<'a, 'r> FromRequest<'a, 'r> for Anonymous {
+        type Error = ();
+
+        fn from_request(_request: &'a Request<'r>) -> RequestOutcome<Self, Self::Error> {
+            Outcome::Forward(())
+        }
+    }
+
+    impl RateLimitKey for Anonymous {
+        fn get_rate_limit_key(&self) -> String {
+            String::from("anonymous")
+        }
+    }
+
+    fn build_client () -> Client {
+        let server = ::rocket::ignite().manage(RateLimiter::new(0.001, 2).unwrap());
+
+        Client::untracked(server).unwrap()
+    }
+
+    #[test]
+    fn test_rate_limited_when_unauthenticated () {
+        let client = build_client();
+        let request = client.post("/messages");
+
+        let outcome = request.inner().guard::<RateLimited<ApiKey>>();
+        assert_eq!(Some(Status::Unauthorized), outcome.failed().map(|failure| failure.0));
+    }
+
+    #[test]
+    fn test_rate_limited_when_principal_forwards () {
+        let client = build_client();
+        let request = client.post("/messages");
+
+        assert!(request.inner().guard::<RateLimited<Anonymous>>().is_forward());
+    }
+
+    #[test]
+    fn test_rate_limited_within_limit () {
+        let client = build_client();
+        let request = client.post("/messages").header(Header::new("X-Api-Key", "quux"));
+
+        let principal = request.inner().guard::<RateLimited<ApiKey>>().unwrap();
+        assert_eq!("quux", principal.into_inner().0);
+    }
+
+    #[test]
+    fn test_rate_limited_over_limit () {
+        let client = build_client();
+        let request = client.post("/messages").header(Header::new("X-Api-Key", "quux"));
+
+        assert!(request.inner().guard::<RateLimited<ApiKey>>().is_success());
+        assert!(request.inner().guard::<RateLimited<ApiKey>>().is_success());
+
+        let outcome = request.inner().guard::<RateLimited<ApiKey>>();
+        assert_eq!(Some(Status::TooManyRequests), outcome.failed().map(|failure| failure.0));
+    }
+
+    #[test]
+    fn test_rate_limited_over_limit_headers () {
+        use api::replies::too_many_requests_catcher;
+        use rocket::{Data, Route};
+        use rocket::handler::Outcome as HandlerOutcome;
+        use rocket::http::Method;
+
+        fn post_message<'r>(request: &'r Request, _data: Data) -> HandlerOutcome<'r> {
+            match request.guard::<RateLimited<ApiKey>>() {
+                Outcome::Success(_) => HandlerOutcome::from(request, "OK"),
+                Outcome::Failure((status, _)) => HandlerOutcome::Failure(status),
+                Outcome::Forward(_) => HandlerOutcome::Failure(Status::InternalServerError),
+            }
+        }
+
+        let server = ::rocket::ignite()
+            .manage(RateLimiter::new(0.5, 1).unwrap())
+            .mount("/", vec![Route::new(Method::Post, "/messages", post_message)])
+            .register(vec![too_many_requests_catcher()]);
+        let client = Client::untracked(server).unwrap();
+
+        let response = client.post("/messages").header(Header::new("X-Api-Key", "quux")).dispatch();
+        assert_eq!(Status::Ok, response.status());
+
+        let response = client.post("/messages").header(Header::new("X-Api-Key", "quux")).dispatch();
+        assert_eq!(Status::TooManyRequests, response.status());
+        assert_eq!(Some("2"), response.headers().get_one("Retry-After"));
+        assert_eq!(Some("1"), response.headers().get_one("X-RateLimit-Limit"));
+        assert_eq!(Some("0"), response.headers().get_one("X-RateLimit-Remaining"));
+        assert_eq!(Some("2"), response.headers().get_one("X-RateLimit-Reset"));
+    }
+
+    #[test]
+    fn test_rate_limiter_acquire () {
+        let rate_limiter = RateLimiter::new(0.5, 2).unwrap();
+
+        assert_eq!(Ok(RateLimitInfo { limit: 2, remaining: 1, reset: 0 }), rate_limiter.acquire("foo"));
+        assert_eq!(Ok(RateLimitInfo { limit: 2, remaining: 0, reset: 0 }), rate_limiter.acquire("foo"));
+        assert_eq!(Err(RateLimitInfo { limit: 2, remaining: 0, reset: 2 }), rate_limiter.acquire("foo"));
+    }
+
+    #[test]
+    fn test_rate_limiter_is_per_principal () {
+        let rate_limiter = RateLimiter::new(0.001, 1).unwrap();
+
+        assert!(rate_limiter.try_acquire("foo"));
+        assert!(!rate_limiter.try_acquire("foo"));
+        assert!(rate_limiter.try_acquire("bar"));
+    }
+
+    #[test]
+    fn test_rate_limiter_prunes_idle_buckets () {
+        let rate_limiter = RateLimiter::new(1000.0, 1).unwrap()
+            .with_prune_interval(Duration::from_millis(1));
+
+        assert!(rate_limiter.try_acquire("foo"));
+        ::std::thread::sleep(Duration::from_millis(5));
+        assert!(rate_limiter.try_acquire("bar"));
+
+        let buckets = rate_limiter.buckets.lock().unwrap();
+        assert!(!buckets.by_principal.contains_key("foo"), "The refilled bucket should be pruned");
+        assert!(buckets.by_principal.contains_key("bar"));
+    }
+
+    #[test]
+    fn test_rate_limiter_prunes_once_by_interval () {
+        let rate_limiter = RateLimiter::new(1000.0, 1).unwrap();
+
+        assert!(rate_limiter.try_acquire("foo"));
+        ::std::thread::sleep(Duration::from_millis(5));
+        assert!(rate_limiter.try_acquire("bar"));
+
+        let buckets = rate_limiter.buckets.lock().unwrap();
+        assert!(buckets.by_principal.contains_key("foo"), "The buckets shouldn't be pruned before the interval");
+    }
+
+    #[test]
+    fn test_rate_limiter_rejects_invalid_rate () {
+        assert!(RateLimiter::new(0.0, 1).is_err());
+        assert!(RateLimiter::new(-1.0, 1).is_err());
+        assert!(RateLimiter::new(::std::f64::NAN, 1).is_err());
+        assert!(RateLimiter::new(::std::f64::INFINITY, 1).is_err());
+    }
+}
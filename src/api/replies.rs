@@ -2,16 +2,45 @@
 //!
 //! This module provides useful traits and methods to craft API replies from an existing type.
 
-#[cfg(feature = "pgsql")]
+#[cfg(feature = "serialization")]
+use api::guards::{Prefer, ReturnPreference};
+use api::guards::RangeHeader;
+use api::rate_limit::RateLimitInfo;
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
 use diesel::result::{DatabaseErrorInformation, DatabaseErrorKind, QueryResult};
-#[cfg(feature = "pgsql")]
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
 use diesel::result::Error as ResultError;
 
-use rocket::http::Status;
+#[cfg(feature = "serialization")]
+use flate2::Compression;
+use log::{Level, Log, Record};
+#[cfg(feature = "serialization")]
+use flate2::write::GzEncoder;
+use rocket::Catcher;
+use rocket::http::{ContentType, Header, Status};
+use rocket::Request;
+use rocket::Response;
+use rocket::State;
+use rocket::response::{Responder, Result as ResponseResult};
+use rocket::response::content::Plain;
+use rocket::response::status::Custom;
 use rocket_contrib::json::Json;
 
 #[cfg(feature = "serialization")]
 use serde::Serialize;
+#[cfg(feature = "serialization")]
+use serde_json;
+
+#[cfg(feature = "validation")]
+use validator::ValidationErrors;
+
+use std::borrow::Cow;
+use std::fs::File;
+#[cfg(feature = "serialization")]
+use std::io::{Cursor, Write};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 /*   -------------------------------------------------------------
      Custom types
@@ -19,10 +48,13 @@ use serde::Serialize;
 
 pub type ApiJsonResponse<T> = Result<Json<T>, Status>;
 
+pub type ApiTextResponse = Result<Plain<String>, Status>;
+
 /*   -------------------------------------------------------------
      API Response
 
      :: Implementation for QueryResult (Diesel ORM)
+     :: Implementation for Option
      :: Implementation for Json (Rocket contrib)
      :: Implementation for Serialize (Serde)
      - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
@@ -33,7 +65,7 @@ pub trait ApiResponse<T> {
     fn into_json_response(self) -> ApiJsonResponse<T>;
 }
 
-#[cfg(feature = "pgsql")]
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
 impl<T> ApiResponse<T> for QueryResult<T> {
     /// Prepares an API response from a query result.
     ///
@@ -89,22 +121,75 @@ impl<T> ApiResponse<T> for QueryResult<T> {
     /// taken), it returns a 409 Conflict.
     /// If the failure is from a foreign key integrity constraint, it returns a 400.
     /// If there is any other database issue, it returns a 500.
+    ///
+    /// As `QueryResult<T>` is an alias for `Result<T, diesel::result::Error>`, the result
+    /// can be transformed before, keeping the same error handling:
+    ///
+    /// ```
+    /// #[get("/player/<name>/score")]
+    /// pub fn get_player_score(connection: DatabaseConnection, name: String) -> ApiJsonResponse<Score> {
+    ///     players
+    ///         .filter(username.eq(&name))
+    ///         .first::<Player>(&*connection)
+    ///         .map(|player| player.compute_score())
+    ///         .into_json_response()
+    /// }
+    /// ```
     fn into_json_response(self) -> ApiJsonResponse<T> {
         self
             // CASE I - The query returns one value, we return a JSON representation fo the item
             .map(|item| Json(item))
-            .map_err(|error| match error {
-                // Case II - The query returns no result, we return a 404 Not found response
-                ResultError::NotFound => Status::NotFound,
+            .map_err(build_query_error_response)
+    }
+}
 
-                // Case III -  We need to handle a database error, which could be a 400/409/500
-                ResultError::DatabaseError(kind, details) => {
-                    build_database_error_response(kind, details)
-                }
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+impl<T> ApiResponse<T> for QueryResult<Option<T>> {
+    /// Prepares an API response from an optional query result.
+    ///
+    /// This is the result of the Diesel `optional` method, to fetch an item or get None.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// #[get("/player/<name>")]
+    /// pub fn get_player(connection: DatabaseConnection, name: String) -> ApiJsonResponse<Player> {
+    ///     players
+    ///         .filter(username.eq(&name))
+    ///         .first::<Player>(&*connection)
+    ///         .optional()
+    ///         .into_json_response()
+    /// }
+    /// ```
+    ///
+    /// This will produce a JSON representation when the result is found,
+    /// a 404 error when no result is found, and an error response according
+    /// the database error otherwise, like for `QueryResult<T>`.
+    fn into_json_response(self) -> ApiJsonResponse<T> {
+        match self {
+            Ok(Some(item)) => Ok(Json(item)),
+            Ok(None) => Err(Status::NotFound),
+            Err(error) => Err(build_query_error_response(error)),
+        }
+    }
+}
 
-                // Case IV - The error is probably server responsibility, log it and throw a 500
-                _ => error.into_failure_response(),
-            })
+/// Prepares an API response from an optional value, with a 404 error when there is none.
+///
+/// As the `Option` is also serializable, the expected response type must be known,
+/// e.g. from the return type of the handler.
+///
+/// # Examples
+///
+/// ```
+/// #[get("/player/<name>")]
+/// pub fn get_player(registry: State<PlayersRegistry>, name: String) -> ApiJsonResponse<Player> {
+///     registry.find(&name).into_json_response()
+/// }
+/// ```
+impl<T> ApiResponse<T> for Option<T> {
+    fn into_json_response(self) -> ApiJsonResponse<T> {
+        self.map(Json).ok_or(Status::NotFound)
     }
 }
 
@@ -120,6 +205,9 @@ impl<T> ApiResponse<T> for Json<T> {
 /// This is probably the easiest way to convert most struct
 /// into API responders.
 ///
+/// It also covers dynamic JSON assembled as a `serde_json::Value`,
+/// e.g. for proxy or aggregation endpoints, sent as is.
+///
 /// # Examples
 ///
 #[cfg(feature = "serialization")]
@@ -131,13 +219,54 @@ impl<T> ApiResponse<T> for T
     }
 }
 
+/*   -------------------------------------------------------------
+     Text Response
+
+     :: Implementation for &str
+     :: Implementation for String
+     :: Implementation for Cow<str>
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// This trait allows to consume a string into a plain text HTTP response.
+///
+/// # Examples
+///
+/// ```
+/// #[get("/status")]
+/// pub fn status() -> ApiTextResponse {
+///     "ALIVE".into_text_response()
+/// }
+/// ```
+pub trait TextResponse {
+    /// Consumes the value and creates a 200 text/plain response.
+    fn into_text_response(self) -> ApiTextResponse;
+}
+
+impl<'a> TextResponse for &'a str {
+    fn into_text_response(self) -> ApiTextResponse {
+        Ok(Plain(self.to_string()))
+    }
+}
+
+impl TextResponse for String {
+    fn into_text_response(self) -> ApiTextResponse {
+        Ok(Plain(self))
+    }
+}
+
+impl<'a> TextResponse for Cow<'a, str> {
+    fn into_text_response(self) -> ApiTextResponse {
+        Ok(Plain(self.into_owned()))
+    }
+}
+
 /*   -------------------------------------------------------------
      API Delete Response
 
      :: Implementation for QueryResult (Diesel ORM)
      - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
 
-#[cfg(feature = "pgsql")]
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
 /// This trait allows to consume an object into an HTTP response.
 ///
 /// This response is a odd case for DELETE queries, which return
@@ -147,7 +276,7 @@ pub trait ApiDeleteResponse<T> {
     fn into_delete_json_response(self) -> ApiJsonResponse<()>;
 }
 
-#[cfg(feature = "pgsql")]
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
 impl ApiDeleteResponse<usize> for QueryResult<usize> {
     fn into_delete_json_response(self) -> ApiJsonResponse<()> {
         match self {
@@ -158,6 +287,378 @@ impl ApiDeleteResponse<usize> for QueryResult<usize> {
     }
 }
 
+/*   -------------------------------------------------------------
+     Envelope
+
+     :: Responder
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A JSON responder wrapping the value under a `data` key, with optional metadata
+/// under a `meta` key, for APIs wrapping all their successful responses.
+///
+/// # Examples
+///
+/// ```
+/// #[get("/players")]
+/// pub fn get_players(connection: DatabaseConnection) -> Envelope<Vec<Player>> {
+///     let players = players.load::<Player>(&*connection).unwrap_or_default();
+///     let count = players.len();
+///
+///     Envelope::with_meta(players, json!({ "count": count }))
+/// }
+/// ```
+#[cfg(feature = "serialization")]
+#[derive(Debug, Serialize)]
+pub struct Envelope<T, M = serde_json::Value> {
+    pub data: T,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<M>,
+}
+
+#[cfg(feature = "serialization")]
+impl<T> Envelope<T> {
+    pub fn new (data: T) -> Self {
+        Self {
+            data,
+            meta: None,
+        }
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl<T, M> Envelope<T, M> {
+    pub fn with_meta (data: T, meta: M) -> Self {
+        Self {
+            data,
+            meta: Some(meta),
+        }
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl<'r, T: Serialize, M: Serialize> Responder<'r> for Envelope<T, M> {
+    fn respond_to(self, request: &Request) -> ResponseResult<'r> {
+        Json(self).respond_to(request)
+    }
+}
+
+/*   -------------------------------------------------------------
+     Created responder
+
+     :: Responder
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A JSON responder for creation endpoints, replying 201 Created
+/// with the URL of the new resource in the Location header.
+///
+/// # Examples
+///
+/// ```
+/// #[post("/players", data="<player>")]
+/// pub fn post_player(connection: DatabaseConnection, player: Json<NewPlayer>) -> Result<Created<Player>, Status> {
+///     let player = insert_player(&*connection, player.into_inner())?;
+///     let location = format!("/players/{}", player.name);
+///
+///     Ok(Created::new(player, &location))
+/// }
+/// ```
+#[cfg(feature = "serialization")]
+#[derive(Debug)]
+pub struct Created<T> {
+    pub data: T,
+    pub location: String,
+}
+
+#[cfg(feature = "serialization")]
+impl<T> Created<T> {
+    pub fn new (data: T, location: &str) -> Self {
+        Self {
+            data,
+            location: location.to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl<'r, T: Serialize> Responder<'r> for Created<T> {
+    fn respond_to(self, request: &Request) -> ResponseResult<'r> {
+        Upserted::new(self.data, true)
+            .with_location(&self.location)
+            .respond_to(request)
+    }
+}
+
+/*   -------------------------------------------------------------
+     Upsert responder
+
+     :: Responder
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A JSON responder for upsert endpoints, replying 201 Created when the resource
+/// has been inserted, with an optional Location header, and 200 OK when it has been updated.
+///
+/// # Examples
+///
+/// ```
+/// #[put("/players/<name>", data="<player>")]
+/// pub fn put_player(connection: DatabaseConnection, name: String, player: Json<Player>) -> Upserted<Player> {
+///     let (player, created) = upsert_player(&*connection, &name, player.into_inner());
+///
+///     Upserted::new(player, created).with_location(&format!("/players/{}", name))
+/// }
+/// ```
+#[cfg(feature = "serialization")]
+#[derive(Debug)]
+pub struct Upserted<T> {
+    pub data: T,
+    pub created: bool,
+
+    /// The URL of the resource, sent in the Location header when it has been created
+    pub location: Option<String>,
+}
+
+#[cfg(feature = "serialization")]
+impl<T> Upserted<T> {
+    pub fn new (data: T, created: bool) -> Self {
+        Self {
+            data,
+            created,
+            location: None,
+        }
+    }
+
+    pub fn with_location (mut self, location: &str) -> Self {
+        self.location = Some(location.to_string());
+
+        self
+    }
+
+    pub fn get_status (&self) -> Status {
+        if self.created {
+            Status::Created
+        } else {
+            Status::Ok
+        }
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl<'r, T: Serialize> Responder<'r> for Upserted<T> {
+    fn respond_to(self, request: &Request) -> ResponseResult<'r> {
+        let status = self.get_status();
+        let location = if self.created { self.location } else { None };
+
+        let mut response = Custom(status, Json(self.data)).respond_to(request)?;
+        if let Some(location) = location {
+            response.set_raw_header("Location", location);
+        }
+
+        Ok(response)
+    }
+}
+
+/*   -------------------------------------------------------------
+     Preferred response
+
+     :: Responder
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A JSON responder honoring the return preference of the client, as read by the `Prefer` guard:
+/// a 204 No Content for `return=minimal`, the JSON representation otherwise.
+///
+/// When a preference has been expressed, it's echoed in the Preference-Applied header.
+#[cfg(feature = "serialization")]
+#[derive(Debug)]
+pub struct PreferredResponse<T> {
+    pub data: T,
+    pub preference: Option<ReturnPreference>,
+}
+
+#[cfg(feature = "serialization")]
+impl<T> PreferredResponse<T> {
+    pub fn new (data: T, prefer: &Prefer) -> Self {
+        Self {
+            data,
+            preference: prefer.return_preference,
+        }
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl<'r, T: Serialize> Responder<'r> for PreferredResponse<T> {
+    fn respond_to(self, request: &Request) -> ResponseResult<'r> {
+        let mut response = match self.preference {
+            Some(ReturnPreference::Minimal) => Response::build().status(Status::NoContent).finalize(),
+            _ => Json(self.data).respond_to(request)?,
+        };
+
+        if let Some(preference) = self.preference {
+            response.set_raw_header("Preference-Applied", preference.as_str());
+        }
+
+        Ok(response)
+    }
+}
+
+/*   -------------------------------------------------------------
+     Batch response
+
+     :: Responder
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The result of one operation of a batch.
+#[cfg(feature = "serialization")]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BatchEntry {
+    pub status: u16,
+    pub body: serde_json::Value,
+}
+
+/// A 207 Multi-Status responder for batch endpoints, with the result of each
+/// operation as a JSON body like `[{"status":200,"body":{...}},{"status":404,"body":"..."}]`.
+///
+/// # Examples
+///
+/// ```
+/// #[post("/players/batch", data="<names>")]
+/// pub fn get_players(connection: DatabaseConnection, names: Json<Vec<String>>) -> BatchResponse {
+///     names.iter().fold(BatchResponse::new(), |batch, name| {
+///         match find_player(&*connection, name) {
+///             Some(player) => batch.with_entry(Status::Ok, &player),
+///             None => batch.with_entry(Status::NotFound, &"No such player."),
+///         }
+///     })
+/// }
+/// ```
+#[cfg(feature = "serialization")]
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct BatchResponse {
+    pub entries: Vec<BatchEntry>,
+}
+
+#[cfg(feature = "serialization")]
+impl BatchResponse {
+    pub fn new () -> Self {
+        Self::default()
+    }
+
+    /// Adds the result of an operation.
+    ///
+    /// If the body can't be serialized, the operation is reported as a 500 Internal server error.
+    pub fn with_entry<T: Serialize> (mut self, status: Status, body: &T) -> Self {
+        let entry = match serde_json::to_value(body) {
+            Ok(body) => BatchEntry {
+                status: status.code,
+                body,
+            },
+            Err(error) => BatchEntry {
+                status: build_internal_server_error_response(&error.to_string()).code,
+                body: serde_json::Value::Null,
+            },
+        };
+        self.entries.push(entry);
+
+        self
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl<'r> Responder<'r> for BatchResponse {
+    fn respond_to(self, request: &Request) -> ResponseResult<'r> {
+        Custom(Status::MultiStatus, Json(self.entries)).respond_to(request)
+    }
+}
+
+/*   -------------------------------------------------------------
+     Gzip JSON responder
+
+     :: Responder
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The minimal size of a JSON body, in bytes, to compress it
+#[cfg(feature = "serialization")]
+pub const GZIP_THRESHOLD: usize = 1024;
+
+/// A JSON responder compressing the body with gzip when the client accepts it.
+///
+/// Small bodies, under `GZIP_THRESHOLD` bytes, are sent uncompressed, as the gain
+/// isn't worth the cost.
+///
+/// # Examples
+///
+/// ```
+/// #[get("/players")]
+/// pub fn get_players(connection: DatabaseConnection) -> GzipJson<Vec<Player>> {
+///     GzipJson(players.load::<Player>(&*connection).unwrap_or_default())
+/// }
+/// ```
+#[cfg(feature = "serialization")]
+pub struct GzipJson<T>(pub T);
+
+#[cfg(feature = "serialization")]
+impl<'r, T: Serialize> Responder<'r> for GzipJson<T> {
+    fn respond_to(self, request: &Request) -> ResponseResult<'r> {
+        let body = serde_json::to_vec(&self.0)
+            .map_err(|error| build_internal_server_error_response(&error.to_string()))?;
+
+        let mut response = Response::build();
+        response
+            .header(ContentType::JSON)
+            .raw_header("Vary", "Accept-Encoding");
+
+        if body.len() < GZIP_THRESHOLD || !accepts_gzip(request) {
+            return response.sized_body(Cursor::new(body)).ok();
+        }
+
+        let compressed_body = compress_with_gzip(&body)
+            .map_err(|error| build_internal_server_error_response(&error.to_string()))?;
+
+        response
+            .raw_header("Content-Encoding", "gzip")
+            .sized_body(Cursor::new(compressed_body))
+            .ok()
+    }
+}
+
+/// Determines if the client accepts gzip encoding according the Accept-Encoding header.
+///
+/// An explicit gzip entry takes precedence over the `*` wildcard, so `gzip;q=0, *`
+/// refuses gzip.
+#[cfg(feature = "serialization")]
+fn accepts_gzip(request: &Request) -> bool {
+    let mut gzip = None;
+    let mut wildcard = None;
+
+    for coding in request.headers().get("Accept-Encoding").flat_map(|value| value.split(',')) {
+        let mut parameters = coding.split(';').map(|parameter| parameter.trim());
+        let name = parameters.next().unwrap_or("");
+        let is_accepted = !parameters.any(is_zero_quality);
+
+        if name.eq_ignore_ascii_case("gzip") {
+            gzip = Some(is_accepted);
+        } else if name == "*" {
+            wildcard = Some(is_accepted);
+        }
+    }
+
+    gzip.or(wildcard).unwrap_or(false)
+}
+
+/// Determines if a parameter of an Accept-Encoding entry is a `q=0` quality value, refusing the coding.
+#[cfg(feature = "serialization")]
+fn is_zero_quality(parameter: &str) -> bool {
+    parameter.starts_with("q=") && parameter[2..].parse::<f32>().map(|quality| quality == 0.0).unwrap_or(false)
+}
+
+#[cfg(feature = "serialization")]
+fn compress_with_gzip(content: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(content)?;
+
+    encoder.finish()
+}
+
 /*   -------------------------------------------------------------
      Failure response
 
@@ -168,9 +669,23 @@ impl ApiDeleteResponse<usize> for QueryResult<usize> {
 pub trait FailureResponse {
     /// Consumes the variable and creates a Failure response .
     fn into_failure_response(self) -> Status;
+
+    /// Consumes the variable and creates a RFC 7807 problem details response.
+    #[cfg(feature = "serialization")]
+    fn into_problem(self) -> ProblemResponse where Self: Sized {
+        ProblemResponse::from_status(self.into_failure_response())
+    }
+}
+
+/// Allows helpers returning a failure response to be used as loaders or callbacks
+/// of other helpers, e.g. in `paginate_by_keyset`.
+impl FailureResponse for Status {
+    fn into_failure_response(self) -> Status {
+        self
+    }
 }
 
-#[cfg(feature = "pgsql")]
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
 impl FailureResponse for ResultError {
     /// Consumes the error and creates a 500 Internal server error Status response.
     fn into_failure_response(self) -> Status {
@@ -178,33 +693,973 @@ impl FailureResponse for ResultError {
     }
 }
 
-/*   -------------------------------------------------------------
-     Helper methods to prepare API responses
-     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+/// Allows handlers returning `anyhow::Result` to use `into_problem_response`.
+///
+/// The error chain is logged. It's only sent to the client in verbose errors mode,
+/// to avoid to leak internals in production.
+#[cfg(feature = "anyhow")]
+impl FailureResponse for anyhow::Error {
+    /// Consumes the error and creates a 500 Internal server error Status response.
+    fn into_failure_response(self) -> Status {
+        build_internal_server_error_response(&format!("{:#}", self))
+    }
 
-#[deprecated(since="0.6.0", note="Use directly Status::NotFound instead.")]
-pub fn build_not_found_response() -> Status {
-    Status::NotFound
+    #[cfg(feature = "serialization")]
+    fn into_problem(self) -> ProblemResponse {
+        let detail = describe_internal_error(&self, is_verbose_errors());
+        let status = self.into_failure_response();
+
+        ProblemResponse::from_status(status).with_detail(&detail)
+    }
 }
 
-#[deprecated(since="0.6.0", note="Use directly Status::BadRequest instead.")]
-pub fn build_bad_request_response() -> Status {
-    Status::BadRequest
+/// Describes an internal error for the client, with the error chain in verbose mode,
+/// or as a generic message otherwise.
+#[cfg(feature = "anyhow")]
+fn describe_internal_error(error: &anyhow::Error, verbose: bool) -> String {
+    if verbose {
+        format!("{:#}", error)
+    } else {
+        Status::InternalServerError.reason.to_string()
+    }
 }
 
-pub fn build_internal_server_error_response(message: &str) -> Status {
-    warn!(target:"api", "{}", message);
+/// Determines if the internal errors details are sent to the client
+static VERBOSE_ERRORS: OnceLock<bool> = OnceLock::new();
 
-    Status::InternalServerError
+/// Sets if the internal errors details are sent to the client, returning false if it has already been set.
+pub fn set_verbose_errors (verbose: bool) -> bool {
+    VERBOSE_ERRORS.set(verbose).is_ok()
 }
 
-#[cfg(feature = "pgsql")]
-fn build_database_error_response(error_kind: DatabaseErrorKind, info: Box<dyn DatabaseErrorInformation>) -> Status {
-    match error_kind {
-        // Case IIIa - The query tries to do an INSERT violating an unique constraint
-        //             e.g. two INSERT with the same unique value
-        //             We return a 409 Conflict
-        DatabaseErrorKind::UniqueViolation => Status::Conflict,
+/// Determines if the internal errors details are sent to the client, false by default.
+pub fn is_verbose_errors () -> bool {
+    *VERBOSE_ERRORS.get().unwrap_or(&false)
+}
+
+/*   -------------------------------------------------------------
+     API error
+
+     :: Responder
+     :: FailureResponse
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// An error to short-circuit a request handler with a specific status and message.
+///
+/// The message is serialized as JSON body. Server errors (5xx) are logged when the error
+/// is turned into a response, client errors (4xx) aren't.
+///
+/// Headers like `Retry-After` or `WWW-Authenticate` can be added to the response.
+///
+/// # Examples
+///
+/// ```
+/// #[delete("/player/<name>")]
+/// pub fn delete_player(connection: DatabaseConnection, name: String, user: User) -> Result<Json<()>, ApiError> {
+///     if !user.is_admin() {
+///         return Err(api_error(Status::Forbidden, "no access"));
+///     }
+///
+///     // ...
+/// }
+/// ```
+///
+/// ```
+/// Err(api_error(Status::ServiceUnavailable, "maintenance in progress").with_header("Retry-After", "120"))
+/// ```
+#[derive(Debug, PartialEq, Eq)]
+pub struct ApiError {
+    pub status: Status,
+    pub message: String,
+    pub headers: Vec<Header<'static>>,
+}
+
+impl ApiError {
+    pub fn new (status: Status, message: &str) -> Self {
+        Self {
+            status,
+            message: message.to_string(),
+            headers: Vec::new(),
+        }
+    }
+
+    /// Adds a header to the error response.
+    pub fn with_header (mut self, name: &str, value: &str) -> Self {
+        self.headers.push(Header::new(name.to_string(), value.to_string()));
+
+        self
+    }
+
+    /// Logs the error to the global logger, if it's a server error.
+    fn log(&self) {
+        if Level::Warn <= ::log::max_level() {
+            self.log_to(::log::logger());
+        }
+    }
+
+    /// Logs the error to the specified logger, if it's a server error.
+    fn log_to(&self, logger: &dyn Log) {
+        if is_server_error(self.status) {
+            logger.log(&Record::builder()
+                .args(format_args!("{}", self.message))
+                .level(Level::Warn)
+                .target("api")
+                .build());
+        }
+    }
+}
+
+impl From<Status> for ApiError {
+    fn from(status: Status) -> Self {
+        ApiError::new(status, status.reason)
+    }
+}
+
+impl<'r> Responder<'r> for ApiError {
+    fn respond_to(self, request: &Request) -> ResponseResult<'r> {
+        self.log();
+
+        let mut response = Custom(self.status, Json(self.message)).respond_to(request)?;
+        for header in self.headers {
+            response.set_header(header);
+        }
+
+        Ok(response)
+    }
+}
+
+impl FailureResponse for ApiError {
+    fn into_failure_response(self) -> Status {
+        self.log();
+
+        self.status
+    }
+
+    #[cfg(feature = "serialization")]
+    fn into_problem(self) -> ProblemResponse {
+        self.log();
+
+        ProblemResponse::from_status(self.status).with_detail(&self.message)
+    }
+}
+
+/// Builds an API error with the specified status and message.
+pub fn api_error(status: Status, message: &str) -> ApiError {
+    ApiError::new(status, message)
+}
+
+/// Determines if the status is a server error (5xx) which should be logged.
+fn is_server_error(status: Status) -> bool {
+    status.code >= 500 && status.code < 600
+}
+
+/*   -------------------------------------------------------------
+     Problem details
+
+     :: Responder
+     :: Implementation for Result<T, FailureResponse>
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+#[cfg(feature = "serialization")]
+pub type ApiProblemResponse<T> = Result<Json<T>, ProblemResponse>;
+
+/// An error response following the RFC 7807 problem details format,
+/// sent as `application/problem+json`.
+///
+/// # Examples
+///
+/// ```
+/// #[get("/player/<name>")]
+/// pub fn get_player(connection: DatabaseConnection, name: String) -> ApiProblemResponse<Player> {
+///     find_player(&*connection, &name)
+///         .map_err(|_| api_error(Status::NotFound, "No such player."))
+///         .into_problem_response()
+/// }
+/// ```
+#[cfg(feature = "serialization")]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ProblemResponse {
+    /// An URI identifying the problem type, `about:blank` when the status is enough
+    #[serde(rename = "type")]
+    pub problem_type: String,
+
+    pub title: String,
+    pub status: u16,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[cfg(feature = "serialization")]
+impl ProblemResponse {
+    /// Builds a problem described by the status only.
+    pub fn from_status (status: Status) -> Self {
+        Self {
+            problem_type: String::from("about:blank"),
+            title: status.reason.to_string(),
+            status: status.code,
+            detail: None,
+        }
+    }
+
+    pub fn with_type (mut self, problem_type: &str, title: &str) -> Self {
+        self.problem_type = problem_type.to_string();
+        self.title = title.to_string();
+
+        self
+    }
+
+    pub fn with_detail (mut self, detail: &str) -> Self {
+        self.detail = Some(detail.to_string());
+
+        self
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl<'r> Responder<'r> for ProblemResponse {
+    fn respond_to(self, _request: &Request) -> ResponseResult<'r> {
+        let status = Status::from_code(self.status).unwrap_or(Status::InternalServerError);
+        let body = serde_json::to_vec(&self)
+            .map_err(|error| build_internal_server_error_response(&error.to_string()))?;
+
+        Response::build()
+            .status(status)
+            .header(ContentType::new("application", "problem+json"))
+            .sized_body(Cursor::new(body))
+            .ok()
+    }
+}
+
+/// This trait allows to consume a result into a JSON response, or a problem details response.
+#[cfg(feature = "serialization")]
+pub trait ProblemApiResponse<T> {
+    fn into_problem_response(self) -> ApiProblemResponse<T>;
+}
+
+#[cfg(feature = "serialization")]
+impl<T, E> ProblemApiResponse<T> for Result<T, E>
+    where E: FailureResponse
+{
+    fn into_problem_response(self) -> ApiProblemResponse<T> {
+        self
+            .map(Json)
+            .map_err(FailureResponse::into_problem)
+    }
+}
+
+/*   -------------------------------------------------------------
+     Validation failure
+
+     :: Responder
+     :: FailureResponse
+     :: Implementation for validator::ValidationErrors
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A field failing a validation rule.
+#[cfg(feature = "serialization")]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+
+    /// The failed validation rule, e.g. `length` or `email`
+    pub code: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// A 422 Unprocessable Entity response, listing the fields failing validation
+/// as a JSON body like `{"errors":[{"field":"email","code":"email"}]}`.
+///
+/// With the `validation` feature, the errors of the `validator` crate are converted into it.
+///
+/// # Examples
+///
+/// ```
+/// #[post("/register", data="<user>")]
+/// pub fn register(connection: DatabaseConnection, user: Json<UserToRegister>) -> Result<Json<Player>, ValidationFailure> {
+///     user.validate()?;
+///
+///     // ...
+/// }
+/// ```
+#[cfg(feature = "serialization")]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ValidationFailure {
+    pub errors: Vec<FieldError>,
+}
+
+#[cfg(feature = "serialization")]
+impl ValidationFailure {
+    pub fn new () -> Self {
+        Self::default()
+    }
+
+    pub fn with_error (mut self, field: &str, code: &str) -> Self {
+        self.errors.push(FieldError {
+            field: field.to_string(),
+            code: code.to_string(),
+            message: None,
+        });
+
+        self
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl<'r> Responder<'r> for ValidationFailure {
+    fn respond_to(self, request: &Request) -> ResponseResult<'r> {
+        Custom(Status::UnprocessableEntity, Json(self)).respond_to(request)
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl FailureResponse for ValidationFailure {
+    fn into_failure_response(self) -> Status {
+        Status::UnprocessableEntity
+    }
+}
+
+#[cfg(feature = "validation")]
+impl From<ValidationErrors> for ValidationFailure {
+    /// Converts the errors of the fields, sorted by field name.
+    ///
+    /// The errors of nested structs and lists aren't converted.
+    fn from(errors: ValidationErrors) -> Self {
+        let mut field_errors: Vec<_> = errors.field_errors().into_iter().collect();
+        field_errors.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let errors = field_errors
+            .into_iter()
+            .flat_map(|(field, errors)| {
+                errors.iter().map(move |error| FieldError {
+                    field: field.to_string(),
+                    code: error.code.to_string(),
+                    message: error.message.as_ref().map(|message| message.to_string()),
+                })
+            })
+            .collect();
+
+        Self { errors }
+    }
+}
+
+#[cfg(feature = "validation")]
+impl FailureResponse for ValidationErrors {
+    fn into_failure_response(self) -> Status {
+        Status::UnprocessableEntity
+    }
+}
+
+/*   -------------------------------------------------------------
+     Gone
+
+     :: Responder
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A 410 Gone responder for removed resources or endpoints, explaining the replacement
+/// as JSON body like `{"message":"...","replacement":"/v2/players"}`.
+///
+/// The `Deprecation` and `Sunset` headers can be added, with HTTP-dates,
+/// to tell the clients when the resource has been deprecated and removed.
+///
+/// # Examples
+///
+/// ```
+/// #[get("/v1/players")]
+/// pub fn get_players_v1() -> Gone {
+///     Gone::new()
+///         .with_message("The v1 API has been removed.")
+///         .with_replacement("/v2/players")
+///         .with_sunset("Sun, 01 Sep 2024 00:00:00 GMT")
+/// }
+/// ```
+#[cfg(feature = "serialization")]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Gone {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+
+    /// The URL of the resource to use instead
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replacement: Option<String>,
+
+    #[serde(skip)]
+    pub deprecation: Option<String>,
+
+    #[serde(skip)]
+    pub sunset: Option<String>,
+}
+
+#[cfg(feature = "serialization")]
+impl Gone {
+    pub fn new () -> Self {
+        Self::default()
+    }
+
+    pub fn with_message (mut self, message: &str) -> Self {
+        self.message = Some(message.to_string());
+
+        self
+    }
+
+    pub fn with_replacement (mut self, replacement: &str) -> Self {
+        self.replacement = Some(replacement.to_string());
+
+        self
+    }
+
+    /// Adds a Deprecation header, with the HTTP-date the resource has been deprecated.
+    pub fn with_deprecation (mut self, date: &str) -> Self {
+        self.deprecation = Some(date.to_string());
+
+        self
+    }
+
+    /// Adds a Sunset header, with the HTTP-date the resource has been removed.
+    pub fn with_sunset (mut self, date: &str) -> Self {
+        self.sunset = Some(date.to_string());
+
+        self
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl<'r> Responder<'r> for Gone {
+    fn respond_to(self, request: &Request) -> ResponseResult<'r> {
+        let headers: Vec<_> = vec![("Deprecation", &self.deprecation), ("Sunset", &self.sunset)]
+            .into_iter()
+            .filter_map(|(name, value)| value.clone().map(|value| Header::new(name, value)))
+            .collect();
+
+        let mut response = if self.message.is_none() && self.replacement.is_none() {
+            build_empty_response(Status::Gone)
+        } else {
+            Custom(Status::Gone, Json(self)).respond_to(request)?
+        };
+
+        for header in headers {
+            response.set_header(header);
+        }
+
+        Ok(response)
+    }
+}
+
+/*   -------------------------------------------------------------
+     Service unavailable
+
+     :: Responder
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The default delay, in seconds, a client should wait before to retry when the service is overloaded
+pub const DEFAULT_RETRY_AFTER: u64 = 5;
+
+/// The effective retry delay, when overridden by the configuration
+static RETRY_AFTER: OnceLock<u64> = OnceLock::new();
+
+/// Sets the retry delay in seconds, returning false if it has already been set.
+pub fn set_retry_after (seconds: u64) -> bool {
+    RETRY_AFTER.set(seconds).is_ok()
+}
+
+/// Gets the retry delay in seconds.
+pub fn get_retry_after () -> u64 {
+    *RETRY_AFTER.get().unwrap_or(&DEFAULT_RETRY_AFTER)
+}
+
+/// The value of a Retry-After header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RetryAfter {
+    /// A delay in seconds
+    Seconds(u64),
+
+    /// An HTTP-date, e.g. `Wed, 21 Oct 2015 07:28:00 GMT`
+    Date(String),
+}
+
+impl RetryAfter {
+    pub fn to_header_value (&self) -> String {
+        match *self {
+            RetryAfter::Seconds(seconds) => seconds.to_string(),
+            RetryAfter::Date(ref date) => date.clone(),
+        }
+    }
+}
+
+/// A 503 Service Unavailable responder, with a Retry-After header to help clients
+/// to back off when the service sheds load.
+///
+/// The message is serialized as JSON body.
+///
+/// # Examples
+///
+/// ```
+/// #[get("/reports")]
+/// pub fn get_reports(queue: State<ReportsQueue>) -> Result<Json<Vec<Report>>, ServiceUnavailable> {
+///     if queue.is_full() {
+///         return Err(ServiceUnavailable::new("the reports queue is full"));
+///     }
+///
+///     // ...
+/// }
+/// ```
+#[derive(Debug, PartialEq, Eq)]
+pub struct ServiceUnavailable {
+    pub retry_after: RetryAfter,
+    pub message: String,
+}
+
+impl ServiceUnavailable {
+    /// Builds the responder, with the configured retry delay.
+    pub fn new (message: &str) -> Self {
+        Self {
+            retry_after: RetryAfter::Seconds(get_retry_after()),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn with_retry_after (mut self, retry_after: RetryAfter) -> Self {
+        self.retry_after = retry_after;
+
+        self
+    }
+}
+
+impl<'r> Responder<'r> for ServiceUnavailable {
+    fn respond_to(self, request: &Request) -> ResponseResult<'r> {
+        let mut response = Custom(Status::ServiceUnavailable, Json(self.message)).respond_to(request)?;
+        response.set_header(Header::new("Retry-After", self.retry_after.to_header_value()));
+
+        Ok(response)
+    }
+}
+
+/// Builds a catcher answering the 503 failures of the guards, like the pool exhaustion
+/// in `DatabaseConnection`, with a Retry-After header.
+pub fn service_unavailable_catcher () -> Catcher {
+    Catcher::new(503, handle_service_unavailable)
+}
+
+fn handle_service_unavailable<'r> (request: &'r Request) -> ResponseResult<'r> {
+    ServiceUnavailable::new(Status::ServiceUnavailable.reason).respond_to(request)
+}
+
+/*   -------------------------------------------------------------
+     Too many requests
+
+     :: Responder
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A 429 Too Many Requests responder, with the Retry-After header and the
+/// X-RateLimit-Limit, X-RateLimit-Remaining and X-RateLimit-Reset headers,
+/// so clients know when to retry.
+///
+/// The X-RateLimit-* headers can be disabled, as some proxies dislike them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TooManyRequests {
+    pub info: RateLimitInfo,
+    pub message: String,
+    pub rate_limit_headers: bool,
+}
+
+impl TooManyRequests {
+    pub fn new (info: RateLimitInfo) -> Self {
+        Self {
+            info,
+            message: String::from(Status::TooManyRequests.reason),
+            rate_limit_headers: true,
+        }
+    }
+
+    pub fn with_rate_limit_headers (mut self, enabled: bool) -> Self {
+        self.rate_limit_headers = enabled;
+
+        self
+    }
+}
+
+impl<'r> Responder<'r> for TooManyRequests {
+    fn respond_to(self, request: &Request) -> ResponseResult<'r> {
+        let mut response = Custom(Status::TooManyRequests, Json(self.message)).respond_to(request)?;
+        response.set_header(Header::new("Retry-After", self.info.reset.to_string()));
+
+        if self.rate_limit_headers {
+            response.set_header(Header::new("X-RateLimit-Limit", self.info.limit.to_string()));
+            response.set_header(Header::new("X-RateLimit-Remaining", self.info.remaining.to_string()));
+            response.set_header(Header::new("X-RateLimit-Reset", self.info.reset.to_string()));
+        }
+
+        Ok(response)
+    }
+}
+
+/// Builds a catcher answering the 429 failures of the `RateLimited` guard
+/// with the rate limit headers.
+pub fn too_many_requests_catcher () -> Catcher {
+    Catcher::new(429, handle_too_many_requests)
+}
+
+fn handle_too_many_requests<'r> (request: &'r Request) -> ResponseResult<'r> {
+    match request.local_cache(|| None::<TooManyRequests>) {
+        Some(response) => response.clone().respond_to(request),
+        None => Custom(Status::TooManyRequests, Json(Status::TooManyRequests.reason)).respond_to(request),
+    }
+}
+
+/*   -------------------------------------------------------------
+     Partial content
+
+     :: Responder
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A responder sending a range of a content as 206 Partial Content,
+/// or the full content as 200 OK.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PartialContent {
+    pub content: Vec<u8>,
+
+    /// The first and last positions of the range, or None for the full content
+    pub range: Option<(u64, u64)>,
+
+    /// The length of the full content
+    pub length: u64,
+}
+
+impl PartialContent {
+    pub fn full (content: Vec<u8>) -> Self {
+        Self {
+            length: content.len() as u64,
+            content,
+            range: None,
+        }
+    }
+
+    /// Extracts the first requested range from the content.
+    pub fn from_range (content: Vec<u8>, range: &RangeHeader) -> Result<Self, RangeNotSatisfiable> {
+        let length = content.len() as u64;
+        let (start, end) = range.resolve(length).map_err(|_| RangeNotSatisfiable { length })?;
+
+        Ok(Self {
+            content: content[start as usize..=end as usize].to_vec(),
+            range: Some((start, end)),
+            length,
+        })
+    }
+}
+
+impl<'r> Responder<'r> for PartialContent {
+    fn respond_to(self, _request: &Request) -> ResponseResult<'r> {
+        let mut builder = Response::build();
+        builder
+            .raw_header("Accept-Ranges", "bytes")
+            .sized_body(::std::io::Cursor::new(self.content));
+
+        match self.range {
+            Some((start, end)) => builder
+                .status(Status::PartialContent)
+                .raw_header("Content-Range", format!("bytes {}-{}/{}", start, end, self.length)),
+            None => builder.status(Status::Ok),
+        };
+
+        builder.ok()
+    }
+}
+
+/// A 416 Range Not Satisfiable responder, with the length of the content.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RangeNotSatisfiable {
+    pub length: u64,
+}
+
+impl<'r> Responder<'r> for RangeNotSatisfiable {
+    fn respond_to(self, _request: &Request) -> ResponseResult<'r> {
+        Response::build()
+            .status(Status::RangeNotSatisfiable)
+            .raw_header("Content-Range", format!("bytes */{}", self.length))
+            .ok()
+    }
+}
+
+/*   -------------------------------------------------------------
+     File download
+
+     :: Responder
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A responder streaming a file as a download, with a Content-Type guessed
+/// from the extension, and a Content-Disposition header with the file name.
+///
+/// The file is streamed, not loaded in memory. A missing file gives a 404 Not Found.
+///
+/// # Examples
+///
+/// ```
+/// #[get("/reports/<id>/export")]
+/// pub fn export_report(id: i32) -> FileDownload {
+///     let path = generate_report_export(id);
+///
+///     FileDownload::new(path).with_filename(&format!("report-{}.csv", id))
+/// }
+/// ```
+#[derive(Debug, PartialEq, Eq)]
+pub struct FileDownload {
+    pub path: PathBuf,
+
+    /// The file name suggested to the client, or None to use the one of the path
+    pub filename: Option<String>,
+}
+
+impl FileDownload {
+    pub fn new<P: AsRef<Path>> (path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            filename: None,
+        }
+    }
+
+    pub fn with_filename (mut self, filename: &str) -> Self {
+        self.filename = Some(filename.to_string());
+
+        self
+    }
+
+    /// Gets the file name suggested to the client.
+    pub fn get_filename (&self) -> String {
+        match self.filename {
+            Some(ref filename) => filename.clone(),
+            None => self.path
+                .file_name()
+                .map(|filename| filename.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Guesses the content type from the extension, or uses application/octet-stream.
+    pub fn get_content_type (&self) -> ContentType {
+        self.path
+            .extension()
+            .and_then(|extension| ContentType::from_extension(&extension.to_string_lossy()))
+            .unwrap_or(ContentType::Binary)
+    }
+}
+
+/// Builds a Content-Disposition header value for an attachment, quoting the file name.
+///
+/// The control characters are removed, so the file name can't inject headers.
+/// A non-ASCII file name is given as an RFC 5987 `filename*` parameter,
+/// with an ASCII fallback in `filename` for older clients.
+fn build_attachment_disposition (filename: &str) -> String {
+    let filename: String = filename.chars().filter(|c| !c.is_control()).collect();
+
+    let fallback: String = filename
+        .chars()
+        .map(|c| if c.is_ascii() { c } else { '_' })
+        .collect();
+    let fallback = fallback.replace('\\', "\\\\").replace('"', "\\\"");
+
+    if filename.is_ascii() {
+        return format!("attachment; filename=\"{}\"", fallback);
+    }
+
+    format!("attachment; filename=\"{}\"; filename*=UTF-8''{}", fallback, encode_rfc5987_value(&filename))
+}
+
+/// Percent-encodes a value for an RFC 5987 extended parameter, keeping the attr-char characters.
+fn encode_rfc5987_value (value: &str) -> String {
+    value.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9'
+            | b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+impl<'r> Responder<'r> for FileDownload {
+    fn respond_to(self, _request: &Request) -> ResponseResult<'r> {
+        let file = File::open(&self.path).map_err(|error| match error.kind() {
+            ErrorKind::NotFound => Status::NotFound,
+            _ => build_internal_server_error_response(&error.to_string()),
+        })?;
+
+        Response::build()
+            .header(self.get_content_type())
+            .raw_header("Content-Disposition", build_attachment_disposition(&self.get_filename()))
+            .sized_body(file)
+            .ok()
+    }
+}
+
+/*   -------------------------------------------------------------
+     Not found bodies
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The messages of the 404 responses, by path prefix, to be used as a managed state
+/// with `not_found_catcher`.
+///
+/// # Examples
+///
+/// ```
+/// let not_found_bodies = NotFoundBodies::new("Not Found")
+///     .with_prefix("/api", "This API method doesn't exist.")
+///     .with_prefix("/admin", "This administration page doesn't exist.");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NotFoundBodies {
+    default_message: String,
+    prefixes: Vec<(String, String)>,
+}
+
+impl NotFoundBodies {
+    pub fn new (default_message: &str) -> Self {
+        Self {
+            default_message: default_message.to_string(),
+            prefixes: Vec::new(),
+        }
+    }
+
+    /// Sets the message for the paths under a prefix, like a mount point.
+    pub fn with_prefix (mut self, prefix: &str, message: &str) -> Self {
+        self.prefixes.push((prefix.trim_end_matches('/').to_string(), message.to_string()));
+
+        self
+    }
+
+    /// Gets the message for a path, from the longest matching prefix,
+    /// or the default message.
+    pub fn get_message (&self, path: &str) -> &str {
+        self.prefixes
+            .iter()
+            .filter(|&&(ref prefix, _)| {
+                path.starts_with(prefix.as_str())
+                    && (path.len() == prefix.len() || path[prefix.len()..].starts_with('/'))
+            })
+            .max_by_key(|&&(ref prefix, _)| prefix.len())
+            .map(|&(_, ref message)| message.as_str())
+            .unwrap_or(&self.default_message)
+    }
+}
+
+/// Builds a catcher answering the 404 with the message of the managed `NotFoundBodies`
+/// matching the request path, as a JSON body.
+pub fn not_found_catcher () -> Catcher {
+    Catcher::new(404, handle_not_found)
+}
+
+fn handle_not_found<'r> (request: &'r Request) -> ResponseResult<'r> {
+    let message = match request.guard::<State<NotFoundBodies>>() {
+        ::rocket::Outcome::Success(bodies) => bodies.get_message(request.uri().path()).to_string(),
+        _ => String::from(Status::NotFound.reason),
+    };
+
+    Custom(Status::NotFound, Json(message)).respond_to(request)
+}
+
+/*   -------------------------------------------------------------
+     Helper methods to prepare API responses
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+#[deprecated(since="0.6.0", note="Use directly Status::NotFound instead.")]
+pub fn build_not_found_response() -> Status {
+    Status::NotFound
+}
+
+#[deprecated(since="0.6.0", note="Use directly Status::BadRequest instead.")]
+pub fn build_bad_request_response() -> Status {
+    Status::BadRequest
+}
+
+/// Builds a 304 Not Modified response, without body, for conditional requests.
+pub fn build_not_modified_response() -> Response<'static> {
+    build_empty_response(Status::NotModified)
+}
+
+/// Determines if an If-None-Match header value matches the ETag of the current representation,
+/// so a 304 Not Modified response can be sent instead.
+///
+/// The value can be `*` or a list of ETags. As RFC 9110 requires for If-None-Match,
+/// the weak comparison is used: the `W/` prefix is ignored.
+pub fn is_none_match_satisfied(if_none_match: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+
+    if_none_match
+        .split(',')
+        .map(|candidate| candidate.trim())
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+/// Builds a 412 Precondition Failed response, without body, for conditional requests.
+pub fn build_precondition_failed_response() -> Response<'static> {
+    build_empty_response(Status::PreconditionFailed)
+}
+
+/// Builds a 428 Precondition Required response, without body, for conditional requests.
+pub fn build_precondition_required_response() -> Response<'static> {
+    build_empty_response(Status::PreconditionRequired)
+}
+
+/// Builds a response without body.
+///
+/// Contrary to a `Status` used as responder, the catchers aren't called.
+pub fn build_empty_response(status: Status) -> Response<'static> {
+    Response::build()
+        .status(status)
+        .finalize()
+}
+
+pub fn build_internal_server_error_response(message: &str) -> Status {
+    warn!(target:"api", "{}", message);
+
+    Status::InternalServerError
+}
+
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+fn build_query_error_response(error: ResultError) -> Status {
+    match error {
+        // Case II - The query returns no result, we return a 404 Not found response
+        ResultError::NotFound => Status::NotFound,
+
+        // Case III -  We need to handle a database error, which could be a 400/409/500
+        ResultError::DatabaseError(kind, details) => {
+            build_database_error_response(kind, details)
+        }
+
+        // Case IV - The query can't be built or its result can't be read,
+        //           that's probably a bug in the code, log it as such and throw a 500
+        ResultError::QueryBuilderError(_) | ResultError::DeserializationError(_) => {
+            build_internal_server_error_response(&describe_programming_error(&error))
+        }
+
+        // Case V - The error is probably server responsibility, log it and throw a 500
+        _ => error.into_failure_response(),
+    }
+}
+
+/// Describes an error caused by the code rather than by the request or the database state.
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+fn describe_programming_error(error: &ResultError) -> String {
+    match *error {
+        ResultError::QueryBuilderError(ref details) => {
+            format!("The query can't be built, check the code building it: {}", details)
+        }
+
+        ResultError::DeserializationError(ref details) => {
+            format!("The query result can't be deserialized, check the model matches the schema: {}", details)
+        }
+
+        _ => error.to_string(),
+    }
+}
+
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+fn build_database_error_response(error_kind: DatabaseErrorKind, info: Box<dyn DatabaseErrorInformation>) -> Status {
+    match error_kind {
+        // Case IIIa - The query tries to do an INSERT violating an unique constraint
+        //             e.g. two INSERT with the same unique value
+        //             We return a 409 Conflict
+        DatabaseErrorKind::UniqueViolation => Status::Conflict,
 
         // Case IIIb - The query violated a foreign key constraint
         //             e.g. an INSERT referring to a non existing user 1004
@@ -216,3 +1671,719 @@ fn build_database_error_response(error_kind: DatabaseErrorKind, info: Box<dyn Da
         _ => build_internal_server_error_response(info.message()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use logger::CapturingLogger;
+    use rocket::local::Client;
+
+    #[test]
+    fn test_option_when_some () {
+        let response: ApiJsonResponse<u32> = Some(4).into_json_response();
+
+        assert_eq!(4, response.unwrap().into_inner());
+    }
+
+    #[test]
+    fn test_option_when_none () {
+        let value: Option<u32> = None;
+        let response: ApiJsonResponse<u32> = value.into_json_response();
+
+        assert_eq!(Status::NotFound, response.unwrap_err());
+    }
+
+    #[cfg(feature = "serialization")]
+    fn get_json_value_response (value: serde_json::Value) -> (Option<ContentType>, Option<String>) {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.get("/");
+
+        let mut response = value.into_json_response().respond_to(request.inner()).unwrap();
+
+        (response.content_type(), response.body_string())
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_json_value_object () {
+        let (content_type, body) = get_json_value_response(serde_json::json!({ "name": "quux" }));
+
+        assert_eq!(Some(ContentType::JSON), content_type);
+        assert_eq!(Some(String::from(r#"{"name":"quux"}"#)), body);
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_json_value_array () {
+        let (content_type, body) = get_json_value_response(serde_json::json!([1, 2, 3]));
+
+        assert_eq!(Some(ContentType::JSON), content_type);
+        assert_eq!(Some(String::from("[1,2,3]")), body);
+    }
+
+    #[test]
+    #[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+    fn test_optional_query_result_when_found () {
+        let result: QueryResult<Option<u32>> = Ok(Some(4));
+        let response: ApiJsonResponse<u32> = result.into_json_response();
+
+        assert_eq!(4, response.unwrap().into_inner());
+    }
+
+    #[test]
+    #[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+    fn test_optional_query_result_when_not_found () {
+        let result: QueryResult<Option<u32>> = Ok(None);
+        let response: ApiJsonResponse<u32> = result.into_json_response();
+
+        assert_eq!(Status::NotFound, response.unwrap_err());
+    }
+
+    #[test]
+    #[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+    fn test_optional_query_result_when_database_error () {
+        let details = Box::new(String::from("duplicate key value violates unique constraint"));
+        let result: QueryResult<Option<u32>> = Err(ResultError::DatabaseError(DatabaseErrorKind::UniqueViolation, details));
+        let response: ApiJsonResponse<u32> = result.into_json_response();
+
+        assert_eq!(Status::Conflict, response.unwrap_err());
+    }
+
+    #[test]
+    #[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+    fn test_transformed_query_result () {
+        let result: Result<String, ResultError> = Ok(4).map(|level: u32| format!("level {}", level));
+        let response: ApiJsonResponse<String> = result.into_json_response();
+
+        assert_eq!("level 4", response.unwrap().into_inner());
+    }
+
+    #[test]
+    #[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+    fn test_transformed_query_result_when_not_found () {
+        let result: Result<String, ResultError> = Err(ResultError::NotFound).map(|level: u32| format!("level {}", level));
+        let response: ApiJsonResponse<String> = result.into_json_response();
+
+        assert_eq!(Status::NotFound, response.unwrap_err());
+    }
+
+    #[test]
+    #[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+    fn test_query_result_when_query_builder_error () {
+        let result: QueryResult<u32> = Err(ResultError::QueryBuilderError("empty update".into()));
+        let response: ApiJsonResponse<u32> = result.into_json_response();
+
+        assert_eq!(Status::InternalServerError, response.unwrap_err());
+    }
+
+    #[test]
+    #[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+    fn test_query_result_when_deserialization_error () {
+        let result: QueryResult<u32> = Err(ResultError::DeserializationError("unexpected null".into()));
+        let response: ApiJsonResponse<u32> = result.into_json_response();
+
+        assert_eq!(Status::InternalServerError, response.unwrap_err());
+    }
+
+    #[test]
+    #[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+    fn test_describe_programming_error () {
+        let query_builder_error = describe_programming_error(&ResultError::QueryBuilderError("quux".into()));
+        let deserialization_error = describe_programming_error(&ResultError::DeserializationError("quux".into()));
+
+        assert!(query_builder_error.starts_with("The query can't be built"));
+        assert!(deserialization_error.starts_with("The query result can't be deserialized"));
+        assert_ne!(query_builder_error, deserialization_error);
+        assert_ne!(ResultError::RollbackTransaction.to_string(), query_builder_error);
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_text_response () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.get("/status");
+
+        let responses = vec![
+            "ALIVE".into_text_response(),
+            String::from("ALIVE").into_text_response(),
+            Cow::Borrowed("ALIVE").into_text_response(),
+            Cow::<str>::Owned(String::from("ALIVE")).into_text_response(),
+        ];
+
+        for text_response in responses {
+            let mut response = text_response.respond_to(request.inner()).unwrap();
+
+            assert_eq!(Status::Ok, response.status());
+            assert_eq!(Some(ContentType::Plain), response.content_type());
+            assert_eq!(Some(String::from("ALIVE")), response.body_string());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "anyhow")]
+    fn test_describe_internal_error () {
+        let error = anyhow::anyhow!("quux").context("Can't load the players");
+
+        assert_eq!("Can't load the players: quux", describe_internal_error(&error, true));
+        assert_eq!("Internal Server Error", describe_internal_error(&error, false));
+    }
+
+    #[test]
+    #[cfg(feature = "anyhow")]
+    fn test_anyhow_error_into_failure_response () {
+        let result: anyhow::Result<u32> = Err(anyhow::anyhow!("quux"));
+
+        assert_eq!(Status::InternalServerError, result.unwrap_err().into_failure_response());
+    }
+
+    #[test]
+    fn test_api_error_for_client_error () {
+        let logger = CapturingLogger::new();
+        let error = api_error(Status::Forbidden, "no access");
+        error.log_to(&logger);
+
+        assert!(logger.get_records().is_empty(), "4xx errors shouldn't be logged");
+        assert_eq!(Status::Forbidden, error.into_failure_response());
+    }
+
+    #[test]
+    fn test_api_error_for_server_error () {
+        let logger = CapturingLogger::new();
+        let error = api_error(Status::InternalServerError, "database is on fire");
+        error.log_to(&logger);
+
+        assert_eq!(vec!["WARN api: database is on fire"], logger.get_records(), "5xx errors should be logged");
+        assert_eq!("database is on fire", error.message);
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_problem_response () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.get("/player/quux");
+
+        let problem = api_error(Status::NotFound, "No such player.").into_problem();
+        let mut response = problem.respond_to(request.inner()).unwrap();
+
+        assert_eq!(Status::NotFound, response.status());
+        assert_eq!(Some("application/problem+json"), response.headers().get_one("Content-Type"));
+        assert_eq!(
+            serde_json::json!({ "type": "about:blank", "title": "Not Found", "status": 404, "detail": "No such player." }),
+            serde_json::from_str::<serde_json::Value>(&response.body_string().unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_into_problem_response () {
+        let result: Result<u32, ApiError> = Ok(42);
+        assert_eq!(Some(42), result.into_problem_response().ok().map(|json| json.into_inner()));
+
+        let result: Result<u32, ApiError> = Err(ApiError::from(Status::Conflict));
+        let problem = result.into_problem_response().unwrap_err();
+        assert_eq!(409, problem.status);
+        assert_eq!("Conflict", problem.title);
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_validation_failure_response () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.post("/register");
+
+        let failure = ValidationFailure::new().with_error("email", "email");
+        let mut response = failure.respond_to(request.inner()).unwrap();
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+        assert_eq!(
+            Some(String::from(r#"{"errors":[{"field":"email","code":"email"}]}"#)),
+            response.body_string()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "validation")]
+    fn test_validation_failure_from_validator () {
+        use validator::ValidationError;
+
+        let mut errors = ValidationErrors::new();
+        errors.add("name", ValidationError::new("length"));
+        errors.add("email", ValidationError::new("email"));
+
+        let failure = ValidationFailure::from(errors);
+
+        assert_eq!(
+            serde_json::json!({ "errors": [
+                { "field": "email", "code": "email" },
+                { "field": "name", "code": "length" },
+            ] }),
+            serde_json::to_value(&failure).unwrap()
+        );
+        assert_eq!(Status::UnprocessableEntity, failure.into_failure_response());
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_api_error_with_header () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.get("/");
+
+        let error = api_error(Status::ServiceUnavailable, "maintenance in progress")
+            .with_header("Retry-After", "120");
+        let response = error.respond_to(request.inner()).unwrap();
+
+        assert_eq!(Status::ServiceUnavailable, response.status());
+        assert_eq!(Some("120"), response.headers().get_one("Retry-After"));
+    }
+
+    #[test]
+    fn test_retry_after_header_value () {
+        assert_eq!("120", RetryAfter::Seconds(120).to_header_value());
+
+        let date = "Wed, 21 Oct 2015 07:28:00 GMT";
+        assert_eq!(date, RetryAfter::Date(date.to_string()).to_header_value());
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_gone () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.get("/v1/players");
+
+        let mut response = Gone::new()
+            .with_message("The v1 API has been removed.")
+            .with_replacement("/v2/players")
+            .with_deprecation("Mon, 01 Jan 2024 00:00:00 GMT")
+            .with_sunset("Sun, 01 Sep 2024 00:00:00 GMT")
+            .respond_to(request.inner())
+            .unwrap();
+
+        assert_eq!(Status::Gone, response.status());
+        assert_eq!(Some("Mon, 01 Jan 2024 00:00:00 GMT"), response.headers().get_one("Deprecation"));
+        assert_eq!(Some("Sun, 01 Sep 2024 00:00:00 GMT"), response.headers().get_one("Sunset"));
+        assert_eq!(
+            Some(String::from(r#"{"message":"The v1 API has been removed.","replacement":"/v2/players"}"#)),
+            response.body_string()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_gone_without_body () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.get("/v1/players");
+
+        let response = Gone::new().respond_to(request.inner()).unwrap();
+
+        assert_eq!(Status::Gone, response.status());
+        assert_eq!(None, response.headers().get_one("Sunset"));
+        assert!(response.body().is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_service_unavailable () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.get("/");
+
+        let response = ServiceUnavailable::new("the queue is full")
+            .with_retry_after(RetryAfter::Seconds(30))
+            .respond_to(request.inner())
+            .unwrap();
+
+        assert_eq!(Status::ServiceUnavailable, response.status());
+        assert_eq!(Some("30"), response.headers().get_one("Retry-After"));
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_service_unavailable_catcher () {
+        use rocket::{Data, Route};
+        use rocket::handler::Outcome;
+        use rocket::http::Method;
+
+        fn overloaded_handler<'r>(_request: &'r Request, _data: Data) -> Outcome<'r> {
+            Outcome::Failure(Status::ServiceUnavailable)
+        }
+
+        let server = ::rocket::ignite()
+            .mount("/", vec![Route::new(Method::Get, "/reports", overloaded_handler)])
+            .register(vec![service_unavailable_catcher()]);
+        let client = Client::untracked(server).unwrap();
+        let response = client.get("/reports").dispatch();
+
+        assert_eq!(Status::ServiceUnavailable, response.status());
+        assert_eq!(Some(get_retry_after().to_string()), response.headers().get_one("Retry-After").map(String::from));
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_too_many_requests () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.get("/");
+
+        let info = RateLimitInfo { limit: 10, remaining: 0, reset: 6 };
+        let response = TooManyRequests::new(info).respond_to(request.inner()).unwrap();
+
+        assert_eq!(Status::TooManyRequests, response.status());
+        assert_eq!(Some("6"), response.headers().get_one("Retry-After"));
+        assert_eq!(Some("10"), response.headers().get_one("X-RateLimit-Limit"));
+        assert_eq!(Some("0"), response.headers().get_one("X-RateLimit-Remaining"));
+        assert_eq!(Some("6"), response.headers().get_one("X-RateLimit-Reset"));
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_too_many_requests_without_rate_limit_headers () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.get("/");
+
+        let info = RateLimitInfo { limit: 10, remaining: 0, reset: 6 };
+        let response = TooManyRequests::new(info)
+            .with_rate_limit_headers(false)
+            .respond_to(request.inner())
+            .unwrap();
+
+        assert_eq!(Some("6"), response.headers().get_one("Retry-After"));
+        assert!(response.headers().get_one("X-RateLimit-Limit").is_none());
+    }
+
+    #[test]
+    fn test_partial_content () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.get("/");
+
+        let range = RangeHeader::parse("bytes=4-").unwrap();
+        let mut response = PartialContent::from_range(b"quux quux".to_vec(), &range)
+            .unwrap()
+            .respond_to(request.inner())
+            .unwrap();
+
+        assert_eq!(Status::PartialContent, response.status());
+        assert_eq!(Some("bytes 4-8/9"), response.headers().get_one("Content-Range"));
+        assert_eq!(Some(String::from(" quux")), response.body_string());
+    }
+
+    #[test]
+    fn test_partial_content_when_unsatisfiable () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.get("/");
+
+        let range = RangeHeader::parse("bytes=9-").unwrap();
+        let error = PartialContent::from_range(b"quux quux".to_vec(), &range).unwrap_err();
+        let response = error.respond_to(request.inner()).unwrap();
+
+        assert_eq!(Status::RangeNotSatisfiable, response.status());
+        assert_eq!(Some("bytes */9"), response.headers().get_one("Content-Range"));
+    }
+
+    #[test]
+    fn test_not_found_bodies_get_message () {
+        let bodies = NotFoundBodies::new("Not Found")
+            .with_prefix("/api", "No such API method")
+            .with_prefix("/api/admin/", "No such administration method");
+
+        assert_eq!("No such API method", bodies.get_message("/api/players/foo"));
+        assert_eq!("No such administration method", bodies.get_message("/api/admin/users"));
+        assert_eq!("No such API method", bodies.get_message("/api"));
+        assert_eq!("Not Found", bodies.get_message("/apiary"));
+        assert_eq!("Not Found", bodies.get_message("/"));
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_not_found_catcher () {
+        let bodies = NotFoundBodies::new("Not Found")
+            .with_prefix("/api", "No such API method")
+            .with_prefix("/admin", "No such administration page");
+
+        let server = ::rocket::ignite()
+            .manage(bodies)
+            .register(vec![not_found_catcher()]);
+        let client = Client::untracked(server).unwrap();
+
+        let expected_bodies = [
+            ("/api/unknown", r#""No such API method""#),
+            ("/admin/unknown", r#""No such administration page""#),
+            ("/unknown", r#""Not Found""#),
+        ];
+
+        for &(path, expected_body) in expected_bodies.iter() {
+            let mut response = client.get(path).dispatch();
+
+            assert_eq!(Status::NotFound, response.status());
+            assert_eq!(Some(String::from(expected_body)), response.body_string());
+        }
+    }
+
+    #[test]
+    fn test_build_not_modified_response () {
+        let mut response = build_not_modified_response();
+        assert_eq!(Status::NotModified, response.status());
+        assert!(response.body().is_none());
+    }
+
+    #[test]
+    fn test_is_none_match_satisfied () {
+        assert!(is_none_match_satisfied("\"quux\"", "\"quux\""));
+        assert!(is_none_match_satisfied("\"foo\", W/\"quux\"", "\"quux\""));
+        assert!(is_none_match_satisfied("*", "\"quux\""));
+
+        assert!(!is_none_match_satisfied("\"foo\"", "\"quux\""));
+        assert!(!is_none_match_satisfied("quux", "\"quux\""));
+    }
+
+    #[test]
+    fn test_build_precondition_failed_response () {
+        let mut response = build_precondition_failed_response();
+        assert_eq!(Status::PreconditionFailed, response.status());
+        assert!(response.body().is_none());
+    }
+
+    #[test]
+    fn test_build_precondition_required_response () {
+        let mut response = build_precondition_required_response();
+        assert_eq!(Status::PreconditionRequired, response.status());
+        assert!(response.body().is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_envelope_with_scalar () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.get("/");
+
+        let mut response = Envelope::new(42).respond_to(request.inner()).unwrap();
+
+        assert_eq!(Some(ContentType::JSON), response.content_type());
+        assert_eq!(Some(String::from(r#"{"data":42}"#)), response.body_string());
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_envelope_with_object () {
+        let mut data = ::std::collections::BTreeMap::new();
+        data.insert("name", "quux");
+
+        let envelope = Envelope::with_meta(data, serde_json::json!({ "count": 1 }));
+        let value = serde_json::to_value(&envelope).unwrap();
+
+        assert_eq!(serde_json::json!({ "data": { "name": "quux" }, "meta": { "count": 1 } }), value);
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_created () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.post("/players");
+
+        let mut response = Created::new(42, "/players/quux").respond_to(request.inner()).unwrap();
+
+        assert_eq!(Status::Created, response.status());
+        assert_eq!(Some("/players/quux"), response.headers().get_one("Location"));
+        assert_eq!(Some(String::from("42")), response.body_string());
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_upserted_when_created () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.put("/players/quux");
+
+        let mut response = Upserted::new(42, true)
+            .with_location("/players/quux")
+            .respond_to(request.inner())
+            .unwrap();
+
+        assert_eq!(Status::Created, response.status());
+        assert_eq!(Some("/players/quux"), response.headers().get_one("Location"));
+        assert_eq!(Some(String::from("42")), response.body_string());
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_upserted_when_updated () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.put("/players/quux");
+
+        let mut response = Upserted::new(42, false)
+            .with_location("/players/quux")
+            .respond_to(request.inner())
+            .unwrap();
+
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(None, response.headers().get_one("Location"));
+        assert_eq!(Some(String::from("42")), response.body_string());
+    }
+
+    #[test]
+    fn test_file_download () {
+        use rocket::response::Body;
+        use std::io::Read;
+
+        let path = ::std::env::temp_dir().join(format!("limiting-factor-download-{}.csv", ::std::process::id()));
+        ::std::fs::write(&path, "id,name\n1,quux\n").unwrap();
+
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.get("/reports/1/export");
+        let mut response = FileDownload::new(&path)
+            .with_filename("report-1.csv")
+            .respond_to(request.inner())
+            .unwrap();
+
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(Some(ContentType::CSV), response.content_type());
+        assert_eq!(Some("attachment; filename=\"report-1.csv\""), response.headers().get_one("Content-Disposition"));
+
+        match response.body() {
+            Some(Body::Sized(mut body, size)) => {
+                let mut content = String::new();
+                body.read_to_string(&mut content).unwrap();
+
+                assert_eq!(15, size);
+                assert_eq!("id,name\n1,quux\n", content);
+            },
+            _ => panic!("The file should be sent as a sized body"),
+        }
+
+        ::std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_file_download_when_missing () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.get("/reports/1/export");
+
+        let result = FileDownload::new("/nonexistent/report.csv").respond_to(request.inner());
+        assert_eq!(Some(Status::NotFound), result.err());
+    }
+
+    #[test]
+    fn test_build_attachment_disposition () {
+        assert_eq!("attachment; filename=\"a \\\"b\\\".txt\"", build_attachment_disposition("a \"b\".txt"));
+    }
+
+    #[test]
+    fn test_build_attachment_disposition_strips_control_characters () {
+        assert_eq!("attachment; filename=\"aSet-Cookie: x\"", build_attachment_disposition("a\r\nSet-Cookie: x"));
+    }
+
+    #[test]
+    fn test_build_attachment_disposition_non_ascii () {
+        assert_eq!(
+            "attachment; filename=\"r_sum_.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf",
+            build_attachment_disposition("résumé.pdf")
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_preferred_response_minimal () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.put("/players/quux");
+
+        let prefer = Prefer { return_preference: Some(ReturnPreference::Minimal) };
+        let response = PreferredResponse::new(42, &prefer).respond_to(request.inner()).unwrap();
+
+        assert_eq!(Status::NoContent, response.status());
+        assert_eq!(Some("return=minimal"), response.headers().get_one("Preference-Applied"));
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_preferred_response_representation () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.put("/players/quux");
+
+        let prefer = Prefer { return_preference: Some(ReturnPreference::Representation) };
+        let mut response = PreferredResponse::new(42, &prefer).respond_to(request.inner()).unwrap();
+
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(Some("return=representation"), response.headers().get_one("Preference-Applied"));
+        assert_eq!(Some(String::from("42")), response.body_string());
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_preferred_response_without_preference () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.put("/players/quux");
+
+        let mut response = PreferredResponse::new(42, &Prefer::default()).respond_to(request.inner()).unwrap();
+
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(None, response.headers().get_one("Preference-Applied"));
+        assert_eq!(Some(String::from("42")), response.body_string());
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_batch_response () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.post("/players/batch");
+
+        let batch = BatchResponse::new()
+            .with_entry(Status::Ok, &serde_json::json!({ "name": "quux" }))
+            .with_entry(Status::NotFound, &"No such player.");
+        let mut response = batch.respond_to(request.inner()).unwrap();
+
+        assert_eq!(Status::MultiStatus, response.status());
+        assert_eq!(Some(ContentType::JSON), response.content_type());
+
+        let body: serde_json::Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(serde_json::json!([
+            { "status": 200, "body": { "name": "quux" } },
+            { "status": 404, "body": "No such player." },
+        ]), body);
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_gzip_json_with_large_body () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.get("/").header(Header::new("Accept-Encoding", "deflate, gzip;q=0.8"));
+
+        let body = vec!["quux"; GZIP_THRESHOLD];
+        let mut response = GzipJson(body).respond_to(request.inner()).unwrap();
+
+        assert_eq!(Some("gzip"), response.headers().get_one("Content-Encoding"));
+        assert_eq!(Some(ContentType::JSON), response.content_type());
+        assert_eq!(Some(&[0x1f, 0x8b][..]), response.body_bytes().as_ref().map(|bytes| &bytes[..2]));
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_gzip_json_with_small_body () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.get("/").header(Header::new("Accept-Encoding", "gzip"));
+
+        let mut response = GzipJson(vec!["quux"]).respond_to(request.inner()).unwrap();
+
+        assert_eq!(None, response.headers().get_one("Content-Encoding"));
+        assert_eq!(Some(String::from("[\"quux\"]")), response.body_string());
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_gzip_json_when_gzip_not_accepted () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.get("/").header(Header::new("Accept-Encoding", "gzip;q=0"));
+
+        let body = vec!["quux"; GZIP_THRESHOLD];
+        let response = GzipJson(body).respond_to(request.inner()).unwrap();
+
+        assert_eq!(None, response.headers().get_one("Content-Encoding"));
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_gzip_json_when_gzip_refused_but_wildcard_accepted () {
+        let client = Client::untracked(::rocket::ignite()).unwrap();
+        let request = client.get("/").header(Header::new("Accept-Encoding", "gzip;q=0, *"));
+
+        let body = vec!["quux"; GZIP_THRESHOLD];
+        let response = GzipJson(body).respond_to(request.inner()).unwrap();
+
+        assert_eq!(None, response.headers().get_one("Content-Encoding"));
+    }
+}
@@ -0,0 +1,288 @@
+//! # Request ID
+//!
+//! This module provides a fairing to identify each request, and a guard to get the ID.
+//!
+//! The ID is read from the request header if the client or a proxy provides it,
+//! or generated otherwise, then sent back in the same response header.
+//!
+//! The kernel attaches the fairing, which is enabled by the `LF_REQUEST_ID` variable.
+
+use config::EnvironmentConfigurable;
+use ErrorResult;
+use rocket::{Data, Outcome, Request, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Status};
+use rocket::request::{FromRequest, Outcome as RequestOutcome};
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use ulid::Ulid;
+use uuid::Uuid;
+
+/// The default header to read and write the request ID
+pub const DEFAULT_REQUEST_ID_HEADER: &'static str = "X-Request-Id";
+
+/// The maximum length of an inbound request ID, longer ones being truncated
+/// before being sent back and logged
+pub const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// The counter of the requests, shared by the fairings of all the listeners,
+/// so the IDs don't collide when the service listens to several addresses
+static REQUEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/*   -------------------------------------------------------------
+     Request ID generator
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The strategies to generate a request ID when the request doesn't provide one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestIdGenerator {
+    /// A random UUID v4
+    Uuid,
+
+    /// A monotonic counter, starting at 1 when the server starts, shared by all the listeners
+    Counter,
+
+    /// An ULID, sortable by generation time
+    Ulid,
+}
+
+impl RequestIdGenerator {
+    pub fn parse (name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "uuid" | "uuidv4" => Some(RequestIdGenerator::Uuid),
+            "counter" => Some(RequestIdGenerator::Counter),
+            "ulid" => Some(RequestIdGenerator::Ulid),
+            _ => None,
+        }
+    }
+}
+
+/*   -------------------------------------------------------------
+     Request ID fairing
+
+     :: Fairing
+     :: EnvironmentConfigurable
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A fairing to identify each request, reusing the inbound ID when present, when enabled.
+pub struct RequestIdFairing {
+    enabled: bool,
+    header: String,
+    generator: RequestIdGenerator,
+}
+
+impl RequestIdFairing {
+    pub fn new (header: &str, generator: RequestIdGenerator) -> Self {
+        Self {
+            enabled: true,
+            header: header.to_string(),
+            generator,
+        }
+    }
+
+    /// Allows to disable the fairing, so the requests aren't identified.
+    pub fn with_enabled (mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+
+        self
+    }
+
+    pub fn is_enabled (&self) -> bool {
+        self.enabled
+    }
+
+    pub fn get_header (&self) -> &str {
+        &self.header
+    }
+
+    /// Generates a new request ID according the configured strategy.
+    pub fn generate (&self) -> String {
+        match self.generator {
+            RequestIdGenerator::Uuid => Uuid::new_v4().to_string(),
+            RequestIdGenerator::Counter => {
+                (REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed) + 1).to_string()
+            },
+            RequestIdGenerator::Ulid => Ulid::new().to_string(),
+        }
+    }
+}
+
+impl Default for RequestIdFairing {
+    fn default () -> Self {
+        Self::new(DEFAULT_REQUEST_ID_HEADER, RequestIdGenerator::Uuid)
+    }
+}
+
+impl Fairing for RequestIdFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request ID",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    fn on_request(&self, request: &mut Request, _data: &Data) {
+        if !self.enabled {
+            return;
+        }
+
+        let id = request.headers()
+            .get_one(&self.header)
+            .and_then(read_inbound_id)
+            .unwrap_or_else(|| self.generate());
+
+        request.local_cache(|| RequestId(id));
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        let id = request.local_cache(|| RequestId(String::new()));
+
+        if !id.0.is_empty() {
+            response.set_header(Header::new(self.header.clone(), id.0.clone()));
+        }
+    }
+}
+
+/// Reads the inbound request ID, truncated to `MAX_REQUEST_ID_LENGTH` characters,
+/// or returns None if it's empty.
+fn read_inbound_id (id: &str) -> Option<String> {
+    if id.is_empty() {
+        return None;
+    }
+
+    Some(id.chars().take(MAX_REQUEST_ID_LENGTH).collect())
+}
+
+/// Configures the fairing from the following environment variables:
+///
+///   - `LF_REQUEST_ID` (facultative): if set, each request is identified by an ID
+///   - `LF_REQUEST_ID_HEADER` (facultative, by default `X-Request-Id`): the header to use
+///   - `LF_REQUEST_ID_GENERATOR` (facultative, by default `uuid`): the strategy to generate
+///     an ID, `uuid`, `counter` or `ulid`
+impl EnvironmentConfigurable for RequestIdFairing {
+    fn parse_environment() -> ErrorResult<Self> {
+        let header = env::var("LF_REQUEST_ID_HEADER")
+            .unwrap_or_else(|_| String::from(DEFAULT_REQUEST_ID_HEADER));
+
+        let generator = match env::var("LF_REQUEST_ID_GENERATOR") {
+            Ok(variable) => {
+                RequestIdGenerator::parse(&variable).unwrap_or_else(|| {
+                    warn!(target: "config", "The LF_REQUEST_ID_GENERATOR variable must be uuid, counter or ulid.");
+
+                    RequestIdGenerator::Uuid
+                })
+            },
+            Err(_) => RequestIdGenerator::Uuid,
+        };
+
+        Ok(Self::new(&header, generator).with_enabled(env::var("LF_REQUEST_ID").is_ok()))
+    }
+}
+
+/*   -------------------------------------------------------------
+     Request ID guard
+
+     :: FromRequest
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The ID of the current request, as set by the `RequestIdFairing`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl<'a, 'r> FromRequest<'a, 'r> for RequestId {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> RequestOutcome<Self, Self::Error> {
+        let id = request.local_cache(|| RequestId(String::new()));
+
+        if id.0.is_empty() {
+            warn!(target: "request", "No request ID is available, the RequestIdFairing should be attached and enabled.");
+
+            return Outcome::Failure((Status::InternalServerError, ()));
+        }
+
+        Outcome::Success(id.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::Client;
+
+    fn build_client (fairing: RequestIdFairing) -> Client {
+        Client::untracked(::rocket::ignite().attach(fairing)).unwrap()
+    }
+
+    #[test]
+    fn test_custom_header () {
+        let client = build_client(RequestIdFairing::new("X-Correlation-Id", RequestIdGenerator::Uuid));
+        let response = client.get("/").dispatch();
+
+        assert!(response.headers().get_one("X-Correlation-Id").is_some());
+        assert!(response.headers().get_one(DEFAULT_REQUEST_ID_HEADER).is_none());
+    }
+
+    #[test]
+    fn test_inbound_id_is_reused () {
+        let client = build_client(RequestIdFairing::default());
+        let response = client.get("/")
+            .header(Header::new(DEFAULT_REQUEST_ID_HEADER, "quux"))
+            .dispatch();
+
+        assert_eq!(Some("quux"), response.headers().get_one(DEFAULT_REQUEST_ID_HEADER));
+    }
+
+    #[test]
+    fn test_inbound_id_is_truncated () {
+        let client = build_client(RequestIdFairing::default());
+        let response = client.get("/")
+            .header(Header::new(DEFAULT_REQUEST_ID_HEADER, "q".repeat(1000)))
+            .dispatch();
+
+        let id = response.headers().get_one(DEFAULT_REQUEST_ID_HEADER).unwrap();
+        assert_eq!(MAX_REQUEST_ID_LENGTH, id.len());
+    }
+
+    #[test]
+    fn test_read_inbound_id () {
+        assert_eq!(Some(String::from("quux")), read_inbound_id("quux"));
+        assert_eq!(Some("é".repeat(MAX_REQUEST_ID_LENGTH)), read_inbound_id(&"é".repeat(200)));
+        assert_eq!(None, read_inbound_id(""));
+    }
+
+    #[test]
+    fn test_disabled_fairing () {
+        let client = build_client(RequestIdFairing::default().with_enabled(false));
+        let response = client.get("/")
+            .header(Header::new(DEFAULT_REQUEST_ID_HEADER, "quux"))
+            .dispatch();
+
+        assert!(response.headers().get_one(DEFAULT_REQUEST_ID_HEADER).is_none());
+    }
+
+    #[test]
+    fn test_generators_produce_distinct_ids () {
+        for generator in &[RequestIdGenerator::Uuid, RequestIdGenerator::Counter, RequestIdGenerator::Ulid] {
+            let fairing = RequestIdFairing::new(DEFAULT_REQUEST_ID_HEADER, *generator);
+
+            assert_ne!(fairing.generate(), fairing.generate(), "{:?} generated the same ID twice", generator);
+        }
+    }
+
+    #[test]
+    fn test_counter_is_shared_by_listeners () {
+        let first_listener = RequestIdFairing::new(DEFAULT_REQUEST_ID_HEADER, RequestIdGenerator::Counter);
+        let second_listener = RequestIdFairing::new(DEFAULT_REQUEST_ID_HEADER, RequestIdGenerator::Counter);
+
+        assert_ne!(first_listener.generate(), second_listener.generate());
+    }
+
+    #[test]
+    fn test_parse_generator () {
+        assert_eq!(Some(RequestIdGenerator::Uuid), RequestIdGenerator::parse("UUIDv4"));
+        assert_eq!(Some(RequestIdGenerator::Counter), RequestIdGenerator::parse("counter"));
+        assert_eq!(Some(RequestIdGenerator::Ulid), RequestIdGenerator::parse("ulid"));
+        assert_eq!(None, RequestIdGenerator::parse("sequence"));
+    }
+}
@@ -0,0 +1,75 @@
+//! # Build information
+//!
+//! This module exposes the version of the library, and details about the build
+//! injected at compile time, to help support and debugging.
+//!
+//! The following environment variables are read when the crate is compiled:
+//!
+//!   - `LF_BUILD_GIT_SHA` (facultative): the commit the library has been built from
+//!   - `LF_BUILD_TIMESTAMP` (facultative): when the library has been built
+//!
+//! For example: `LF_BUILD_GIT_SHA=$(git rev-parse HEAD) cargo build`
+
+#[cfg(feature = "serialization")]
+use serde::Serialize;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// The version of the library
+pub const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+
+/// Information about the build of the library.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_sha: Option<&'static str>,
+    pub build_timestamp: Option<&'static str>,
+}
+
+impl BuildInfo {
+    /// Gets the information injected when this library has been compiled.
+    pub fn current () -> Self {
+        Self {
+            version: VERSION,
+            git_sha: option_env!("LF_BUILD_GIT_SHA"),
+            build_timestamp: option_env!("LF_BUILD_TIMESTAMP"),
+        }
+    }
+}
+
+impl Display for BuildInfo {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "limiting-factor {}", self.version)?;
+
+        if let Some(git_sha) = self.git_sha {
+            write!(f, ", commit {}", git_sha)?;
+        }
+
+        if let Some(build_timestamp) = self.build_timestamp {
+            write!(f, ", built {}", build_timestamp)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_matches_package_version () {
+        assert_eq!(env!("CARGO_PKG_VERSION"), BuildInfo::current().version);
+    }
+
+    #[test]
+    fn test_display () {
+        let build_info = BuildInfo {
+            version: "1.2.3",
+            git_sha: Some("abc123"),
+            build_timestamp: None,
+        };
+
+        assert_eq!("limiting-factor 1.2.3, commit abc123", build_info.to_string());
+    }
+}
@@ -7,18 +7,66 @@
 //! It also provides a `DefaultConfig` implementation of this `Config` trait to
 //! extract variables from an .env file or environment.
 
-use dotenv::dotenv;
-#[cfg(feature = "pgsql")]
+#[cfg(feature = "jwt")]
+use api::jwt::{set_jwt_verifier, JwtVerifier};
+use api::guards::{get_request_body_limit, set_content_type_body_limits, set_request_body_limit, ContentTypeBodyLimits, REQUEST_BODY_LIMIT};
+#[cfg(feature = "cursor-signing")]
+use api::pagination::set_cursor_secret;
+use api::replies::{set_retry_after, set_verbose_errors, DEFAULT_RETRY_AFTER};
+use dotenv::Error as DotenvError;
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+use database::{set_check_max_connections, set_connect_retry, set_log_queries, set_slow_acquire_threshold, ConnectRetry, PoolOptions, DEFAULT_CONNECT_RETRY, DEFAULT_SLOW_ACQUIRE_THRESHOLD};
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
 use kernel::DefaultService;
 use kernel::{MinimalService, Service};
+use log::Level;
+use logger::set_service_name;
+use rocket::Outcome;
+use rocket::Request;
 use rocket::Route;
+use rocket::State;
+use rocket::request::{FromRequest, Outcome as RequestOutcome};
+#[cfg(feature = "serialization")]
+use serde::Serialize;
+use std::collections::HashMap;
 use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+use std::time::Duration;
 use ErrorResult;
 
 /*   -------------------------------------------------------------
      Config trait
      - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
 
+/// The service name used when `LF_SERVICE_NAME` isn't set and the executable name can't be read
+pub const DEFAULT_SERVICE_NAME: &'static str = "service";
+
+/// The name of the running executable, read once as it doesn't change
+static EXECUTABLE_NAME: OnceLock<Option<String>> = OnceLock::new();
+
+/// Gets the default service name: the name of the running executable,
+/// as cargo names the binaries after their package, else `DEFAULT_SERVICE_NAME`.
+///
+/// The `env!("CARGO_PKG_NAME")` macro can't be used here, as it would give the name
+/// of this library, not the one of the application.
+pub fn get_default_service_name () -> &'static str {
+    EXECUTABLE_NAME
+        .get_or_init(|| env::current_exe().ok().and_then(|path| get_executable_name(&path).map(String::from)))
+        .as_deref()
+        .unwrap_or(DEFAULT_SERVICE_NAME)
+}
+
+/// Gets the name of an executable from its path, without extension.
+fn get_executable_name (path: &Path) -> Option<&str> {
+    path.file_stem().and_then(|name| name.to_str())
+}
+
 /// This trait allows to provide a configuration for the resources needed by the API.
 pub trait Config {
     fn get_database_url(&self) -> &str;
@@ -26,6 +74,130 @@ pub trait Config {
     fn get_database_pool_size(&self) -> u32;
     fn with_database(&self) -> bool;
     fn into_service(self, routes: Vec<Route>) -> Box<dyn Service>;
+
+    /// Gets the name of the service, as printed when the server starts,
+    /// added to the structured logs and sent to PostgreSQL as `application_name`.
+    fn get_service_name(&self) -> &str { get_default_service_name() }
+
+    /// Gets the API version to prefix the entry point with, e.g. `v1`.
+    fn get_api_version(&self) -> Option<&str> { None }
+
+    /// Gets the entry point prefixed by the API version, e.g. `/v1` or `/api/v1`,
+    /// where the routes are mounted.
+    fn get_versioned_entry_point(&self) -> String {
+        build_versioned_entry_point(self.get_entry_point(), self.get_api_version())
+    }
+
+    /// Gets the addresses the server should listen to.
+    ///
+    /// When empty, the address and port of the Rocket configuration are used.
+    fn get_listen_targets(&self) -> &[SocketAddr] { &[] }
+
+    /// Gets the address the server should listen to, when there isn't any listen target.
+    ///
+    /// When None, the address of the Rocket configuration is used.
+    fn get_address(&self) -> Option<IpAddr> { None }
+
+    /// Gets the port the server should listen to, when there isn't any listen target.
+    ///
+    /// When None, the port of the Rocket configuration is used.
+    fn get_port(&self) -> Option<u16> { None }
+
+    /// Gets the path of a health route to mount, answering 200 ALIVE to GET requests,
+    /// or None not to mount it.
+    fn get_health_endpoint(&self) -> Option<&str> { None }
+
+    /// Gets the certificate and private key to serve HTTPS, needing the `tls` feature.
+    ///
+    /// When None, the TLS configuration of Rocket is used, if any.
+    fn get_tls_paths(&self) -> Option<&TlsPaths> { None }
+
+    /// Determines if the pending database migrations should be applied at startup,
+    /// needing the `migrations` feature.
+    fn should_run_migrations(&self) -> bool { false }
+
+    /// Gets an extra setting, read from a `LF_EXTRA_<KEY>` variable,
+    /// for the settings specific to the service.
+    fn get_extra(&self, _key: &str) -> Option<&str> { None }
+
+    /// Gets an extra setting as an unsigned integer.
+    ///
+    /// Returns None if the setting is missing or isn't an unsigned integer.
+    fn get_extra_u32(&self, key: &str) -> Option<u32> {
+        let value = self.get_extra(key)?;
+
+        match value.parse::<u32>() {
+            Ok(value) => Some(value),
+            Err(_) => {
+                warn!(target: "config", "The LF_EXTRA_{} variable must be an unsigned integer.", key);
+
+                None
+            },
+        }
+    }
+
+    /// Gets an extra setting as a boolean, accepting 1/0, true/false, yes/no and on/off.
+    ///
+    /// Returns None if the setting is missing or isn't a boolean.
+    fn get_extra_bool(&self, key: &str) -> Option<bool> {
+        let value = self.get_extra(key)?;
+
+        match parse_bool(value) {
+            Some(value) => Some(value),
+            None => {
+                warn!(target: "config", "The LF_EXTRA_{} variable must be a boolean.", key);
+
+                None
+            },
+        }
+    }
+
+    /// Resolves the settings to expose to the request handlers.
+    fn resolve(&self) -> ResolvedConfig {
+        ResolvedConfig {
+            entry_point: self.get_entry_point().to_string(),
+            with_database: self.with_database(),
+            database_pool_size: self.get_database_pool_size(),
+            request_body_limit: get_request_body_limit(),
+        }
+    }
+}
+
+/*   -------------------------------------------------------------
+     ResolvedConfig
+
+     :: FromRequest
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The settings resolved at startup, registered by the kernel as a managed state,
+/// so request handlers don't need to read the environment again.
+///
+/// The database URL isn't exposed, as it could contain credentials.
+///
+/// # Examples
+///
+/// ```
+/// #[get("/entry-point")]
+/// pub fn get_entry_point(config: ResolvedConfig) -> String {
+///     config.entry_point
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResolvedConfig {
+    pub entry_point: String,
+    pub with_database: bool,
+    pub database_pool_size: u32,
+    pub request_body_limit: u64,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for ResolvedConfig {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> RequestOutcome<Self, Self::Error> {
+        let config = request.guard::<State<ResolvedConfig>>()?;
+
+        Outcome::Success(config.clone())
+    }
 }
 
 /*   -------------------------------------------------------------
@@ -47,57 +219,207 @@ pub trait EnvironmentConfigurable {
 /// This is a default implementation of the `Config` trait, which extracts  the following variables
 /// from an .env file or environment:
 ///
+///   - `LF_SERVICE_NAME` (facultative, by default the name of the executable): the name of the service,
+///     for the startup banner, the structured logs and the PostgreSQL `application_name`
 ///   - `API_ENTRY_POINT` (facultative, by default `/`): the mounting point of the API methods
-///   - `DATABASE_URL` (mandatory): the URL to connect to your database
+///   - `API_VERSION` (facultative): a version to prefix the mounting point with, e.g. `v1`
+///   - `DATABASE_URL` (mandatory): the URL to connect to your database, or a secret reference
+///     like `secret+file:/run/secrets/database_url` resolved by `resolve_secret`; with the `mysql`
+///     feature, a `mysql://` URL uses a MySQL pool instead of a PostgreSQL one; with the `sqlite`
+///     feature, a `sqlite://` URL, a `file:` URI or `:memory:` uses a SQLite pool, of one connection
+///     for in-memory databases
+///   - `TEST_DATABASE_URL` (facultative, mandatory in test mode): the URL to use instead
+///     of `DATABASE_URL` when the `LF_TEST_MODE` variable is set, to run the tests against
+///     another database
 ///   - `DATABASE_POOL_SIZE` (facultative, by default 4): the number of connections to open
-#[cfg(feature = "pgsql")]
+///   - `DATABASE_POOL_SIZE_<NAME>` (facultative, by default `DATABASE_POOL_SIZE`): the number
+///     of connections to open for a named pool, see `get_named_database_pool_size`
+///   - `DATABASE_POOL_TIMEOUT` (facultative, by default 30): the time in seconds to wait for
+///     a connection from the pool, before failing the request with a 503
+///   - `DATABASE_POOL_MIN_IDLE` (facultative, by default `DATABASE_POOL_SIZE`): the number
+///     of idle connections to maintain in the pool, up to `DATABASE_POOL_SIZE`
+///   - `DATABASE_POOL_TEST_ON_CHECKOUT` (facultative, by default true): whether to check
+///     a connection is alive before handing it to a request
+///   - `DATABASE_CONNECT_RETRIES` (facultative, by default 0): the number of times to retry
+///     the connection to the database at startup, e.g. when both are started together
+///   - `DATABASE_CONNECT_DELAY_MS` (facultative, by default 500): the delay before retrying
+///     the connection, doubled after each failed attempt
+///   - `DATABASE_SLOW_ACQUIRE_MS` (facultative, by default 500): the duration from which getting
+///     a connection from the pool is logged as slow
+///   - `DATABASE_LOG_QUERIES` (facultative): if set, the queries run through the pooled
+///     connections are logged at debug level
+///   - `DATABASE_CHECK_MAX_CONNECTIONS` (facultative): if set, warns at startup when the pool size
+///     is a large fraction of the max_connections setting of the server
+///   - `LF_RUN_MIGRATIONS` (facultative): if set, the pending migrations embedded in the application
+///     are applied at startup, with the `migrations` feature
+///   - `LF_REQUEST_BODY_LIMIT` (facultative, by default 1000000): the maximum size of a request body
+///   - `LF_REQUEST_BODY_LIMITS` (facultative): the maximum size of a request body by content type,
+///     e.g. `application/json=1000000,application/octet-stream=50000000`
+///   - `LF_CURSOR_SECRET` (facultative): a secret to sign the pagination cursors with,
+///     or a secret reference resolved by `resolve_secret`, with the `cursor-signing` feature
+///   - `LF_JWT_SECRET` or `LF_JWT_PUBLIC_KEY` (facultative): the HS256 secret or the PEM-encoded
+///     RS256 public key to verify the bearer tokens with, or secret references, with the `jwt` feature
+///   - `LF_RETRY_AFTER` (facultative, by default 5): the delay in seconds sent in the Retry-After
+///     header of 503 responses
+///   - `LF_VERBOSE_ERRORS` (facultative): if set, the details of the internal errors
+///     are sent to the clients, for development
+///   - `APP_LISTEN` (facultative): a comma-separated list of addresses to listen to,
+///     e.g. `127.0.0.1:8000,[::1]:8000`; when set, `APP_ADDRESS` and `APP_PORT` are ignored
+///   - `APP_ADDRESS` (facultative): the address to listen to, an IP or a hostname
+///   - `APP_PORT` (facultative): the port to listen to, or `random` to pick a free port
+///   - `APP_HEALTH_ENDPOINT` (facultative, by default `/status`): the path of a health route
+///     answering ALIVE, not mounted if empty or if a GET route is already mounted there
+///   - `APP_TLS_CERT` and `APP_TLS_KEY` (facultative): the paths to the PEM certificate chain
+///     and private key to serve HTTPS, both needed, with the `tls` feature
+///   - `LF_EXTRA_<KEY>` (facultative): extra settings for the service, see `get_extra`
+///
+/// The .env file isn't read if the `LF_SKIP_DOTENV` variable is set.
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
 pub struct DefaultConfig {
+    service_name: String,
     database_url: String,
     entry_point: String,
+    api_version: Option<String>,
     database_pool_size: u32,
+    database_pool_options: PoolOptions,
     with_database: bool,
+    run_migrations: bool,
+    listen_targets: Vec<SocketAddr>,
+    address: Option<IpAddr>,
+    port: Option<u16>,
+    health_endpoint: Option<String>,
+    tls_paths: Option<TlsPaths>,
+    extra: HashMap<String, String>,
 }
 
-#[cfg(feature = "pgsql")]
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
 impl DefaultConfig {
     const DEFAULT_DATABASE_POOL_SIZE: u32 = 4;
+
+    /// Gets the r2d2 settings of the pool, beyond its size.
+    pub fn get_database_pool_options(&self) -> PoolOptions { self.database_pool_options }
+
+    /// Consumes the configuration into a service, like `into_service`, customizing
+    /// the default service with a closure, e.g. to give it the embedded migrations.
+    ///
+    /// When the database is disabled, the minimal service is used and the closure isn't called.
+    pub fn into_service_with<F> (self, routes: Vec<Route>, customize: F) -> Box<dyn Service>
+        where F: FnOnce(DefaultService) -> DefaultService
+    {
+        if !self.with_database {
+            info!(target: "config", "The database is disabled, the minimal service will be used.");
+
+            return MinimalConfig::from(self).into_service(routes);
+        }
+
+        let service = DefaultService {
+            config: self,
+            routes,
+            #[cfg(feature = "pgsql")]
+            pool: None,
+            #[cfg(feature = "migrations")]
+            migrations: None,
+        };
+
+        Box::new(customize(service))
+    }
 }
 
-#[cfg(feature = "pgsql")]
+/// Builds a configuration for the tests of the other modules.
+#[cfg(all(test, any(feature = "pgsql", feature = "mysql", feature = "sqlite")))]
+impl DefaultConfig {
+    pub fn for_tests (database_url: &str, entry_point: &str, with_database: bool) -> Self {
+        DefaultConfig {
+            service_name: String::from("quux"),
+            database_url: String::from(database_url),
+            entry_point: String::from(entry_point),
+            api_version: None,
+            database_pool_size: DefaultConfig::DEFAULT_DATABASE_POOL_SIZE,
+            database_pool_options: PoolOptions::default(),
+            with_database,
+            run_migrations: false,
+            listen_targets: Vec::new(),
+            address: None,
+            port: None,
+            health_endpoint: None,
+            tls_paths: None,
+            extra: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
 impl Config for DefaultConfig {
+    fn get_service_name(&self) -> &str { &self.service_name }
+
     fn get_database_url(&self) -> &str { &self.database_url }
 
     fn get_entry_point(&self) -> &str { &self.entry_point }
 
+    fn get_api_version(&self) -> Option<&str> { self.api_version.as_ref().map(|version| version.as_str()) }
+
     fn get_database_pool_size(&self) -> u32 { self.database_pool_size }
 
     fn with_database(&self) -> bool { self.with_database }
 
+    fn should_run_migrations(&self) -> bool { self.run_migrations }
+
+    fn get_listen_targets(&self) -> &[SocketAddr] { &self.listen_targets }
+
+    fn get_address(&self) -> Option<IpAddr> { self.address }
+
+    fn get_port(&self) -> Option<u16> { self.port }
+
+    fn get_health_endpoint(&self) -> Option<&str> { self.health_endpoint.as_ref().map(|path| path.as_str()) }
+
+    fn get_tls_paths(&self) -> Option<&TlsPaths> { self.tls_paths.as_ref() }
+
+    fn get_extra(&self, key: &str) -> Option<&str> { self.extra.get(key).map(|value| value.as_str()) }
+
+    /// Consumes the configuration into a service.
+    ///
+    /// When the database is disabled, a minimal service is used instead, so no pool
+    /// is initialized and no connection to the database is tested.
     fn into_service(self, routes: Vec<Route>) -> Box<dyn Service> {
-        let service = DefaultService {
-            config: self,
-            routes,
-        };
+        self.into_service_with(routes, |service| service)
+    }
+}
 
-        Box::new(service)
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+impl From<DefaultConfig> for MinimalConfig {
+    fn from(config: DefaultConfig) -> Self {
+        MinimalConfig {
+            service_name: config.service_name,
+            entry_point: config.entry_point,
+            api_version: config.api_version,
+            listen_targets: config.listen_targets,
+            address: config.address,
+            port: config.port,
+            health_endpoint: config.health_endpoint,
+            tls_paths: config.tls_paths,
+            extra: config.extra,
+        }
     }
 }
 
-#[cfg(feature = "pgsql")]
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
 impl EnvironmentConfigurable for DefaultConfig {
     fn parse_environment() -> ErrorResult<Self> {
-        if let Err(error) = dotenv() {
-            warn!(target: "config", "Can't parse .env: {}", error);
-        };
+        load_dotenv();
 
+        let service_name = initialize_service_name();
         let with_database = env::var("LF_DISABLE_DATABASE").is_err();
 
-        let database_url = match env::var("DATABASE_URL") {
-            Ok(url) => url,
-            Err(e) => {
+        let database_url = match read_database_url() {
+            Ok(url) => resolve_secret(&url).map_err(|error| {
+                error!(target: "config", "The DATABASE_URL variable can't be resolved: {}", error);
+
+                error
+            })?,
+            Err(message) => {
                 if with_database {
-                    error!(target: "config", "You need to specify a DATABASE_URL variable in the environment (or .env file).");
-                    return Err(Box::new(e));
+                    error!(target: "config", "{}", message);
+                    return Err(message.into());
                 }
 
                 String::new()
@@ -107,25 +429,69 @@ impl EnvironmentConfigurable for DefaultConfig {
         let entry_point = env::var("API_ENTRY_POINT")
             .unwrap_or_else(|_| String::from("/"));
 
-        let database_pool_size = match env::var("DATABASE_POOL_SIZE") {
+        let api_version = env::var("API_VERSION")
+            .ok()
+            .filter(|version| !version.is_empty());
+
+        let database_pool_size = read_pool_size_variable("DATABASE_POOL_SIZE")
+            .unwrap_or(DefaultConfig::DEFAULT_DATABASE_POOL_SIZE);
+
+        let database_pool_options = PoolOptions {
+            connection_timeout: read_pool_timeout_variable("DATABASE_POOL_TIMEOUT"),
+            min_idle: clamp_pool_min_idle(read_pool_size_variable("DATABASE_POOL_MIN_IDLE"), database_pool_size),
+            test_on_check_out: read_bool_variable("DATABASE_POOL_TEST_ON_CHECKOUT"),
+        };
+
+        let slow_acquire_threshold = match env::var("DATABASE_SLOW_ACQUIRE_MS") {
             Ok(variable) => {
-                match variable.parse::<u32>() {
-                    Ok(size) => size,
+                match variable.parse::<u64>() {
+                    Ok(threshold) => threshold,
                     Err(_) => {
-                        warn!(target: "config", "The DATABASE_POOL_SIZE variable must be an unsigned integer.");
+                        warn!(target: "config", "The DATABASE_SLOW_ACQUIRE_MS variable must be an unsigned integer.");
 
-                        DefaultConfig::DEFAULT_DATABASE_POOL_SIZE
+                        DEFAULT_SLOW_ACQUIRE_THRESHOLD
                     },
                 }
             },
-            Err(_) => DefaultConfig::DEFAULT_DATABASE_POOL_SIZE,
+            Err(_) => DEFAULT_SLOW_ACQUIRE_THRESHOLD,
         };
+        set_slow_acquire_threshold(slow_acquire_threshold);
+
+        set_log_queries(env::var("DATABASE_LOG_QUERIES").is_ok());
+        set_check_max_connections(env::var("DATABASE_CHECK_MAX_CONNECTIONS").is_ok());
+        set_connect_retry(read_connect_retry());
+
+        let run_migrations = env::var("LF_RUN_MIGRATIONS").is_ok();
+
+        initialize_request_body_limit();
+        initialize_retry_after();
+        initialize_verbose_errors();
+        initialize_cursor_secret()?;
+        #[cfg(feature = "jwt")]
+        initialize_jwt_verifier()?;
+
+        let listen_targets = read_listen_targets()?;
+        let address = read_address()?;
+        let port = read_port()?;
+        let health_endpoint = read_health_endpoint();
+        let tls_paths = read_tls_paths()?;
+        let extra = parse_extra_settings(filter_utf8_variables(env::vars_os()));
 
         Ok(DefaultConfig {
+            service_name,
             database_url,
             entry_point,
+            api_version,
             database_pool_size,
+            database_pool_options,
             with_database,
+            run_migrations,
+            listen_targets,
+            address,
+            port,
+            health_endpoint,
+            tls_paths,
+            extra,
         })
     }
 }
@@ -140,14 +506,69 @@ impl EnvironmentConfigurable for DefaultConfig {
 /// This is a minimal implementation of the `Config` trait, which extracts the following variables
 /// from an .env file or environment:
 ///
+///   - `LF_SERVICE_NAME` (facultative, by default the name of the executable): the name of the service,
+///     for the startup banner, the structured logs and the PostgreSQL `application_name`
 ///   - `API_ENTRY_POINT` (facultative, by default `/`): the mounting point of the API methods
+///   - `API_VERSION` (facultative): a version to prefix the mounting point with, e.g. `v1`
+///   - `LF_REQUEST_BODY_LIMIT` (facultative, by default 1000000): the maximum size of a request body
+///   - `LF_REQUEST_BODY_LIMITS` (facultative): the maximum size of a request body by content type,
+///     e.g. `application/json=1000000,application/octet-stream=50000000`
+///   - `LF_CURSOR_SECRET` (facultative): a secret to sign the pagination cursors with,
+///     or a secret reference resolved by `resolve_secret`, with the `cursor-signing` feature
+///   - `LF_JWT_SECRET` or `LF_JWT_PUBLIC_KEY` (facultative): the HS256 secret or the PEM-encoded
+///     RS256 public key to verify the bearer tokens with, or secret references, with the `jwt` feature
+///   - `LF_RETRY_AFTER` (facultative, by default 5): the delay in seconds sent in the Retry-After
+///     header of 503 responses
+///   - `LF_VERBOSE_ERRORS` (facultative): if set, the details of the internal errors
+///     are sent to the clients, for development
+///   - `APP_LISTEN` (facultative): a comma-separated list of addresses to listen to;
+///     when set, `APP_ADDRESS` and `APP_PORT` are ignored
+///   - `APP_ADDRESS` (facultative): the address to listen to, an IP or a hostname
+///   - `APP_PORT` (facultative): the port to listen to, or `random` to pick a free port
+///   - `APP_HEALTH_ENDPOINT` (facultative, by default `/status`): the path of a health route
+///     answering ALIVE, not mounted if empty or if a GET route is already mounted there
+///   - `APP_TLS_CERT` and `APP_TLS_KEY` (facultative): the paths to the PEM certificate chain
+///     and private key to serve HTTPS, both needed, with the `tls` feature
+///   - `LF_EXTRA_<KEY>` (facultative): extra settings for the service, see `get_extra`
+///
+///  The .env file isn't read if the `LF_SKIP_DOTENV` variable is set.
 ///
 ///  It sets the server not to use a database.
 pub struct MinimalConfig {
+    service_name: String,
     entry_point: String,
+    api_version: Option<String>,
+    listen_targets: Vec<SocketAddr>,
+    address: Option<IpAddr>,
+    port: Option<u16>,
+    health_endpoint: Option<String>,
+    tls_paths: Option<TlsPaths>,
+    extra: HashMap<String, String>,
+}
+
+/// Builds a configuration for the tests of the other modules.
+#[cfg(test)]
+impl MinimalConfig {
+    pub fn for_tests (entry_point: &str) -> Self {
+        MinimalConfig {
+            service_name: String::from("quux"),
+            entry_point: String::from(entry_point),
+            api_version: None,
+            listen_targets: Vec::new(),
+            address: None,
+            port: None,
+            health_endpoint: None,
+            tls_paths: None,
+            extra: HashMap::new(),
+        }
+    }
 }
 
 impl Config for MinimalConfig {
+    fn get_service_name(&self) -> &str {
+        &self.service_name
+    }
+
     fn get_database_url(&self) -> &str {
         ""
     }
@@ -156,12 +577,28 @@ impl Config for MinimalConfig {
         &self.entry_point
     }
 
+    fn get_api_version(&self) -> Option<&str> {
+        self.api_version.as_ref().map(|version| version.as_str())
+    }
+
     fn get_database_pool_size(&self) -> u32 {
         0
     }
 
     fn with_database(&self) -> bool { false }
 
+    fn get_listen_targets(&self) -> &[SocketAddr] { &self.listen_targets }
+
+    fn get_address(&self) -> Option<IpAddr> { self.address }
+
+    fn get_port(&self) -> Option<u16> { self.port }
+
+    fn get_health_endpoint(&self) -> Option<&str> { self.health_endpoint.as_ref().map(|path| path.as_str()) }
+
+    fn get_tls_paths(&self) -> Option<&TlsPaths> { self.tls_paths.as_ref() }
+
+    fn get_extra(&self, key: &str) -> Option<&str> { self.extra.get(key).map(|value| value.as_str()) }
+
     fn into_service(self, routes: Vec<Route>) -> Box<dyn Service> {
         let service = MinimalService {
             config: self,
@@ -174,15 +611,1569 @@ impl Config for MinimalConfig {
 
 impl EnvironmentConfigurable for MinimalConfig {
     fn parse_environment() -> ErrorResult<Self> {
-        if let Err(error) = dotenv() {
-            warn!(target: "config", "Can't parse .env: {}", error);
-        };
+        load_dotenv();
 
+        let service_name = initialize_service_name();
         let entry_point = env::var("API_ENTRY_POINT")
             .unwrap_or_else(|_| String::from("/"));
 
+        let api_version = env::var("API_VERSION")
+            .ok()
+            .filter(|version| !version.is_empty());
+
+        initialize_request_body_limit();
+        initialize_retry_after();
+        initialize_verbose_errors();
+        initialize_cursor_secret()?;
+        #[cfg(feature = "jwt")]
+        initialize_jwt_verifier()?;
+
+        let listen_targets = read_listen_targets()?;
+        let address = read_address()?;
+        let port = read_port()?;
+        let health_endpoint = read_health_endpoint();
+        let tls_paths = read_tls_paths()?;
+        let extra = parse_extra_settings(filter_utf8_variables(env::vars_os()));
+
         Ok(MinimalConfig {
+            service_name,
             entry_point,
+            api_version,
+            listen_targets,
+            address,
+            port,
+            health_endpoint,
+            tls_paths,
+            extra,
+        })
+    }
+}
+
+/*   -------------------------------------------------------------
+     Features
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// Determines if the crate has been built with the `serialization` feature,
+/// needed for the JSON responders like `ApiResponse` for Serialize types.
+pub fn has_serialization () -> bool {
+    cfg!(feature = "serialization")
+}
+
+/*   -------------------------------------------------------------
+     Environment variables registry
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The specification of an environment variable read by the library.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+pub struct EnvVarSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+
+    /// The default value, as documented, or None if there isn't any
+    pub default: Option<&'static str>,
+
+    pub required: bool,
+}
+
+/// The environment variables read by the configurations and the kernel.
+pub const ENVIRONMENT_VARIABLES: &'static [EnvVarSpec] = &[
+    EnvVarSpec {
+        name: "API_ENTRY_POINT",
+        description: "The mounting point of the API methods",
+        default: Some("/"),
+        required: false,
+    },
+    EnvVarSpec {
+        name: "APP_LISTEN",
+        description: "A comma-separated list of addresses to listen to, overriding APP_ADDRESS and APP_PORT",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "API_VERSION",
+        description: "A version to prefix the mounting point with, e.g. v1",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "APP_ADDRESS",
+        description: "The address to listen to, an IP or a hostname",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "APP_PORT",
+        description: "The port to listen to, or random to pick a free port",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "APP_HEALTH_ENDPOINT",
+        description: "The path of a health route answering ALIVE, not mounted if empty",
+        default: Some("/status"),
+        required: false,
+    },
+    EnvVarSpec {
+        name: "APP_TLS_CERT",
+        description: "The path to the PEM certificate chain to serve HTTPS, with APP_TLS_KEY",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "APP_TLS_KEY",
+        description: "The path to the PEM private key to serve HTTPS, with APP_TLS_CERT",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "DATABASE_URL",
+        description: "The URL to connect to the database",
+        default: None,
+        required: true,
+    },
+    EnvVarSpec {
+        name: "DATABASE_POOL_SIZE",
+        description: "The number of connections to open",
+        default: Some("4"),
+        required: false,
+    },
+    EnvVarSpec {
+        name: "DATABASE_POOL_SIZE_<NAME>",
+        description: "The number of connections to open for a named pool",
+        default: Some("DATABASE_POOL_SIZE"),
+        required: false,
+    },
+    EnvVarSpec {
+        name: "DATABASE_POOL_TIMEOUT",
+        description: "The time in seconds to wait for a connection from the pool",
+        default: Some("30"),
+        required: false,
+    },
+    EnvVarSpec {
+        name: "DATABASE_POOL_MIN_IDLE",
+        description: "The number of idle connections to maintain in the pool",
+        default: Some("DATABASE_POOL_SIZE"),
+        required: false,
+    },
+    EnvVarSpec {
+        name: "DATABASE_POOL_TEST_ON_CHECKOUT",
+        description: "Whether to check a connection is alive before handing it to a request",
+        default: Some("true"),
+        required: false,
+    },
+    EnvVarSpec {
+        name: "DATABASE_CONNECT_RETRIES",
+        description: "The number of times to retry the connection to the database at startup",
+        default: Some("0"),
+        required: false,
+    },
+    EnvVarSpec {
+        name: "DATABASE_CONNECT_DELAY_MS",
+        description: "The delay before retrying the connection, doubled after each failed attempt",
+        default: Some("500"),
+        required: false,
+    },
+    EnvVarSpec {
+        name: "DATABASE_SLOW_ACQUIRE_MS",
+        description: "The duration from which getting a connection from the pool is logged as slow",
+        default: Some("500"),
+        required: false,
+    },
+    EnvVarSpec {
+        name: "DATABASE_LOG_QUERIES",
+        description: "If set, the queries are logged at debug level",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "DATABASE_CHECK_MAX_CONNECTIONS",
+        description: "If set, warns when the pool size is a large fraction of the server max_connections",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_CURSOR_SECRET",
+        description: "A secret to sign the pagination cursors with, with the cursor-signing feature",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_DISABLE_DATABASE",
+        description: "If set, the service runs without database",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_DUMP_ROUTES",
+        description: "If set, the mounted routes are printed instead of launching the server",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_EXTRA_<KEY>",
+        description: "An extra setting for the service, read through Config::get_extra",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_JSON_CHARSET",
+        description: "If set, the JSON responses are sent as application/json; charset=utf-8",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_JWT_SECRET",
+        description: "The HS256 secret to verify the bearer tokens with, with the jwt feature",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_JWT_PUBLIC_KEY",
+        description: "The PEM-encoded RS256 public key to verify the bearer tokens with, with the jwt feature",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_LOG_CLIENT_ERRORS",
+        description: "If set, the 4xx responses are logged at debug level",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_LOG_FORMAT",
+        description: "The format of the log lines: text or json",
+        default: Some("text"),
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_LOG_LEVEL",
+        description: "The minimum level of the log lines: off, error, warn, info, debug or trace, overridden by RUST_LOG for the text format",
+        default: Some("info"),
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_REQUEST_BODY_LIMIT",
+        description: "The maximum size of a request body, in bytes",
+        default: Some("1000000"),
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_REQUEST_BODY_LIMITS",
+        description: "The maximum size of a request body by content type, e.g. application/json=1000000",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_REQUEST_ID",
+        description: "If set, each request is identified by an ID, reused from or sent back in the LF_REQUEST_ID_HEADER header",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_REQUEST_ID_GENERATOR",
+        description: "The strategy to generate a request ID: uuid, counter or ulid",
+        default: Some("uuid"),
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_REQUEST_ID_HEADER",
+        description: "The header to read and write the request ID",
+        default: Some("X-Request-Id"),
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_RETRY_AFTER",
+        description: "The delay in seconds sent in the Retry-After header of 503 responses",
+        default: Some("5"),
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_RUN_MIGRATIONS",
+        description: "If set, the pending embedded migrations are applied at startup",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_SERVICE_NAME",
+        description: "The name of the service, for the banner, the logs and the PostgreSQL application_name",
+        default: Some("the name of the executable"),
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_SKIP_DOTENV",
+        description: "If set, the .env file isn't read",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_STRICT_ROUTES",
+        description: "If set, routes overlapping the built-in routes prevent the server to start",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_TEST_MODE",
+        description: "If set, TEST_DATABASE_URL is used instead of DATABASE_URL, and must be set",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "LF_VERBOSE_ERRORS",
+        description: "If set, the details of the internal errors are sent to the clients",
+        default: None,
+        required: false,
+    },
+    EnvVarSpec {
+        name: "TEST_DATABASE_URL",
+        description: "The URL to connect to the database in test mode",
+        default: None,
+        required: false,
+    },
+];
+
+/// Finds the specification of an environment variable in the registry.
+pub fn find_environment_variable (name: &str) -> Option<&'static EnvVarSpec> {
+    ENVIRONMENT_VARIABLES
+        .iter()
+        .find(|spec| spec.name == name)
+}
+
+/// Describes the known environment variables, one per line.
+pub fn describe_environment () -> String {
+    ENVIRONMENT_VARIABLES
+        .iter()
+        .map(|spec| {
+            let mut line = format!("{}: {}", spec.name, spec.description);
+
+            if spec.required {
+                line.push_str(" (mandatory)");
+            } else if let Some(default) = spec.default {
+                line.push_str(&format!(" (facultative, by default {})", default));
+            } else {
+                line.push_str(" (facultative)");
+            }
+
+            line
         })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/*   -------------------------------------------------------------
+     Secrets
+
+     :: SecretResolver
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The prefix of the secret references, so they can't be mistaken for regular values
+/// like `file:` SQLite database URLs.
+pub const SECRET_REFERENCE_PREFIX: &str = "secret+";
+
+/// This trait allows to resolve a secret reference like `secret+vault:secret/data/db#url`,
+/// so the secret value doesn't need to be stored in the environment.
+pub trait SecretResolver: Send + Sync {
+    /// Gets the scheme of the references handled by this resolver, e.g. `vault`
+    fn get_scheme(&self) -> &str;
+
+    /// Resolves the reference, the part after the scheme, into the secret value.
+    fn resolve(&self, reference: &str) -> ErrorResult<String>;
+}
+
+/// Resolves `secret+file:/path/to/secret` references by reading the file,
+/// e.g. for Docker or Kubernetes secrets.
+///
+/// The trailing newline of the file is ignored.
+pub struct FileSecretResolver;
+
+impl SecretResolver for FileSecretResolver {
+    fn get_scheme(&self) -> &str { "file" }
+
+    fn resolve(&self, reference: &str) -> ErrorResult<String> {
+        let content = fs::read_to_string(reference)?;
+
+        Ok(content.trim_end_matches(|c| c == '\n' || c == '\r').to_string())
+    }
+}
+
+/// Resolves `secret+env:VARIABLE` references by reading another environment variable.
+pub struct EnvSecretResolver;
+
+impl SecretResolver for EnvSecretResolver {
+    fn get_scheme(&self) -> &str { "env" }
+
+    fn resolve(&self, reference: &str) -> ErrorResult<String> {
+        resolve_env_reference(reference, |name| env::var(name).ok())
+    }
+}
+
+/// Resolves an env reference, reading the variable with the specified function.
+fn resolve_env_reference<F> (reference: &str, read_variable: F) -> ErrorResult<String>
+    where F: Fn(&str) -> Option<String>
+{
+    read_variable(reference).ok_or_else(|| format!("{}: environment variable not found", reference).into())
+}
+
+/// The resolvers registered by the application, in addition of the file and env ones
+static SECRET_RESOLVERS: OnceLock<Vec<Box<dyn SecretResolver>>> = OnceLock::new();
+
+/// Registers resolvers for other schemes, like Vault or AWS Secrets Manager,
+/// returning false if they have already been set.
+///
+/// They should be registered before the configuration is parsed.
+pub fn set_secret_resolvers (resolvers: Vec<Box<dyn SecretResolver>>) -> bool {
+    SECRET_RESOLVERS.set(resolvers).is_ok()
+}
+
+/// Resolves a value if it's a secret reference, i.e. starts with `secret+` followed
+/// by the scheme of one of the registered resolvers, or the file and env ones.
+///
+/// Other values, like `postgres://localhost/db` or `file:test.db`, are returned as is.
+pub fn resolve_secret (value: &str) -> ErrorResult<String> {
+    let builtin_resolvers: [&dyn SecretResolver; 2] = [&FileSecretResolver, &EnvSecretResolver];
+
+    let resolvers: Vec<&dyn SecretResolver> = SECRET_RESOLVERS
+        .get()
+        .map(|resolvers| resolvers.as_slice())
+        .unwrap_or(&[])
+        .iter()
+        .map(|resolver| resolver.as_ref())
+        .chain(builtin_resolvers.iter().cloned())
+        .collect();
+
+    resolve_secret_with(value, &resolvers)
+}
+
+/// Resolves a value if it's a secret reference, with the resolver handling its scheme.
+///
+/// Returns an error if no resolver handles the scheme of the reference.
+pub fn resolve_secret_with (value: &str, resolvers: &[&dyn SecretResolver]) -> ErrorResult<String> {
+    if !value.starts_with(SECRET_REFERENCE_PREFIX) {
+        return Ok(value.to_string());
+    }
+
+    let secret_reference = &value[SECRET_REFERENCE_PREFIX.len()..];
+    let separator = match secret_reference.find(':') {
+        Some(separator) => separator,
+        None => return Err(format!("The secret reference {} doesn't have a scheme", value).into()),
+    };
+
+    let scheme = &secret_reference[..separator];
+    let reference = &secret_reference[separator + 1..];
+
+    match resolvers.iter().find(|resolver| resolver.get_scheme() == scheme) {
+        Some(resolver) => resolver.resolve(reference).map_err(|error| {
+            format!("Can't resolve the secret reference {}: {}", value, error).into()
+        }),
+        None => Err(format!("Can't resolve the secret reference {}: no resolver for the {} scheme", value, scheme).into()),
+    }
+}
+
+/*   -------------------------------------------------------------
+     Settings shared by all configurations
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// Loads the .env file into the environment, unless `LF_SKIP_DOTENV` is set.
+///
+/// Returns true if a .env file has been loaded, false if the loading has been skipped
+/// or if the file is absent, unreadable or malformed.
+fn load_dotenv () -> bool {
+    if env::var("LF_SKIP_DOTENV").is_ok() {
+        return false;
+    }
+
+    let path = env::current_dir().ok().and_then(|directory| find_dotenv_path(&directory));
+
+    load_dotenv_with(path, ::dotenv::from_path)
+}
+
+/// Finds the .env file in the specified directory or its parents, like dotenv does.
+fn find_dotenv_path (directory: &Path) -> Option<PathBuf> {
+    directory
+        .ancestors()
+        .map(|directory| directory.join(".env"))
+        .find(|path| path.is_file())
+}
+
+/// Loads the .env file at the specified path with the specified loader,
+/// logging the path of the loaded or failing file.
+fn load_dotenv_with<F> (path: Option<PathBuf>, load: F) -> bool
+    where F: FnOnce(&Path) -> Result<(), DotenvError>
+{
+    let path = match path {
+        Some(path) => path,
+        None => {
+            debug!(target: "config", "No .env file found in the current directory or its parents.");
+
+            return false;
+        },
+    };
+
+    match load(&path) {
+        Ok(()) => {
+            debug!(target: "config", "Environment loaded from {}.", path.display());
+
+            true
+        },
+        Err(error) => {
+            log!(target: "config", get_dotenv_error_level(&error), "Can't read {}: {}", path.display(), error);
+
+            false
+        },
+    }
+}
+
+/// Gets the level to log a .env loading error at: a missing file is fine,
+/// but an unreadable or malformed file is probably a real problem.
+fn get_dotenv_error_level (error: &DotenvError) -> Level {
+    match *error {
+        DotenvError::Io(ref error) if error.kind() == ErrorKind::NotFound => Level::Debug,
+        _ => Level::Warn,
+    }
+}
+
+/// Parses a request body limit, which must be a positive integer.
+fn parse_request_body_limit (value: &str) -> Option<u64> {
+    match value.parse::<u64>() {
+        Ok(0) | Err(_) => None,
+        Ok(limit) => Some(limit),
+    }
+}
+
+/// Reads a pool size variable, which must be an unsigned integer.
+///
+/// Returns None if the variable isn't set or is invalid.
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+fn read_pool_size_variable (name: &str) -> Option<u32> {
+    let variable = env::var(name).ok()?;
+
+    parse_pool_size_variable(name, &variable)
+}
+
+/// Parses the value of a pool size variable, warning if it isn't an unsigned integer.
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+fn parse_pool_size_variable (name: &str, value: &str) -> Option<u32> {
+    match value.parse::<u32>() {
+        Ok(size) => Some(size),
+        Err(_) => {
+            warn!(target: "config", "The {} variable must be an unsigned integer.", name);
+
+            None
+        },
+    }
+}
+
+/// Caps the number of idle connections to the pool size, as r2d2 refuses to build
+/// a pool maintaining more idle connections than it can hold.
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+fn clamp_pool_min_idle (min_idle: Option<u32>, pool_size: u32) -> Option<u32> {
+    match min_idle {
+        Some(min_idle) if min_idle > pool_size => {
+            warn!(target: "config", "The DATABASE_POOL_MIN_IDLE variable can't exceed the pool size of {}, so it has been lowered to it.", pool_size);
+
+            Some(pool_size)
+        },
+        _ => min_idle,
+    }
+}
+
+/// Reads a boolean variable, accepting 1/0, true/false, yes/no and on/off.
+///
+/// Returns None if the variable isn't set or is invalid.
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+fn read_bool_variable (name: &str) -> Option<bool> {
+    let variable = env::var(name).ok()?;
+    let value = parse_bool(&variable);
+
+    if value.is_none() {
+        warn!(target: "config", "The {} variable must be a boolean, like true or false.", name);
+    }
+
+    value
+}
+
+/// Reads a pool timeout variable, in seconds, which must be a positive integer,
+/// as r2d2 doesn't accept a zero timeout.
+///
+/// Returns None if the variable isn't set or is invalid.
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+fn read_pool_timeout_variable (name: &str) -> Option<Duration> {
+    let variable = env::var(name).ok()?;
+
+    parse_pool_timeout_variable(name, &variable)
+}
+
+/// Parses the value of a pool timeout variable, warning if it isn't a positive integer.
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+fn parse_pool_timeout_variable (name: &str, value: &str) -> Option<Duration> {
+    match value.parse::<u64>() {
+        Ok(seconds) if seconds > 0 => Some(Duration::from_secs(seconds)),
+        _ => {
+            warn!(target: "config", "The {} variable must be a positive integer.", name);
+
+            None
+        },
+    }
+}
+
+/// Reads the `DATABASE_CONNECT_RETRIES` and `DATABASE_CONNECT_DELAY_MS` variables
+/// to retry the connection to the database at startup.
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+fn read_connect_retry () -> ConnectRetry {
+    let retries = match env::var("DATABASE_CONNECT_RETRIES") {
+        Ok(variable) => variable.parse::<u32>().unwrap_or_else(|_| {
+            warn!(target: "config", "The DATABASE_CONNECT_RETRIES variable must be an unsigned integer.");
+
+            0
+        }),
+        Err(_) => 0,
+    };
+
+    let base_delay = match env::var("DATABASE_CONNECT_DELAY_MS") {
+        Ok(variable) => variable.parse::<u64>().map(Duration::from_millis).unwrap_or_else(|_| {
+            warn!(target: "config", "The DATABASE_CONNECT_DELAY_MS variable must be an unsigned integer.");
+
+            DEFAULT_CONNECT_RETRY.base_delay
+        }),
+        Err(_) => DEFAULT_CONNECT_RETRY.base_delay,
+    };
+
+    ConnectRetry {
+        attempts: retries.saturating_add(1),
+        base_delay,
+    }
+}
+
+/// Gets the pool size for a named database, from the `DATABASE_POOL_SIZE_<NAME>` variable,
+/// falling back to `DATABASE_POOL_SIZE`, then to the default pool size.
+///
+/// The name is uppercased, and dashes are replaced by underscores,
+/// e.g. `DATABASE_POOL_SIZE_ANALYTICS` for the `analytics` pool.
+#[cfg(feature = "pgsql")]
+pub fn get_named_database_pool_size (name: &str) -> u32 {
+    choose_named_database_pool_size(name, read_pool_size_variable)
+}
+
+/// Picks the pool size for a named database, reading the variables with the specified closure.
+#[cfg(feature = "pgsql")]
+fn choose_named_database_pool_size<F> (name: &str, read_variable: F) -> u32
+    where F: Fn(&str) -> Option<u32>
+{
+    let variable = format!("DATABASE_POOL_SIZE_{}", name.to_uppercase().replace('-', "_"));
+
+    read_variable(&variable)
+        .or_else(|| read_variable("DATABASE_POOL_SIZE"))
+        .unwrap_or(DefaultConfig::DEFAULT_DATABASE_POOL_SIZE)
+}
+
+/// Reads the database URL from the `DATABASE_URL` variable,
+/// or from `TEST_DATABASE_URL` when the `LF_TEST_MODE` variable is set.
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+fn read_database_url () -> Result<String, &'static str> {
+    choose_database_url(
+        env::var("LF_TEST_MODE").is_ok(),
+        env::var("TEST_DATABASE_URL").ok(),
+        env::var("DATABASE_URL").ok(),
+    )
+}
+
+/// Chooses the test database URL in test mode, the database URL otherwise.
+///
+/// In test mode, the test database URL is required: falling back to the regular
+/// database would run the tests against it.
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+fn choose_database_url (test_mode: bool, test_url: Option<String>, url: Option<String>) -> Result<String, &'static str> {
+    if test_mode {
+        return test_url.ok_or("The LF_TEST_MODE variable is set, you need to specify a TEST_DATABASE_URL variable in the environment (or .env file).");
+    }
+
+    url.ok_or("You need to specify a DATABASE_URL variable in the environment (or .env file).")
+}
+
+/// Reads the `LF_CURSOR_SECRET` variable and sets the secret to sign the pagination cursors with.
+#[cfg(feature = "cursor-signing")]
+fn initialize_cursor_secret () -> ErrorResult<()> {
+    let secret = match read_secret_variable("LF_CURSOR_SECRET")? {
+        Some(secret) => secret,
+        None => return Ok(()),
+    };
+
+    if !set_cursor_secret(secret.as_bytes()) {
+        warn!(target: "config", "The cursor secret has already been set.");
+    }
+
+    Ok(())
+}
+
+/// Warns the pagination cursors can't be signed without the `cursor-signing` feature.
+#[cfg(not(feature = "cursor-signing"))]
+fn initialize_cursor_secret () -> ErrorResult<()> {
+    if env::var("LF_CURSOR_SECRET").is_ok() {
+        warn!(target: "config", "The LF_CURSOR_SECRET variable is set, but limiting-factor has been built without the cursor-signing feature.");
+    }
+
+    Ok(())
+}
+
+/// Reads the `LF_JWT_SECRET` or `LF_JWT_PUBLIC_KEY` variable and sets the verifier of the bearer tokens.
+#[cfg(feature = "jwt")]
+fn initialize_jwt_verifier () -> ErrorResult<()> {
+    let secret = read_secret_variable("LF_JWT_SECRET")?;
+    let public_key = read_secret_variable("LF_JWT_PUBLIC_KEY")?;
+
+    let verifier = match (secret, public_key) {
+        (None, None) => return Ok(()),
+        (Some(secret), None) => JwtVerifier::from_secret(secret.as_bytes()),
+        (None, Some(public_key)) => JwtVerifier::from_rsa_public_key(public_key.as_bytes()).map_err(|error| {
+            error!(target: "config", "The LF_JWT_PUBLIC_KEY variable must be a PEM-encoded RSA public key: {}", error);
+
+            error
+        })?,
+        (Some(_), Some(_)) => {
+            error!(target: "config", "Only one of the LF_JWT_SECRET and LF_JWT_PUBLIC_KEY variables can be set.");
+
+            return Err("Ambiguous JWT verification key.".into());
+        },
+    };
+
+    if !set_jwt_verifier(verifier) {
+        warn!(target: "config", "The JWT verifier has already been set.");
+    }
+
+    Ok(())
+}
+
+/// Reads a variable holding a secret or a secret reference resolved by `resolve_secret`.
+///
+/// Returns None if the variable isn't set or is empty.
+fn read_secret_variable (name: &str) -> ErrorResult<Option<String>> {
+    match env::var(name) {
+        Ok(ref variable) if variable.is_empty() => Ok(None),
+        Ok(variable) => resolve_secret(&variable).map(Some).map_err(|error| {
+            error!(target: "config", "The {} variable can't be resolved: {}", name, error);
+
+            error
+        }),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Reads the `LF_REQUEST_BODY_LIMIT` variable and sets the effective request body limit,
+/// then the `LF_REQUEST_BODY_LIMITS` variable to override it by content type.
+fn initialize_request_body_limit () {
+    let limit = match env::var("LF_REQUEST_BODY_LIMIT") {
+        Ok(variable) => {
+            parse_request_body_limit(&variable).unwrap_or_else(|| {
+                warn!(target: "config", "The LF_REQUEST_BODY_LIMIT variable must be a positive integer.");
+
+                REQUEST_BODY_LIMIT
+            })
+        },
+        Err(_) => REQUEST_BODY_LIMIT,
+    };
+
+    if !set_request_body_limit(limit) {
+        warn!(target: "config", "The request body limit has already been set.");
+    }
+
+    if let Ok(variable) = env::var("LF_REQUEST_BODY_LIMITS") {
+        match ContentTypeBodyLimits::parse(&variable) {
+            Some(limits) => {
+                if !set_content_type_body_limits(limits) {
+                    warn!(target: "config", "The request body limits by content type have already been set.");
+                }
+            },
+            None => {
+                warn!(target: "config", "The LF_REQUEST_BODY_LIMITS variable must be a comma-separated list of media type=limit.");
+            },
+        }
+    }
+}
+
+/// Reads the `LF_RETRY_AFTER` variable and sets the delay sent with 503 responses.
+fn initialize_retry_after () {
+    let seconds = match env::var("LF_RETRY_AFTER") {
+        Ok(variable) => {
+            variable.parse::<u64>().unwrap_or_else(|_| {
+                warn!(target: "config", "The LF_RETRY_AFTER variable must be an unsigned integer.");
+
+                DEFAULT_RETRY_AFTER
+            })
+        },
+        Err(_) => DEFAULT_RETRY_AFTER,
+    };
+
+    if !set_retry_after(seconds) {
+        warn!(target: "config", "The retry delay has already been set.");
+    }
+}
+
+/// Reads the `LF_SERVICE_NAME` variable and sets the service name of the structured logs.
+fn initialize_service_name () -> String {
+    let service_name = choose_service_name(env::var("LF_SERVICE_NAME").ok(), get_default_service_name());
+
+    if !set_service_name(service_name.clone()) {
+        warn!(target: "config", "The service name has already been set.");
+    }
+
+    service_name
+}
+
+/// Picks the service name: the configured one, else the default one.
+fn choose_service_name (configured_name: Option<String>, default_name: &str) -> String {
+    configured_name
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| String::from(default_name))
+}
+
+/// Reads the `LF_VERBOSE_ERRORS` variable and sets if the internal errors details are sent.
+fn initialize_verbose_errors () {
+    if !set_verbose_errors(env::var("LF_VERBOSE_ERRORS").is_ok()) {
+        warn!(target: "config", "The verbose errors mode has already been set.");
+    }
+}
+
+/// Combines the entry point and the API version, normalizing the slashes.
+///
+/// For example, `/` and `v1` give `/v1`, `/api/` and `/v1/` give `/api/v1`.
+pub fn build_versioned_entry_point (entry_point: &str, version: Option<&str>) -> String {
+    let segments: Vec<&str> = entry_point
+        .split('/')
+        .chain(version.unwrap_or("").split('/'))
+        .filter(|segment| !segment.is_empty())
+        .collect();
+
+    format!("/{}", segments.join("/"))
+}
+
+/// Resolves the address the server should bind to.
+///
+/// An IP address, including `0.0.0.0` and `::`, is used as is. A hostname is resolved
+/// to its first address, as the listener can only bind to an IP address.
+pub fn resolve_bind_address (address: &str) -> ErrorResult<IpAddr> {
+    if let Ok(ip) = address.parse::<IpAddr>() {
+        return Ok(ip);
+    }
+
+    let error_message = |reason: &str| {
+        format!("Can't resolve the bind address {}: {}. The server needs an IP address to listen to.", address, reason)
+    };
+
+    match (address, 0).to_socket_addrs() {
+        Ok(mut addresses) => addresses
+            .next()
+            .map(|socket_address| socket_address.ip())
+            .ok_or_else(|| error_message("no address found").into()),
+        Err(error) => Err(error_message(&error.to_string()).into()),
+    }
+}
+
+/// Parses a comma-separated list of addresses to listen to.
+///
+/// Each address is a socket address like `127.0.0.1:8000` or `[::1]:8000`,
+/// or a hostname and a port like `localhost:8000`.
+pub fn parse_listen_targets (value: &str) -> ErrorResult<Vec<SocketAddr>> {
+    value
+        .split(',')
+        .map(|target| target.trim())
+        .filter(|target| !target.is_empty())
+        .map(|target| -> ErrorResult<SocketAddr> {
+            if let Ok(address) = target.parse::<SocketAddr>() {
+                return Ok(address);
+            }
+
+            let separator = target.rfind(':')
+                .ok_or_else(|| format!("The listen target {} should be an address and a port.", target))?;
+            let port = target[separator + 1..].parse::<u16>()
+                .map_err(|_| format!("The listen target {} hasn't a valid port.", target))?;
+            let ip = resolve_bind_address(&target[..separator])?;
+
+            Ok(SocketAddr::new(ip, port))
+        })
+        .collect()
+}
+
+/// Reads the `APP_LISTEN` variable to get the addresses to listen to.
+///
+/// When set, it takes precedence over the `APP_ADDRESS` and `APP_PORT` variables,
+/// so a warning is logged if they're set too.
+fn read_listen_targets () -> ErrorResult<Vec<SocketAddr>> {
+    match env::var("APP_LISTEN") {
+        Ok(variable) => {
+            let overridden = get_overridden_listen_variables(env::var("APP_ADDRESS").is_ok(), env::var("APP_PORT").is_ok());
+            for name in overridden {
+                warn!(target: "config", "The {} variable is ignored, as APP_LISTEN is set.", name);
+            }
+
+            parse_listen_targets(&variable).map_err(|error| {
+                error!(target: "config", "The APP_LISTEN variable can't be parsed: {}", error);
+
+                error
+            })
+        },
+        Err(_) => Ok(Vec::new()),
+    }
+}
+
+/// Gets the variables overridden by `APP_LISTEN` among the set ones.
+fn get_overridden_listen_variables (is_address_set: bool, is_port_set: bool) -> Vec<&'static str> {
+    let mut variables = Vec::new();
+
+    if is_address_set {
+        variables.push("APP_ADDRESS");
+    }
+
+    if is_port_set {
+        variables.push("APP_PORT");
+    }
+
+    variables
+}
+
+/// Parses the port to listen to, or picks a free port if the value is `random`.
+pub fn parse_port (value: &str) -> ErrorResult<u16> {
+    if value == "random" {
+        let port = pick_random_port()?;
+        info!(target: "config", "The port {} has been picked to listen to.", port);
+
+        return Ok(port);
+    }
+
+    value
+        .parse::<u16>()
+        .map_err(|_| format!("The port {} should be an integer between 0 and 65535, or random.", value).into())
+}
+
+/// Asks the operating system for a free port.
+///
+/// The port is free when picked, but isn't reserved until the server binds to it.
+pub fn pick_random_port () -> ErrorResult<u16> {
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+
+    Ok(listener.local_addr()?.port())
+}
+
+/// The prefix of the variables holding the extra settings
+const EXTRA_SETTINGS_PREFIX: &'static str = "LF_EXTRA_";
+
+/// Parses the extra settings from the `LF_EXTRA_<KEY>` variables, keyed without the prefix.
+pub fn parse_extra_settings<I> (variables: I) -> HashMap<String, String>
+    where I: IntoIterator<Item = (String, String)>
+{
+    variables
+        .into_iter()
+        .filter_map(|(name, value)| {
+            if !name.starts_with(EXTRA_SETTINGS_PREFIX) || name.len() == EXTRA_SETTINGS_PREFIX.len() {
+                return None;
+            }
+
+            Some((name[EXTRA_SETTINGS_PREFIX.len()..].to_string(), value))
+        })
+        .collect()
+}
+
+/// Skips the variables whose name or value isn't valid UTF-8, as `env::vars` would panic on them.
+///
+/// Such variables aren't read by this crate, but can be set by the process environment.
+fn filter_utf8_variables<I> (variables: I) -> impl Iterator<Item = (String, String)>
+    where I: IntoIterator<Item = (OsString, OsString)>
+{
+    variables
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+}
+
+/// Parses a boolean setting, accepting 1/0, true/false, yes/no and on/off.
+fn parse_bool (value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// The default path of the health route
+pub const DEFAULT_HEALTH_ENDPOINT: &'static str = "/status";
+
+/// Reads the `APP_HEALTH_ENDPOINT` variable to get the path of the health route.
+fn read_health_endpoint () -> Option<String> {
+    match env::var("APP_HEALTH_ENDPOINT") {
+        Ok(ref path) if path.is_empty() => None,
+        Ok(ref path) if !path.starts_with('/') => {
+            warn!(target: "config", "The APP_HEALTH_ENDPOINT variable must be a path starting by /, the health route won't be mounted.");
+
+            None
+        },
+        Ok(path) => Some(path),
+        Err(_) => Some(String::from(DEFAULT_HEALTH_ENDPOINT)),
+    }
+}
+
+/// The paths to the certificate chain and the private key to serve HTTPS
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TlsPaths {
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+/// Builds the TLS paths from the certificate and private key paths,
+/// or returns an error if only one of them is provided.
+pub fn parse_tls_paths (cert_path: Option<String>, key_path: Option<String>) -> ErrorResult<Option<TlsPaths>> {
+    match (cert_path, key_path) {
+        (Some(cert_path), Some(key_path)) => Ok(Some(TlsPaths { cert_path, key_path })),
+        (None, None) => Ok(None),
+        (Some(_), None) => Err("A private key is needed to serve HTTPS with this certificate.".into()),
+        (None, Some(_)) => Err("A certificate is needed to serve HTTPS with this private key.".into()),
+    }
+}
+
+/// Reads the `APP_TLS_CERT` and `APP_TLS_KEY` variables to get the paths to serve HTTPS.
+fn read_tls_paths () -> ErrorResult<Option<TlsPaths>> {
+    let cert_path = env::var("APP_TLS_CERT").ok().filter(|path| !path.is_empty());
+    let key_path = env::var("APP_TLS_KEY").ok().filter(|path| !path.is_empty());
+
+    parse_tls_paths(cert_path, key_path).map_err(|error| {
+        error!(target: "config", "The APP_TLS_CERT and APP_TLS_KEY variables can't be used: {}", error);
+
+        error
+    })
+}
+
+/// Reads the `APP_ADDRESS` variable to get the address to listen to.
+fn read_address () -> ErrorResult<Option<IpAddr>> {
+    match env::var("APP_ADDRESS") {
+        Ok(variable) => resolve_bind_address(&variable).map(Some).map_err(|error| {
+            error!(target: "config", "The APP_ADDRESS variable can't be parsed: {}", error);
+
+            error
+        }),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Reads the `APP_PORT` variable to get the port to listen to.
+fn read_port () -> ErrorResult<Option<u16>> {
+    match env::var("APP_PORT") {
+        Ok(variable) => parse_port(&variable).map(Some).map_err(|error| {
+            error!(target: "config", "The APP_PORT variable can't be parsed: {}", error);
+
+            error
+        }),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::Client;
+
+    #[test]
+    fn test_load_dotenv () {
+        let path = PathBuf::from("/srv/app/.env");
+        let loaded = load_dotenv_with(Some(path.clone()), |loaded_path| {
+            assert_eq!(path, loaded_path);
+
+            Ok(())
+        });
+
+        assert!(loaded);
+    }
+
+    #[test]
+    fn test_load_dotenv_when_absent () {
+        let loaded = load_dotenv_with(None, |_| -> Result<(), DotenvError> {
+            panic!("There is no .env file to load")
+        });
+        assert!(!loaded);
+
+        assert!(!load_dotenv_with(Some(PathBuf::from("/nonexistent/.env")), ::dotenv::from_path));
+    }
+
+    #[test]
+    fn test_find_dotenv_path () {
+        let root = env::temp_dir().join(format!("limiting-factor-dotenv-{}", ::std::process::id()));
+        let directory = root.join("quux");
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(root.join(".env"), "QUUX=42\n").unwrap();
+
+        let path = find_dotenv_path(&directory);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(Some(root.join(".env")), path);
+    }
+
+    #[test]
+    fn test_dotenv_error_level_when_absent () {
+        let error = ::dotenv::from_path("/nonexistent/.env").unwrap_err();
+
+        assert_eq!(Level::Debug, get_dotenv_error_level(&error));
+    }
+
+    #[test]
+    fn test_dotenv_error_level_when_malformed () {
+        let path = env::temp_dir().join(format!("limiting-factor-malformed-{}.env", ::std::process::id()));
+        fs::write(&path, "QUUX='unterminated\n").unwrap();
+
+        let error = ::dotenv::from_path_iter(&path)
+            .unwrap()
+            .filter_map(|item| item.err())
+            .next()
+            .expect("A malformed .env file should give an error");
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(Level::Warn, get_dotenv_error_level(&error));
+    }
+
+    #[test]
+    fn test_dotenv_when_valid () {
+        let path = env::temp_dir().join(format!("limiting-factor-valid-{}.env", ::std::process::id()));
+        fs::write(&path, "QUUX=42\n").unwrap();
+
+        let errors = ::dotenv::from_path_iter(&path)
+            .unwrap()
+            .filter(|item| item.is_err())
+            .count();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(0, errors);
+    }
+
+    #[test]
+    fn test_parse_request_body_limit () {
+        assert_eq!(Some(4096), parse_request_body_limit("4096"));
+    }
+
+    #[test]
+    fn test_parse_request_body_limit_when_invalid () {
+        assert_eq!(None, parse_request_body_limit("0"));
+        assert_eq!(None, parse_request_body_limit("-1"));
+        assert_eq!(None, parse_request_body_limit("1 MB"));
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_has_serialization () {
+        assert!(has_serialization());
+    }
+
+    #[test]
+    #[cfg(not(feature = "serialization"))]
+    fn test_has_serialization_when_disabled () {
+        assert!(!has_serialization());
+    }
+
+    /// Finds the environment variables read by a source file, outside of its tests,
+    /// as the string literals looking like a variable name.
+    ///
+    /// The `LF_EXTRA_` and `DATABASE_POOL_SIZE_{}` prefixes are returned as is.
+    fn find_read_variables (source: &str) -> Vec<&str> {
+        let prefixes = ["LF_", "APP_", "API_", "DATABASE_", "TEST_DATABASE_"];
+        let code = source.split("#[cfg(test)]\nmod tests").next().unwrap();
+
+        code.lines()
+            // The registry itself and the build time variables aren't parsers
+            .filter(|line| {
+                let line = line.trim_start();
+
+                !line.starts_with("name:") && !line.starts_with("default:") && !line.contains("option_env!")
+            })
+            .flat_map(|line| line.split('"'))
+            .filter(|literal| prefixes.iter().any(|prefix| literal.starts_with(prefix) && literal.len() > prefix.len()))
+            .filter(|literal| literal.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || "_{}".contains(c)))
+            .collect()
+    }
+
+    /// Determines if a variable read by the code matches a registry entry,
+    /// a prefix like `LF_EXTRA_` matching the `LF_EXTRA_<KEY>` entry.
+    fn is_registered_as (variable: &str, spec: &EnvVarSpec) -> bool {
+        let prefix = variable.trim_end_matches("{}");
+
+        if prefix.ends_with('_') {
+            spec.name.starts_with(prefix) && spec.name[prefix.len()..].starts_with('<')
+        } else {
+            spec.name == variable
+        }
+    }
+
+    #[test]
+    fn test_known_variables_are_in_registry () {
+        let sources = [
+            include_str!("build_info.rs"), include_str!("config.rs"), include_str!("database.rs"),
+            include_str!("kernel.rs"), include_str!("lib.rs"), include_str!("logger.rs"), include_str!("scheduler.rs"),
+            include_str!("api/charset.rs"), include_str!("api/client_errors.rs"), include_str!("api/guards.rs"),
+            include_str!("api/jwt.rs"), include_str!("api/mod.rs"), include_str!("api/pagination.rs"),
+            include_str!("api/rate_limit.rs"), include_str!("api/replies.rs"), include_str!("api/request_id.rs"),
+        ];
+        let read_variables: Vec<&str> = sources.iter().flat_map(|source| find_read_variables(source)).collect();
+
+        for variable in read_variables.iter() {
+            assert!(
+                ENVIRONMENT_VARIABLES.iter().any(|spec| is_registered_as(variable, spec)),
+                "{} is read, but is missing from the registry", variable
+            );
+        }
+
+        for spec in ENVIRONMENT_VARIABLES.iter() {
+            assert!(
+                read_variables.iter().any(|variable| is_registered_as(variable, spec)),
+                "{} is in the registry, but isn't read", spec.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_read_variables () {
+        let source = r#"
+            let port = env::var("APP_PORT");
+            let key = format!("DATABASE_POOL_SIZE_{}", name);
+            let sha = option_env!("LF_BUILD_GIT_SHA");
+            let message = "LF_TEST_MODE is set";
+        "#;
+
+        assert_eq!(vec!["APP_PORT", "DATABASE_POOL_SIZE_{}"], find_read_variables(source));
+    }
+
+    #[test]
+    fn test_describe_environment () {
+        let description = describe_environment();
+
+        assert_eq!(ENVIRONMENT_VARIABLES.len(), description.lines().count());
+        assert!(description.contains("DATABASE_URL: The URL to connect to the database (mandatory)"));
+        assert!(description.contains("API_ENTRY_POINT: The mounting point of the API methods (facultative, by default /)"));
+    }
+
+    #[test]
+    #[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+    fn test_clamp_pool_min_idle () {
+        assert_eq!(Some(4), clamp_pool_min_idle(Some(8), 4));
+        assert_eq!(Some(2), clamp_pool_min_idle(Some(2), 4));
+        assert_eq!(None, clamp_pool_min_idle(None, 4));
+    }
+
+    #[test]
+    #[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+    fn test_parse_pool_timeout_variable () {
+        assert_eq!(Some(Duration::from_secs(2)), parse_pool_timeout_variable("DATABASE_POOL_TIMEOUT", "2"));
+        assert_eq!(None, parse_pool_timeout_variable("DATABASE_POOL_TIMEOUT", "0"));
+        assert_eq!(None, parse_pool_timeout_variable("DATABASE_POOL_TIMEOUT", "-2"));
+        assert_eq!(None, parse_pool_timeout_variable("DATABASE_POOL_TIMEOUT", "quux"));
+    }
+
+    #[test]
+    #[cfg(feature = "pgsql")]
+    fn test_choose_named_database_pool_size () {
+        let variables: HashMap<&str, &str> = vec![
+            ("DATABASE_POOL_SIZE_TEST_MAIN", "8"),
+            ("DATABASE_POOL_SIZE_TEST_ANALYTICS", "two"),
+            ("DATABASE_POOL_SIZE", "6"),
+        ].into_iter().collect();
+        let read_variable = |name: &str| variables.get(name).and_then(|value| parse_pool_size_variable(name, value));
+
+        assert_eq!(8, choose_named_database_pool_size("test-main", &read_variable));
+
+        // An invalid override falls back to the global setting
+        assert_eq!(6, choose_named_database_pool_size("test-analytics", &read_variable));
+        assert_eq!(6, choose_named_database_pool_size("test-unknown", &read_variable));
+
+        // Without any setting, the default pool size is used
+        assert_eq!(DefaultConfig::DEFAULT_DATABASE_POOL_SIZE, choose_named_database_pool_size("test-main", |_| None));
+    }
+
+    struct VaultResolver;
+
+    impl SecretResolver for VaultResolver {
+        fn get_scheme(&self) -> &str { "vault" }
+
+        fn resolve(&self, reference: &str) -> ErrorResult<String> {
+            match reference {
+                "secret/data/db#url" => Ok(String::from("postgres://vault.invalid/db")),
+                _ => Err("secret not found".into()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_resolve_secret_with_reference () {
+        let resolvers: [&dyn SecretResolver; 1] = [&VaultResolver];
+
+        let value = resolve_secret_with("secret+vault:secret/data/db#url", &resolvers).unwrap();
+        assert_eq!("postgres://vault.invalid/db", value);
+    }
+
+    #[test]
+    fn test_resolve_secret_with_plain_value () {
+        let resolvers: [&dyn SecretResolver; 1] = [&VaultResolver];
+
+        assert_eq!("postgres://localhost/db", resolve_secret_with("postgres://localhost/db", &resolvers).unwrap());
+        assert_eq!("quux", resolve_secret_with("quux", &resolvers).unwrap());
+
+        // Values looking like references without the prefix are regular values
+        assert_eq!("vault:secret/data/db#url", resolve_secret_with("vault:secret/data/db#url", &resolvers).unwrap());
+        assert_eq!("file:test.db?mode=ro", resolve_secret("file:test.db?mode=ro").unwrap());
+        assert_eq!("env:QUUX", resolve_secret("env:QUUX").unwrap());
+    }
+
+    #[test]
+    fn test_resolve_secret_when_resolver_fails () {
+        let resolvers: [&dyn SecretResolver; 1] = [&VaultResolver];
+
+        let error = resolve_secret_with("secret+vault:secret/data/unknown", &resolvers).unwrap_err();
+        assert_eq!("Can't resolve the secret reference secret+vault:secret/data/unknown: secret not found", error.to_string());
+
+        let error = resolve_secret_with("secret+aws:db", &resolvers).unwrap_err();
+        assert_eq!("Can't resolve the secret reference secret+aws:db: no resolver for the aws scheme", error.to_string());
+
+        assert!(resolve_secret_with("secret+vault", &resolvers).is_err());
+    }
+
+    #[test]
+    fn test_resolve_env_reference () {
+        let read_variable = |name: &str| match name {
+            "TEST_SECRET_DATABASE_URL" => Some(String::from("postgres://env.invalid/db")),
+            _ => None,
+        };
+
+        assert_eq!("postgres://env.invalid/db", resolve_env_reference("TEST_SECRET_DATABASE_URL", &read_variable).unwrap());
+        assert!(resolve_env_reference("TEST_SECRET_UNKNOWN", &read_variable).is_err());
+    }
+
+    #[test]
+    fn test_resolve_secret_from_file () {
+        let path = env::temp_dir().join("limiting-factor-test-secret");
+        fs::write(&path, "postgres://file.invalid/db\n").unwrap();
+        let value = resolve_secret(&format!("secret+file:{}", path.display())).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!("postgres://file.invalid/db", value);
+    }
+
+    #[test]
+    fn test_build_versioned_entry_point () {
+        assert_eq!("/v1", build_versioned_entry_point("/", Some("v1")));
+        assert_eq!("/api/v1", build_versioned_entry_point("/api", Some("v1")));
+        assert_eq!("/api/v1", build_versioned_entry_point("/api/", Some("/v1/")));
+        assert_eq!("/api/v1", build_versioned_entry_point("api", Some("v1")));
+    }
+
+    #[test]
+    fn test_build_versioned_entry_point_without_version () {
+        assert_eq!("/", build_versioned_entry_point("/", None));
+        assert_eq!("/", build_versioned_entry_point("", Some("")));
+        assert_eq!("/api", build_versioned_entry_point("/api/", None));
+    }
+
+    #[test]
+    fn test_resolve_bind_address_with_ip () {
+        assert_eq!("127.0.0.1".parse::<IpAddr>().unwrap(), resolve_bind_address("127.0.0.1").unwrap());
+        assert_eq!("0.0.0.0".parse::<IpAddr>().unwrap(), resolve_bind_address("0.0.0.0").unwrap());
+        assert_eq!("::".parse::<IpAddr>().unwrap(), resolve_bind_address("::").unwrap());
+    }
+
+    #[test]
+    fn test_resolve_bind_address_with_localhost () {
+        let ip = resolve_bind_address("localhost").unwrap();
+        assert!(ip.is_loopback());
+    }
+
+    #[test]
+    fn test_resolve_bind_address_when_unresolvable () {
+        let error = resolve_bind_address("unresolvable.invalid").unwrap_err();
+        assert!(error.to_string().contains("needs an IP address"));
+    }
+
+    #[test]
+    fn test_parse_listen_targets () {
+        let targets = parse_listen_targets("127.0.0.1:8000, [::1]:9000,localhost:8080").unwrap();
+
+        assert_eq!(3, targets.len());
+        assert_eq!("127.0.0.1:8000".parse::<SocketAddr>().unwrap(), targets[0]);
+        assert_eq!("[::1]:9000".parse::<SocketAddr>().unwrap(), targets[1]);
+        assert!(targets[2].ip().is_loopback());
+        assert_eq!(8080, targets[2].port());
+    }
+
+    #[test]
+    fn test_parse_listen_targets_when_invalid () {
+        assert!(parse_listen_targets("127.0.0.1").is_err());
+        assert!(parse_listen_targets("127.0.0.1:http").is_err());
+    }
+
+    #[test]
+    fn test_get_overridden_listen_variables () {
+        assert_eq!(vec!["APP_ADDRESS", "APP_PORT"], get_overridden_listen_variables(true, true));
+        assert_eq!(vec!["APP_PORT"], get_overridden_listen_variables(false, true));
+        assert!(get_overridden_listen_variables(false, false).is_empty());
+    }
+
+    #[test]
+    fn test_parse_port () {
+        assert_eq!(8000, parse_port("8000").unwrap());
+        assert!(parse_port("65536").is_err());
+        assert!(parse_port("http").is_err());
+    }
+
+    #[test]
+    fn test_parse_port_when_random () {
+        let port = parse_port("random").unwrap();
+        assert_ne!(0, port);
+
+        let listener = TcpListener::bind(("127.0.0.1", port)).expect("The picked port should be free");
+        assert_eq!(port, listener.local_addr().unwrap().port());
+    }
+
+    #[test]
+    #[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+    fn test_choose_database_url_in_test_mode () {
+        let url = choose_database_url(
+            true,
+            Some(String::from("postgres://localhost/test")),
+            Some(String::from("postgres://localhost/dev")),
+        );
+        assert_eq!(Ok(String::from("postgres://localhost/test")), url);
+
+        // Without test database, the regular one must not be used
+        let url = choose_database_url(true, None, Some(String::from("postgres://localhost/dev")));
+        assert!(url.is_err());
+    }
+
+    #[test]
+    #[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+    fn test_choose_database_url_outside_test_mode () {
+        let url = choose_database_url(
+            false,
+            Some(String::from("postgres://localhost/test")),
+            Some(String::from("postgres://localhost/dev")),
+        );
+        assert_eq!(Ok(String::from("postgres://localhost/dev")), url);
+
+        let url = choose_database_url(false, Some(String::from("postgres://localhost/test")), None);
+        assert!(url.is_err());
+    }
+
+    #[test]
+    fn test_parse_tls_paths () {
+        let tls_paths = parse_tls_paths(Some(String::from("cert.pem")), Some(String::from("key.pem"))).unwrap();
+        assert_eq!(Some(TlsPaths { cert_path: String::from("cert.pem"), key_path: String::from("key.pem") }), tls_paths);
+
+        assert_eq!(None, parse_tls_paths(None, None).unwrap());
+        assert!(parse_tls_paths(Some(String::from("cert.pem")), None).is_err());
+        assert!(parse_tls_paths(None, Some(String::from("key.pem"))).is_err());
+    }
+
+    #[test]
+    fn test_parse_extra_settings () {
+        let variables = vec![
+            (String::from("LF_EXTRA_MAX_PLAYERS"), String::from("42")),
+            (String::from("LF_EXTRA_ALLOW_GUESTS"), String::from("yes")),
+            (String::from("LF_EXTRA_"), String::from("ignored")),
+            (String::from("DATABASE_URL"), String::from("postgres://localhost/test")),
+        ];
+
+        let extra = parse_extra_settings(variables);
+
+        assert_eq!(2, extra.len());
+        assert_eq!(Some(&String::from("42")), extra.get("MAX_PLAYERS"));
+        assert_eq!(Some(&String::from("yes")), extra.get("ALLOW_GUESTS"));
+    }
+
+    #[test]
+    fn test_get_extra_typed () {
+        let mut config = MinimalConfig::for_tests("/");
+        config.extra.insert(String::from("MAX_PLAYERS"), String::from("42"));
+        config.extra.insert(String::from("ALLOW_GUESTS"), String::from("on"));
+        config.extra.insert(String::from("MOTD"), String::from("Welcome"));
+
+        assert_eq!(Some("Welcome"), config.get_extra("MOTD"));
+        assert_eq!(Some(42), config.get_extra_u32("MAX_PLAYERS"));
+        assert_eq!(Some(true), config.get_extra_bool("ALLOW_GUESTS"));
+
+        assert_eq!(None, config.get_extra_u32("MOTD"));
+        assert_eq!(None, config.get_extra_bool("MOTD"));
+        assert_eq!(None, config.get_extra("UNKNOWN"));
+    }
+
+    #[test]
+    fn test_choose_service_name () {
+        assert_eq!("quux", choose_service_name(Some(String::from("quux")), "app"));
+        assert_eq!("app", choose_service_name(Some(String::new()), "app"));
+        assert_eq!("app", choose_service_name(None, "app"));
+    }
+
+    #[test]
+    fn test_get_executable_name () {
+        assert_eq!(Some("app"), get_executable_name(Path::new("/usr/local/bin/app")));
+        assert_eq!(Some("app"), get_executable_name(Path::new("/opt/app/app.exe")));
+        assert_eq!(None, get_executable_name(Path::new("/")));
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_service_name_propagates_to_logs () {
+        use log::Record;
+        use logger::format_record_for_service;
+
+        let service_name = choose_service_name(Some(String::from("quux")), "app");
+
+        let line = format_record_for_service(&Record::builder()
+            .args(format_args!("Server started."))
+            .level(Level::Info)
+            .target("runner")
+            .build(), &service_name);
+
+        let value: ::serde_json::Value = ::serde_json::from_str(&line).unwrap();
+        assert_eq!("quux", value["service"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_filter_utf8_variables () {
+        use std::os::unix::ffi::OsStringExt;
+
+        let variables = vec![
+            (OsString::from("LF_EXTRA_MOTD"), OsString::from("Welcome")),
+            (OsString::from("LF_EXTRA_BINARY"), OsString::from_vec(vec![0xC3, 0x28])),
+            (OsString::from_vec(vec![0xFF]), OsString::from("quux")),
+        ];
+
+        let extra = parse_extra_settings(filter_utf8_variables(variables));
+        assert_eq!(1, extra.len());
+        assert_eq!(Some(&String::from("Welcome")), extra.get("MOTD"));
+    }
+
+    #[test]
+    fn test_parse_bool () {
+        assert_eq!(Some(true), parse_bool("1"));
+        assert_eq!(Some(true), parse_bool("True"));
+        assert_eq!(Some(false), parse_bool("off"));
+        assert_eq!(Some(false), parse_bool("no"));
+        assert_eq!(None, parse_bool("maybe"));
+    }
+
+    #[test]
+    fn test_resolved_config_from_request () {
+        let config = MinimalConfig::for_tests("/api");
+
+        let server = ::rocket::ignite().manage(config.resolve());
+        let client = Client::untracked(server).unwrap();
+        let request = client.get("/api/status");
+
+        let resolved = request.inner().guard::<ResolvedConfig>().unwrap();
+        assert_eq!("/api", resolved.entry_point);
+        assert!(!resolved.with_database);
+    }
+
+    #[test]
+    #[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+    fn test_default_config_into_service_without_database () {
+        let config = DefaultConfig::for_tests("postgres://unreachable.invalid/test", "/api", false);
+
+        let service = config.into_service(Vec::new());
+
+        // The minimal service doesn't know any database, and so doesn't probe it
+        assert_eq!("", service.get_config().get_database_url());
+        assert_eq!("/api", service.get_config().get_entry_point());
+        assert_eq!("quux", service.get_config().get_service_name());
+        assert!(service.check_service_configuration().is_ok());
     }
 }
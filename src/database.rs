@@ -6,26 +6,373 @@
 //! Most code comes from the Rocket manual:
 //! <https://rocket.rs/guide/state/#databases>
 
+use diesel::backend::Backend;
 use diesel::Connection;
+use diesel::ConnectionResult;
+use diesel::connection::{AnsiTransactionManager, SimpleConnection};
+#[cfg(feature = "pgsql")]
+use diesel::RunQueryDsl;
+use diesel::debug_query;
+use diesel::deserialize::{Queryable, QueryableByName};
+#[cfg(feature = "pgsql")]
+use diesel::dsl::sql;
+#[cfg(feature = "mysql")]
+use diesel::mysql::MysqlConnection;
+#[cfg(feature = "pgsql")]
 use diesel::pg::PgConnection;
+use diesel::query_builder::{AsQuery, QueryFragment, QueryId};
 use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::ManageConnection;
 use diesel::r2d2::Pool;
 use diesel::r2d2::PooledConnection;
+use diesel::result::QueryResult;
+use diesel::sql_types::HasSqlType;
+#[cfg(feature = "pgsql")]
+use diesel::sql_types::Text;
+#[cfg(feature = "sqlite")]
+use diesel::sqlite::SqliteConnection;
+#[cfg(feature = "migrations")]
+use diesel_migrations::RunMigrationsError;
+#[cfg(feature = "pgsql")]
+use api::replies::{ApiJsonResponse, ApiResponse};
+#[cfg(feature = "pgsql")]
+use config::get_named_database_pool_size;
 use ErrorResult;
+use log::{Level, Log, Record};
 use r2d2::Error as PoolError;
 use rocket::http::Status;
+#[cfg(all(feature = "pgsql", feature = "serialization"))]
+use rocket::Data;
+#[cfg(all(feature = "pgsql", feature = "serialization"))]
+use rocket::handler::Outcome as HandlerOutcome;
 use rocket::Outcome;
 use rocket::request::FromRequest;
 use rocket::request::Outcome as RequestOutcome;
 use rocket::Request;
+#[cfg(feature = "pgsql")]
+use rocket::Rocket;
 use rocket::State;
+#[cfg(all(feature = "pgsql", feature = "serialization"))]
+use rocket_contrib::json::Json;
+#[cfg(feature = "serialization")]
+use serde::Serialize;
+#[cfg(feature = "migrations")]
+use std::error::Error;
+use std::fmt::Arguments;
+#[cfg(feature = "migrations")]
+use std::fmt::{Display, Formatter, Result as FmtResult};
+#[cfg(feature = "migrations")]
+use std::io::Write;
+#[cfg(feature = "pgsql")]
+use std::marker::PhantomData;
 use std::ops::Deref;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
 
 /*   -------------------------------------------------------------
      Custom types
      - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
 
-pub type PostgreSQLPool = Pool<ConnectionManager<PgConnection>>;
+#[cfg(feature = "pgsql")]
+pub type PostgreSQLPool = Pool<ConnectionManager<LoggingConnection<PgConnection>>>;
+
+#[cfg(feature = "pgsql")]
+pub type PostgreSQLPooledConnection = PooledConnection<ConnectionManager<LoggingConnection<PgConnection>>>;
+
+#[cfg(feature = "mysql")]
+pub type MySQLPool = Pool<ConnectionManager<LoggingConnection<MysqlConnection>>>;
+
+#[cfg(feature = "mysql")]
+pub type MySQLPooledConnection = PooledConnection<ConnectionManager<LoggingConnection<MysqlConnection>>>;
+
+#[cfg(feature = "sqlite")]
+pub type SqlitePool = Pool<ConnectionManager<LoggingConnection<SqliteConnection>>>;
+
+#[cfg(feature = "sqlite")]
+pub type SqlitePooledConnection = PooledConnection<ConnectionManager<LoggingConnection<SqliteConnection>>>;
+
+/*   -------------------------------------------------------------
+     Database backend
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The database server to connect to, as determined by the scheme of the database URL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DatabaseBackend {
+    #[cfg(feature = "pgsql")]
+    PostgreSQL,
+
+    #[cfg(feature = "mysql")]
+    MySQL,
+
+    #[cfg(feature = "sqlite")]
+    SQLite,
+}
+
+impl DatabaseBackend {
+    /// Determines the backend from the database URL scheme.
+    ///
+    /// With the `mysql` feature, `mysql://` URLs use MySQL. With the `sqlite` feature,
+    /// `sqlite://` URLs, `file:` URIs and `:memory:` use SQLite. With the `pgsql` feature,
+    /// any other URL is handed to PostgreSQL, which also accepts connection strings without scheme.
+    ///
+    /// Returns None if the backend for this URL hasn't been enabled.
+    #[cfg_attr(not(any(feature = "mysql", feature = "sqlite")), allow(unused_variables))]
+    pub fn from_url (url: &str) -> Option<Self> {
+        #[cfg(feature = "mysql")]
+        {
+            if url.starts_with("mysql://") {
+                return Some(DatabaseBackend::MySQL);
+            }
+        }
+
+        #[cfg(feature = "sqlite")]
+        {
+            if is_sqlite_url(url) {
+                return Some(DatabaseBackend::SQLite);
+            }
+        }
+
+        #[cfg(feature = "pgsql")]
+        {
+            Some(DatabaseBackend::PostgreSQL)
+        }
+
+        #[cfg(not(feature = "pgsql"))]
+        {
+            None
+        }
+    }
+}
+
+/*   -------------------------------------------------------------
+     Connection acquisition
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The default duration, in milliseconds, from which getting a connection is considered slow
+pub const DEFAULT_SLOW_ACQUIRE_THRESHOLD: u64 = 500;
+
+/// The effective slow acquire threshold, when overridden by the configuration
+static SLOW_ACQUIRE_THRESHOLD: OnceLock<u64> = OnceLock::new();
+
+/// Sets the slow acquire threshold in milliseconds, returning false if it has already been set.
+pub fn set_slow_acquire_threshold (threshold: u64) -> bool {
+    SLOW_ACQUIRE_THRESHOLD.set(threshold).is_ok()
+}
+
+/// Gets the slow acquire threshold in milliseconds.
+pub fn get_slow_acquire_threshold () -> u64 {
+    *SLOW_ACQUIRE_THRESHOLD.get().unwrap_or(&DEFAULT_SLOW_ACQUIRE_THRESHOLD)
+}
+
+/// Gets a connection from the pool, logging how long it took.
+///
+/// That allows to diagnose pool pressure before it leads to 503 responses.
+pub fn acquire_connection<M: ManageConnection>(pool: &Pool<M>, pool_name: &str) -> Result<PooledConnection<M>, PoolError> {
+    acquire_connection_with(pool, pool_name, get_slow_acquire_threshold(), None)
+}
+
+/// Gets a connection from the pool, logging how long it took to the specified logger,
+/// or the global one, and warning if it exceeds the threshold in milliseconds.
+fn acquire_connection_with<M: ManageConnection>(pool: &Pool<M>, pool_name: &str, threshold: u64, logger: Option<&dyn Log>) -> Result<PooledConnection<M>, PoolError> {
+    let start = Instant::now();
+    let connection = pool.get();
+    log_connection_acquisition(pool_name, start.elapsed(), threshold, logger);
+
+    connection
+}
+
+/// Logs the time spent to get a connection, and warns if it's slow.
+///
+/// Returns true if the acquisition is considered slow.
+fn log_connection_acquisition(pool_name: &str, elapsed: Duration, threshold: u64, logger: Option<&dyn Log>) -> bool {
+    let elapsed_ms = elapsed.as_millis();
+    log_record(logger, Level::Debug, "database", format_args!("Connection acquired from the {} pool in {} ms.", pool_name, elapsed_ms));
+
+    let is_slow = elapsed_ms > threshold as u128;
+    if is_slow {
+        log_record(logger, Level::Warn, "database", format_args!("Slow connection acquisition from the {} pool: {} ms.", pool_name, elapsed_ms));
+    }
+
+    is_slow
+}
+
+/// Logs a record to the specified logger, or to the global one if its level is enabled.
+fn log_record(logger: Option<&dyn Log>, level: Level, target: &str, message: Arguments) {
+    let logger = match logger {
+        Some(logger) => logger,
+        None if level <= ::log::max_level() => ::log::logger(),
+        None => return,
+    };
+
+    logger.log(&Record::builder()
+        .args(message)
+        .level(level)
+        .target(target)
+        .build());
+}
+
+/*   -------------------------------------------------------------
+     Query logging
+
+     :: LoggingConnection
+     :: Deref
+     :: SimpleConnection
+     :: Connection
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// Whether the executed queries should be logged, when enabled by the configuration
+static LOG_QUERIES: OnceLock<bool> = OnceLock::new();
+
+/// Enables or disables the query logging, returning false if it has already been set.
+pub fn set_log_queries (enabled: bool) -> bool {
+    LOG_QUERIES.set(enabled).is_ok()
+}
+
+/// Determines if the query logging is enabled. It's disabled by default.
+pub fn should_log_queries () -> bool {
+    *LOG_QUERIES.get().unwrap_or(&false)
+}
+
+/// A connection logging the executed queries, with their bind parameters and duration,
+/// at debug level under the `sql` target, when the query logging is enabled.
+///
+/// The pools built by this crate use this wrapper, so the queries of the handlers
+/// are logged when the `DATABASE_LOG_QUERIES` variable is set. When the query logging
+/// is disabled, the queries are directly run by the wrapped connection.
+///
+/// As it derefs to the wrapped connection, functions expecting a `&PgConnection`
+/// can still be called, but the queries they run aren't logged: they should take
+/// a generic `Connection<Backend = Pg>` instead.
+pub struct LoggingConnection<C> {
+    connection: C,
+    enabled: bool,
+    logger: Option<&'static dyn Log>,
+}
+
+impl<C> LoggingConnection<C> {
+    /// Wraps a connection, logging the queries if the query logging is enabled.
+    pub fn new (connection: C) -> Self {
+        Self {
+            connection,
+            enabled: should_log_queries(),
+            logger: None,
+        }
+    }
+
+    /// Allows to enable or disable the query logging for this connection.
+    pub fn with_query_logging (mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+
+        self
+    }
+
+    /// Allows to log the queries to another logger than the global one.
+    pub fn with_logger (mut self, logger: &'static dyn Log) -> Self {
+        self.logger = Some(logger);
+
+        self
+    }
+
+    /// Consumes the wrapper and returns the connection
+    pub fn into_inner (self) -> C {
+        self.connection
+    }
+
+    /// Gets the SQL to log for a query, or None if the query logging is disabled.
+    fn describe_query<F> (&self, describe: F) -> Option<String>
+        where F: FnOnce() -> String
+    {
+        if self.enabled { Some(describe()) } else { None }
+    }
+
+    /// Runs a query on the wrapped connection, logging its SQL and duration if described.
+    fn run_logged<T, F> (&self, sql: Option<String>, run: F) -> T
+        where F: FnOnce(&C) -> T
+    {
+        let sql = match sql {
+            Some(sql) => sql,
+            None => return run(&self.connection),
+        };
+
+        let start = Instant::now();
+        let result = run(&self.connection);
+        self.log_query(&sql, start.elapsed());
+
+        result
+    }
+
+    fn log_query (&self, sql: &str, elapsed: Duration) {
+        log_record(self.logger, Level::Debug, "sql", format_args!("{} ({} ms)", sql, elapsed.as_millis()));
+    }
+}
+
+impl<C> Deref for LoggingConnection<C> {
+    type Target = C;
+
+    fn deref(&self) -> &Self::Target {
+        &self.connection
+    }
+}
+
+impl<C: SimpleConnection> SimpleConnection for LoggingConnection<C> {
+    fn batch_execute(&self, query: &str) -> QueryResult<()> {
+        let sql = self.describe_query(|| query.to_string());
+
+        self.run_logged(sql, |connection| connection.batch_execute(query))
+    }
+}
+
+impl<C> Connection for LoggingConnection<C>
+    where C: Connection<TransactionManager = AnsiTransactionManager>,
+          <C::Backend as Backend>::QueryBuilder: Default
+{
+    type Backend = C::Backend;
+    type TransactionManager = AnsiTransactionManager;
+
+    fn establish(database_url: &str) -> ConnectionResult<Self> {
+        C::establish(database_url).map(LoggingConnection::new)
+    }
+
+    fn execute(&self, query: &str) -> QueryResult<usize> {
+        let sql = self.describe_query(|| query.to_string());
+
+        self.run_logged(sql, |connection| connection.execute(query))
+    }
+
+    fn query_by_index<T, U>(&self, source: T) -> QueryResult<Vec<U>>
+        where T: AsQuery,
+              T::Query: QueryFragment<Self::Backend> + QueryId,
+              Self::Backend: HasSqlType<T::SqlType>,
+              U: Queryable<T::SqlType, Self::Backend>
+    {
+        let query = source.as_query();
+        let sql = self.describe_query(|| debug_query::<Self::Backend, _>(&query).to_string());
+
+        self.run_logged(sql, |connection| connection.query_by_index(query))
+    }
+
+    fn query_by_name<T, U>(&self, source: &T) -> QueryResult<Vec<U>>
+        where T: QueryFragment<Self::Backend> + QueryId,
+              U: QueryableByName<Self::Backend>
+    {
+        let sql = self.describe_query(|| debug_query::<Self::Backend, _>(source).to_string());
+
+        self.run_logged(sql, |connection| connection.query_by_name(source))
+    }
+
+    fn execute_returning_count<T>(&self, source: &T) -> QueryResult<usize>
+        where T: QueryFragment<Self::Backend> + QueryId
+    {
+        let sql = self.describe_query(|| debug_query::<Self::Backend, _>(source).to_string());
+
+        self.run_logged(sql, |connection| connection.execute_returning_count(source))
+    }
+
+    fn transaction_manager(&self) -> &Self::TransactionManager {
+        self.connection.transaction_manager()
+    }
+}
 
 /*   -------------------------------------------------------------
      DatabaseConnection
@@ -35,14 +382,19 @@ pub type PostgreSQLPool = Pool<ConnectionManager<PgConnection>>;
      - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
 
 /// Represents an established working database connection from the pool
-pub struct DatabaseConnection(pub PooledConnection<ConnectionManager<PgConnection>>);
+///
+/// If no connection can be acquired, the request fails with a 503 Service Unavailable,
+/// answered with a Retry-After header by the catcher registered by the kernel.
+#[cfg(feature = "pgsql")]
+pub struct DatabaseConnection(pub PostgreSQLPooledConnection);
 
+#[cfg(feature = "pgsql")]
 impl<'a, 'r> FromRequest<'a, 'r> for DatabaseConnection {
     type Error = ();
 
     fn from_request(request: &'a Request<'r>) -> RequestOutcome<Self, Self::Error> {
         let pool = request.guard::<State<PostgreSQLPool>>()?;
-        match pool.get() {
+        match acquire_connection(&pool, "default") {
             Ok(connection) => Outcome::Success(DatabaseConnection(connection)),
             Err(error) => {
                 warn!(target:"request", "Can't get a connection from the pool: {}", error);
@@ -53,8 +405,9 @@ impl<'a, 'r> FromRequest<'a, 'r> for DatabaseConnection {
     }
 }
 
+#[cfg(feature = "pgsql")]
 impl Deref for DatabaseConnection {
-    type Target = PgConnection;
+    type Target = LoggingConnection<PgConnection>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -62,43 +415,1151 @@ impl Deref for DatabaseConnection {
 }
 
 /*   -------------------------------------------------------------
-     Helper methods to get a database connection
+     TransactionConnection
+
+     :: FromRequest
+     :: Deref
      - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
 
-/// Builds a r2d2 database pool, to be used in a request guard or a managed state.
+/// Represents an established working database connection from the pool,
+/// to run several writes in a transaction.
+///
+/// If no connection can be acquired, the request fails with a 503 Service Unavailable,
+/// as for `DatabaseConnection`.
 ///
 /// # Examples
 ///
 /// ```
-/// rocket::ignite()
-///    .manage(initialize_database_pool(String::from("postgres://::1/test"), 4)?)
-///    .mount("/", routes)
-///    .launch();
+/// #[post("/games", data="<game>")]
+/// pub fn post_game(connection: TransactionConnection, game: Json<NewGame>) -> ApiJsonResponse<Game> {
+///     connection.run(|connection| {
+///         let game = insert_game(connection, game.into_inner())?;
+///         update_players_scores(connection, &game)?;
+///
+///         Ok(game)
+///     }).map(Json)
+/// }
 /// ```
-pub fn initialize_database_pool(url: &str, max_size: u32) -> Result<PostgreSQLPool, PoolError> {
-    let manager = ConnectionManager::<PgConnection>::new(url);
+#[cfg(feature = "pgsql")]
+pub struct TransactionConnection(pub PostgreSQLPooledConnection);
 
-    Pool::builder()
-        .max_size(max_size)
-        .build(manager)
+#[cfg(feature = "pgsql")]
+impl TransactionConnection {
+    /// Runs the queries of the closure in a transaction, committed if the closure succeeds,
+    /// rolled back otherwise, so the writes are never partially applied.
+    ///
+    /// The error is mapped to a status as by `into_json_response`,
+    /// e.g. a 404 when no result is found, or a 409 for a unique violation.
+    pub fn run<F, T> (&self, queries: F) -> Result<T, Status>
+        where F: FnOnce(&LoggingConnection<PgConnection>) -> QueryResult<T>
+    {
+        let response: ApiJsonResponse<T> = self.0
+            .transaction(|| queries(&self.0))
+            .into_json_response();
+
+        response.map(|json| json.into_inner())
+    }
 }
 
-/// Allows to test if it's possible to establish a connection to the database.
+#[cfg(feature = "pgsql")]
+impl<'a, 'r> FromRequest<'a, 'r> for TransactionConnection {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> RequestOutcome<Self, Self::Error> {
+        let connection = request.guard::<DatabaseConnection>()?;
+
+        Outcome::Success(TransactionConnection(connection.0))
+    }
+}
+
+#[cfg(feature = "pgsql")]
+impl Deref for TransactionConnection {
+    type Target = LoggingConnection<PgConnection>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/*   -------------------------------------------------------------
+     MysqlDatabaseConnection
+
+     :: FromRequest
+     :: Deref
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// Represents an established working database connection from the MySQL pool
 ///
-/// The goal is to test early any issue with the connection, and loudly warn or fail
-/// if the database can't be reached.
+/// If no connection can be acquired, the request fails with a 503 Service Unavailable.
+#[cfg(feature = "mysql")]
+pub struct MysqlDatabaseConnection(pub MySQLPooledConnection);
+
+#[cfg(feature = "mysql")]
+impl<'a, 'r> FromRequest<'a, 'r> for MysqlDatabaseConnection {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> RequestOutcome<Self, Self::Error> {
+        let pool = request.guard::<State<MySQLPool>>()?;
+        match acquire_connection(&pool, "default") {
+            Ok(connection) => Outcome::Success(MysqlDatabaseConnection(connection)),
+            Err(error) => {
+                warn!(target:"request", "Can't get a connection from the pool: {}", error);
+
+                Outcome::Failure((Status::ServiceUnavailable, ()))
+            },
+        }
+    }
+}
+
+#[cfg(feature = "mysql")]
+impl Deref for MysqlDatabaseConnection {
+    type Target = LoggingConnection<MysqlConnection>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/*   -------------------------------------------------------------
+     SqliteDatabaseConnection
+
+     :: FromRequest
+     :: Deref
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// Represents an established working database connection from the SQLite pool
+///
+/// If no connection can be acquired, the request fails with a 503 Service Unavailable.
+#[cfg(feature = "sqlite")]
+pub struct SqliteDatabaseConnection(pub SqlitePooledConnection);
+
+#[cfg(feature = "sqlite")]
+impl<'a, 'r> FromRequest<'a, 'r> for SqliteDatabaseConnection {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> RequestOutcome<Self, Self::Error> {
+        let pool = request.guard::<State<SqlitePool>>()?;
+        match acquire_connection(&pool, "default") {
+            Ok(connection) => Outcome::Success(SqliteDatabaseConnection(connection)),
+            Err(error) => {
+                warn!(target:"request", "Can't get a connection from the pool: {}", error);
+
+                Outcome::Failure((Status::ServiceUnavailable, ()))
+            },
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Deref for SqliteDatabaseConnection {
+    type Target = LoggingConnection<SqliteConnection>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/*   -------------------------------------------------------------
+     Named pools
+
+     Allow to manage several pools in the same server.
+
+     :: PoolName
+     :: NamedPool
+     :: NamedDatabaseConnection
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// This trait allows to identify a database pool, so several pools
+/// can be managed in the same server without colliding in the state.
 ///
 /// # Examples
 ///
 /// ```
-/// // Initial connection to test if the database configuration works
-/// {
-///     test_database_connection(&config.database_url)?;
-///     info!(target: "runner", "Connection to database established.");
+/// pub struct Analytics;
+///
+/// impl PoolName for Analytics {
+///     const NAME: &'static str = "analytics";
 /// }
+///
+/// pub type AnalyticsPool = NamedPool<Analytics>;
+/// pub type AnalyticsConnection = NamedDatabaseConnection<Analytics>;
 /// ```
-pub fn test_database_connection(database_url: &str) -> ErrorResult<()> {
-    PgConnection::establish(database_url)?;
+#[cfg(feature = "pgsql")]
+pub trait PoolName: Send + Sync + 'static {
+    const NAME: &'static str;
+}
 
-    Ok(())
+/// Represents a database pool identified by a name, to be used as a managed state.
+///
+/// As Rocket resolves the state by type, each name gives a distinct `State<NamedPool<N>>`.
+#[cfg(feature = "pgsql")]
+pub struct NamedPool<N: PoolName> {
+    pub pool: PostgreSQLPool,
+    name: PhantomData<N>,
+}
+
+#[cfg(feature = "pgsql")]
+impl<N: PoolName> NamedPool<N> {
+    pub fn new (pool: PostgreSQLPool) -> Self {
+        Self {
+            pool,
+            name: PhantomData,
+        }
+    }
+
+    pub fn get_name (&self) -> &'static str {
+        N::NAME
+    }
+}
+
+#[cfg(feature = "pgsql")]
+impl<N: PoolName> Deref for NamedPool<N> {
+    type Target = PostgreSQLPool;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pool
+    }
+}
+
+/// Represents an established working database connection from a named pool
+#[cfg(feature = "pgsql")]
+pub struct NamedDatabaseConnection<N: PoolName>(pub PostgreSQLPooledConnection, PhantomData<N>);
+
+#[cfg(feature = "pgsql")]
+impl<'a, 'r, N: PoolName> FromRequest<'a, 'r> for NamedDatabaseConnection<N> {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> RequestOutcome<Self, Self::Error> {
+        let pool = request.guard::<State<NamedPool<N>>>()?;
+        match acquire_connection(&pool, N::NAME) {
+            Ok(connection) => Outcome::Success(NamedDatabaseConnection(connection, PhantomData)),
+            Err(error) => {
+                warn!(target:"request", "Can't get a connection from the {} pool: {}", N::NAME, error);
+
+                Outcome::Failure((Status::ServiceUnavailable, ()))
+            },
+        }
+    }
+}
+
+#[cfg(feature = "pgsql")]
+impl<N: PoolName> Deref for NamedDatabaseConnection<N> {
+    type Target = LoggingConnection<PgConnection>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Registers a pool as a named managed state of the server.
+///
+/// # Examples
+///
+/// ```
+/// let server = rocket::ignite();
+/// let server = manage_named_pool::<Main>(server, initialize_database_pool(main_url, 4)?);
+/// let server = manage_named_pool::<Analytics>(server, initialize_database_pool(analytics_url, 2)?);
+/// ```
+#[cfg(feature = "pgsql")]
+pub fn manage_named_pool<N: PoolName>(server: Rocket, pool: PostgreSQLPool) -> Rocket {
+    server.manage(NamedPool::<N>::new(pool))
+}
+
+/// Builds a r2d2 database pool for a named database, sized according the
+/// `DATABASE_POOL_SIZE_<NAME>` variable, or `DATABASE_POOL_SIZE`.
+///
+/// # Examples
+///
+/// ```
+/// let server = manage_named_pool::<Analytics>(server, initialize_named_database_pool::<Analytics>(analytics_url)?);
+/// ```
+#[cfg(feature = "pgsql")]
+pub fn initialize_named_database_pool<N: PoolName>(url: &str) -> Result<PostgreSQLPool, PoolError> {
+    initialize_database_pool(url, get_named_database_pool_size(N::NAME))
+}
+
+/*   -------------------------------------------------------------
+     Helper methods to get a database connection
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The r2d2 settings of a pool, beyond its size.
+///
+/// When a setting is None, the r2d2 default value is used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolOptions {
+    /// The time to wait for a connection before failing, 30 seconds by default
+    pub connection_timeout: Option<Duration>,
+
+    /// The number of idle connections to maintain, by default the pool size
+    pub min_idle: Option<u32>,
+
+    /// Whether to check a connection is alive before handing it, true by default
+    pub test_on_check_out: Option<bool>,
+}
+
+/// Builds a r2d2 database pool, to be used in a request guard or a managed state.
+///
+/// # Examples
+///
+/// ```
+/// rocket::ignite()
+///    .manage(initialize_database_pool(String::from("postgres://::1/test"), 4)?)
+///    .mount("/", routes)
+///    .launch();
+/// ```
+#[cfg(feature = "pgsql")]
+pub fn initialize_database_pool(url: &str, max_size: u32) -> Result<PostgreSQLPool, PoolError> {
+    initialize_pool_with_options::<LoggingConnection<PgConnection>>(url, max_size, &PoolOptions::default())
+}
+
+/// Builds a r2d2 database pool for any Diesel connection type, with the specified settings.
+///
+/// A short connection timeout allows the requests to fail fast with a 503
+/// when the database is down.
+///
+/// # Examples
+///
+/// ```
+/// let options = PoolOptions {
+///     connection_timeout: Some(Duration::from_secs(2)),
+///     ..PoolOptions::default()
+/// };
+///
+/// let pool = initialize_pool_with_options::<LoggingConnection<PgConnection>>("postgres://::1/test", 4, &options)?;
+/// ```
+pub fn initialize_pool_with_options<C>(url: &str, max_size: u32, options: &PoolOptions) -> Result<Pool<ConnectionManager<C>>, PoolError>
+    where ConnectionManager<C>: ManageConnection
+{
+    build_pool_builder(max_size, options)
+        .build(ConnectionManager::<C>::new(url))
+}
+
+/// Prepares a r2d2 pool builder with the specified size and settings.
+fn build_pool_builder<M: ManageConnection>(max_size: u32, options: &PoolOptions) -> ::r2d2::Builder<M> {
+    let mut builder = Pool::builder()
+        .max_size(max_size)
+        .min_idle(options.min_idle);
+
+    if let Some(timeout) = options.connection_timeout {
+        builder = builder.connection_timeout(timeout);
+    }
+
+    if let Some(test_on_check_out) = options.test_on_check_out {
+        builder = builder.test_on_check_out(test_on_check_out);
+    }
+
+    builder
+}
+
+/// Builds a r2d2 MySQL database pool, to be used in a request guard or a managed state.
+///
+/// # Examples
+///
+/// ```
+/// rocket::ignite()
+///    .manage(initialize_mysql_pool("mysql://localhost/test", 4)?)
+///    .mount("/", routes)
+///    .launch();
+/// ```
+#[cfg(feature = "mysql")]
+pub fn initialize_mysql_pool(url: &str, max_size: u32) -> Result<MySQLPool, PoolError> {
+    initialize_pool_with_options::<LoggingConnection<MysqlConnection>>(url, max_size, &PoolOptions::default())
+}
+
+/// Builds a r2d2 SQLite database pool, to be used in a request guard or a managed state.
+///
+/// The path can be a file path, a `file:` URI, or `:memory:`. As each connection
+/// to `:memory:` opens a distinct database, such a pool is limited to one connection.
+///
+/// # Examples
+///
+/// ```
+/// rocket::ignite()
+///    .manage(initialize_sqlite_pool("/var/lib/app/test.db", 4)?)
+///    .mount("/", routes)
+///    .launch();
+/// ```
+#[cfg(feature = "sqlite")]
+pub fn initialize_sqlite_pool(path: &str, max_size: u32) -> Result<SqlitePool, PoolError> {
+    let path = get_sqlite_path(path);
+
+    initialize_pool_with_options::<LoggingConnection<SqliteConnection>>(path, get_sqlite_pool_size(path, max_size), &PoolOptions::default())
+}
+
+/// Gets the size of a SQLite pool, limited to one connection for in-memory databases.
+///
+/// Each connection to `:memory:` opens a distinct empty database, so with several
+/// connections, the requests wouldn't see the data written through another one.
+/// That's also the case for `file:` URIs with `mode=memory`, unless `cache=shared` is set.
+#[cfg(feature = "sqlite")]
+pub fn get_sqlite_pool_size(path: &str, max_size: u32) -> u32 {
+    if is_sqlite_private_memory_database(path) && max_size > 1 {
+        warn!(target: "database", "An in-memory SQLite database can't be shared between connections, the pool size has been lowered to 1.");
+
+        return 1;
+    }
+
+    max_size
+}
+
+/// Determines if a SQLite path opens an in-memory database not shared between connections.
+#[cfg(feature = "sqlite")]
+fn is_sqlite_private_memory_database(path: &str) -> bool {
+    if path == ":memory:" {
+        return true;
+    }
+
+    if !path.starts_with("file:") {
+        return false;
+    }
+
+    let (file, query) = match path.find('?') {
+        Some(separator) => (&path[..separator], &path[separator + 1..]),
+        None => (path, ""),
+    };
+    let mut parameters = query.split('&');
+
+    let is_memory = file == "file::memory:" || parameters.clone().any(|parameter| parameter == "mode=memory");
+
+    is_memory && !parameters.any(|parameter| parameter == "cache=shared")
+}
+
+/// Determines if a database URL points to a SQLite database.
+#[cfg(feature = "sqlite")]
+fn is_sqlite_url(url: &str) -> bool {
+    url == ":memory:" || url.starts_with("sqlite://") || url.starts_with("file:")
+}
+
+/// Gets the path or URI SQLite can open from a database URL,
+/// removing the `sqlite://` scheme if any.
+///
+/// The `file:` URIs are kept as is, so SQLite can read their parameters, e.g. `mode=ro`.
+#[cfg(feature = "sqlite")]
+pub fn get_sqlite_path(url: &str) -> &str {
+    if url.starts_with("sqlite://") {
+        &url["sqlite://".len()..]
+    } else {
+        url
+    }
+}
+
+/// Allows to test if it's possible to open the SQLite database,
+/// given as a file path, a `file:` URI, or `:memory:`.
+#[cfg(feature = "sqlite")]
+pub fn test_sqlite_connection(url: &str) -> ErrorResult<()> {
+    SqliteConnection::establish(get_sqlite_path(url))?;
+
+    Ok(())
+}
+
+/// Allows to test if it's possible to establish a connection to the database.
+///
+/// The backend is determined by the URL scheme.
+///
+/// The goal is to test early any issue with the connection, and loudly warn or fail
+/// if the database can't be reached.
+///
+/// # Examples
+///
+/// ```
+/// // Initial connection to test if the database configuration works
+/// {
+///     test_database_connection(&config.database_url)?;
+///     info!(target: "runner", "Connection to database established.");
+/// }
+/// ```
+pub fn test_database_connection(database_url: &str) -> ErrorResult<()> {
+    let backend = DatabaseBackend::from_url(database_url)
+        .ok_or("No enabled database backend can connect to this database URL.")?;
+
+    match backend {
+        #[cfg(feature = "pgsql")]
+        DatabaseBackend::PostgreSQL => { PgConnection::establish(database_url)?; },
+
+        #[cfg(feature = "mysql")]
+        DatabaseBackend::MySQL => { MysqlConnection::establish(database_url)?; },
+
+        #[cfg(feature = "sqlite")]
+        DatabaseBackend::SQLite => test_sqlite_connection(database_url)?,
+    }
+
+    Ok(())
+}
+
+/// Adds the `application_name` parameter to a PostgreSQL URL, so the connections
+/// of the service can be identified in `pg_stat_activity`.
+///
+/// The URL is kept as is if it already sets an application name, or if it isn't
+/// a `postgres://` or `postgresql://` URL.
+///
+/// # Examples
+///
+/// ```
+/// let url = with_application_name("postgres://localhost/app", config.get_service_name());
+/// ```
+#[cfg(feature = "pgsql")]
+pub fn with_application_name(url: &str, application_name: &str) -> String {
+    let is_uri = url.starts_with("postgres://") || url.starts_with("postgresql://");
+    if !is_uri || has_query_parameter(url, "application_name") {
+        return String::from(url);
+    }
+
+    let separator = if url.contains('?') { '&' } else { '?' };
+
+    format!("{}{}application_name={}", url, separator, encode_uri_parameter(application_name))
+}
+
+/// Determines if the query string of an URL has a parameter, by name.
+#[cfg(feature = "pgsql")]
+fn has_query_parameter(url: &str, name: &str) -> bool {
+    match url.find('?') {
+        Some(position) => url[position + 1..]
+            .split('&')
+            .any(|parameter| parameter.split('=').next() == Some(name)),
+        None => false,
+    }
+}
+
+/// Percent-encodes a value for a query string, keeping the unreserved characters.
+#[cfg(feature = "pgsql")]
+fn encode_uri_parameter(value: &str) -> String {
+    value.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/*   -------------------------------------------------------------
+     Connection retry
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The longest delay to wait between two connection attempts
+pub const MAX_CONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// How to retry the connection to the database at startup,
+/// e.g. when the application and the database are started together.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConnectRetry {
+    /// The number of connection attempts, at least 1
+    pub attempts: u32,
+
+    /// The delay before the second attempt, doubled after each failed attempt
+    pub base_delay: Duration,
+}
+
+/// By default, the connection is attempted once.
+pub const DEFAULT_CONNECT_RETRY: ConnectRetry = ConnectRetry {
+    attempts: 1,
+    base_delay: Duration::from_millis(500),
+};
+
+/// The connection retry settings, when overridden by the configuration
+static CONNECT_RETRY: OnceLock<ConnectRetry> = OnceLock::new();
+
+/// Sets the connection retry settings, returning false if they have already been set.
+pub fn set_connect_retry (retry: ConnectRetry) -> bool {
+    CONNECT_RETRY.set(retry).is_ok()
+}
+
+/// Gets the connection retry settings.
+pub fn get_connect_retry () -> ConnectRetry {
+    *CONNECT_RETRY.get().unwrap_or(&DEFAULT_CONNECT_RETRY)
+}
+
+/// Allows to test if it's possible to establish a connection to the database,
+/// retrying with an exponential backoff.
+///
+/// Each failed attempt is logged. The error of the last attempt is returned
+/// once the attempts are exhausted.
+///
+/// # Examples
+///
+/// ```
+/// // Waits up to 0.5 + 1 + 2 + 4 seconds for the database to be up
+/// test_database_connection_with_retry(&config.database_url, 5, Duration::from_millis(500))?;
+/// ```
+pub fn test_database_connection_with_retry(database_url: &str, attempts: u32, base_delay: Duration) -> ErrorResult<()> {
+    retry_with_backoff(attempts, base_delay, || test_database_connection(database_url))
+}
+
+/// Calls the closure until it succeeds or the attempts are exhausted,
+/// doubling the delay between the attempts.
+fn retry_with_backoff<F>(attempts: u32, base_delay: Duration, mut attempt: F) -> ErrorResult<()>
+    where F: FnMut() -> ErrorResult<()>
+{
+    let attempts = attempts.max(1);
+    let mut attempt_number = 1;
+
+    loop {
+        let error = match attempt() {
+            Ok(()) => return Ok(()),
+            Err(error) => error,
+        };
+
+        if attempt_number >= attempts {
+            return Err(error);
+        }
+
+        let delay = compute_backoff_delay(base_delay, attempt_number - 1);
+        warn!(target: "database", "Can't connect to the database (attempt {}/{}): {}. Retrying in {} ms.", attempt_number, attempts, error, delay.as_millis());
+        thread::sleep(delay);
+
+        attempt_number += 1;
+    }
+}
+
+/// Computes the delay after the nth failed attempt, counted from 0, up to `MAX_CONNECT_DELAY`.
+fn compute_backoff_delay(base_delay: Duration, failed_attempts: u32) -> Duration {
+    2u32.checked_pow(failed_attempts)
+        .and_then(|factor| base_delay.checked_mul(factor))
+        .map_or(MAX_CONNECT_DELAY, |delay| delay.min(MAX_CONNECT_DELAY))
+}
+
+/*   -------------------------------------------------------------
+     Migrations
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The runner of the migrations embedded in the application by the `embed_migrations!`
+/// macro of diesel_migrations, i.e. `embedded_migrations::run_with_output`.
+#[cfg(feature = "migrations")]
+pub type EmbeddedMigrations = fn(&PgConnection, &mut dyn Write) -> Result<(), RunMigrationsError>;
+
+/// An error applying the pending migrations.
+#[cfg(feature = "migrations")]
+#[derive(Debug)]
+pub struct MigrationError(pub String);
+
+#[cfg(feature = "migrations")]
+impl Display for MigrationError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "Can't run the database migrations: {}", self.0)
+    }
+}
+
+#[cfg(feature = "migrations")]
+impl Error for MigrationError {}
+
+/// Runs the pending embedded migrations, logging each applied migration.
+///
+/// # Examples
+///
+/// ```
+/// embed_migrations!();
+///
+/// let connection = pool.get()?;
+/// run_embedded_migrations(&connection, embedded_migrations::run_with_output)?;
+/// ```
+#[cfg(feature = "migrations")]
+pub fn run_embedded_migrations(connection: &PgConnection, migrations: EmbeddedMigrations) -> Result<(), MigrationError> {
+    let mut output = Vec::new();
+    let result = migrations(connection, &mut output);
+
+    for line in String::from_utf8_lossy(&output).lines() {
+        info!(target: "database", "{}", line);
+    }
+
+    result.map_err(|error| MigrationError(error.to_string()))
+}
+
+/*   -------------------------------------------------------------
+     Pool status
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The state of a pool, to monitor its exhaustion.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+pub struct PoolStatus {
+    /// The number of connections currently managed by the pool, in use or idle
+    pub connections: u32,
+
+    /// The number of idle connections, ready to be used
+    pub idle_connections: u32,
+
+    /// The maximum number of connections the pool can manage
+    pub max_size: u32,
+}
+
+/// Gets the state of a pool.
+pub fn pool_status<M: ManageConnection>(pool: &Pool<M>) -> PoolStatus {
+    let state = pool.state();
+
+    PoolStatus {
+        connections: state.connections,
+        idle_connections: state.idle_connections,
+        max_size: pool.max_size(),
+    }
+}
+
+/// Answers the status of the pool managed by the server as JSON.
+///
+/// # Examples
+///
+/// ```
+/// let mut routes = routes![get_players];
+/// routes.push(Route::new(Method::Get, "/metrics/pool", pool_status_handler));
+///
+/// DefaultApplication::start_application(routes);
+/// ```
+#[cfg(all(feature = "pgsql", feature = "serialization"))]
+pub fn pool_status_handler<'r>(request: &'r Request, _data: Data) -> HandlerOutcome<'r> {
+    match request.guard::<State<PostgreSQLPool>>() {
+        Outcome::Success(pool) => HandlerOutcome::from(request, Json(pool_status(&pool))),
+        _ => HandlerOutcome::Failure(Status::InternalServerError),
+    }
+}
+
+/*   -------------------------------------------------------------
+     Pool size check
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The fraction of the server max_connections from which the pool size is considered oversized
+pub const MAX_CONNECTIONS_WARNING_RATIO: f64 = 0.5;
+
+/// Whether the pool size should be compared to the server max_connections, when enabled by the configuration
+static CHECK_MAX_CONNECTIONS: OnceLock<bool> = OnceLock::new();
+
+/// Enables or disables the pool size check, returning false if it has already been set.
+pub fn set_check_max_connections (enabled: bool) -> bool {
+    CHECK_MAX_CONNECTIONS.set(enabled).is_ok()
+}
+
+/// Determines if the pool size check is enabled. It's disabled by default.
+pub fn should_check_max_connections () -> bool {
+    *CHECK_MAX_CONNECTIONS.get().unwrap_or(&false)
+}
+
+/// Compares the pool size to the max_connections setting of the PostgreSQL server,
+/// and warns if the pool would use a large fraction of the available connections.
+///
+/// This check is best-effort: returns None if the setting can't be read,
+/// or if the server isn't PostgreSQL, or else if the pool is oversized.
+#[cfg(feature = "pgsql")]
+pub fn check_max_connections(database_url: &str, pool_size: u32) -> Option<bool> {
+    if DatabaseBackend::from_url(database_url) != Some(DatabaseBackend::PostgreSQL) {
+        return None;
+    }
+
+    let max_connections = match query_max_connections(database_url) {
+        Ok(max_connections) => max_connections,
+        Err(error) => {
+            info!(target: "database", "Can't read max_connections to check the pool size: {}", error);
+
+            return None;
+        }
+    };
+
+    let is_oversized = is_pool_oversized(pool_size, max_connections);
+    if is_oversized {
+        warn!(target: "database", "The pool size {} is a large fraction of the {} connections allowed by the server. Several replicas could exhaust them.", pool_size, max_connections);
+    }
+
+    Some(is_oversized)
+}
+
+#[cfg(feature = "pgsql")]
+fn query_max_connections(database_url: &str) -> ErrorResult<u32> {
+    let connection = PgConnection::establish(database_url)?;
+    let max_connections = ::diesel::select(sql::<Text>("current_setting('max_connections')"))
+        .get_result::<String>(&connection)?;
+
+    Ok(max_connections.parse::<u32>()?)
+}
+
+#[cfg(feature = "pgsql")]
+fn is_pool_oversized(pool_size: u32, max_connections: u32) -> bool {
+    pool_size as f64 > max_connections as f64 * MAX_CONNECTIONS_WARNING_RATIO
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "sqlite")]
+    use logger::CapturingLogger;
+
+    #[cfg(feature = "pgsql")]
+    struct Main;
+
+    #[cfg(feature = "pgsql")]
+    impl PoolName for Main {
+        const NAME: &'static str = "main";
+    }
+
+    #[cfg(feature = "pgsql")]
+    struct Analytics;
+
+    #[cfg(feature = "pgsql")]
+    impl PoolName for Analytics {
+        const NAME: &'static str = "analytics";
+    }
+
+    #[cfg(feature = "pgsql")]
+    fn build_lazy_pool (max_size: u32) -> PostgreSQLPool {
+        let manager = ConnectionManager::<LoggingConnection<PgConnection>>::new("postgres://localhost/test");
+
+        Pool::builder()
+            .max_size(max_size)
+            .min_idle(Some(0))
+            .build_unchecked(manager)
+    }
+
+    #[test]
+    #[cfg(feature = "pgsql")]
+    fn test_database_backend_from_url () {
+        assert_eq!(Some(DatabaseBackend::PostgreSQL), DatabaseBackend::from_url("postgres://localhost/test"));
+        assert_eq!(Some(DatabaseBackend::PostgreSQL), DatabaseBackend::from_url("host=localhost dbname=test"));
+    }
+
+    #[test]
+    #[cfg(feature = "mysql")]
+    fn test_database_backend_from_url_for_mysql () {
+        assert_eq!(Some(DatabaseBackend::MySQL), DatabaseBackend::from_url("mysql://localhost/test"));
+    }
+
+    #[test]
+    #[cfg(feature = "mysql")]
+    fn test_mysql_pool_is_managed () {
+        let manager = ConnectionManager::<LoggingConnection<MysqlConnection>>::new("mysql://localhost/test");
+        let pool: MySQLPool = Pool::builder()
+            .max_size(2)
+            .min_idle(Some(0))
+            .build_unchecked(manager);
+
+        let server = ::rocket::ignite().manage(pool);
+        assert_eq!(2, server.state::<MySQLPool>().expect("MySQL pool should be managed").max_size());
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn test_database_backend_from_url_for_sqlite () {
+        assert_eq!(Some(DatabaseBackend::SQLite), DatabaseBackend::from_url(":memory:"));
+        assert_eq!(Some(DatabaseBackend::SQLite), DatabaseBackend::from_url("sqlite:///tmp/test.db"));
+        assert_eq!(Some(DatabaseBackend::SQLite), DatabaseBackend::from_url("file:test.db?mode=ro"));
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn test_get_sqlite_path () {
+        assert_eq!("/tmp/test.db", get_sqlite_path("sqlite:///tmp/test.db"));
+        assert_eq!("file:test.db?mode=memory", get_sqlite_path("file:test.db?mode=memory"));
+        assert_eq!(":memory:", get_sqlite_path(":memory:"));
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn test_sqlite_connection () {
+        assert!(test_sqlite_connection(":memory:").is_ok());
+        assert!(test_database_connection(":memory:").is_ok());
+        assert!(test_sqlite_connection("/nonexistent.invalid/test.db").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn test_sqlite_connection_with_file_uri () {
+        let path = ::std::env::temp_dir().join("limiting-factor-test-file-uri.db");
+        let url = format!("file:{}?mode=rwc", path.display());
+
+        assert_eq!(Some(DatabaseBackend::SQLite), DatabaseBackend::from_url(&url));
+        assert!(test_database_connection(&url).is_ok());
+
+        let pool = initialize_sqlite_pool(&url, 2).unwrap();
+        assert_eq!(2, pool.max_size());
+        let connection = pool.get().unwrap();
+        let one = ::diesel::select(::diesel::dsl::sql::<::diesel::sql_types::Integer>("1"));
+        assert_eq!(Ok(1), ::diesel::RunQueryDsl::get_result::<i32>(one, &*connection));
+        drop(connection);
+        drop(pool);
+
+        assert!(path.exists(), "SQLite should have created the database file from the URI");
+        ::std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn test_initialize_sqlite_pool () {
+        let pool = initialize_sqlite_pool(":memory:", 1).unwrap();
+
+        assert_eq!(1, pool.max_size());
+        assert!(pool.get().is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn test_get_sqlite_pool_size () {
+        assert_eq!(1, get_sqlite_pool_size(":memory:", 4));
+        assert_eq!(1, get_sqlite_pool_size(":memory:", 1));
+        assert_eq!(4, get_sqlite_pool_size("/tmp/test.db", 4));
+        assert_eq!(4, get_sqlite_pool_size("file:test.db", 4));
+        assert_eq!(1, get_sqlite_pool_size("file::memory:", 4));
+        assert_eq!(1, get_sqlite_pool_size("file:test.db?mode=memory", 4));
+        assert_eq!(4, get_sqlite_pool_size("file:test.db?mode=memory&cache=shared", 4));
+    }
+
+    #[test]
+    #[cfg(feature = "migrations")]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    fn test_run_embedded_migrations () {
+        fn run_migrations (_connection: &PgConnection, output: &mut dyn Write) -> Result<(), RunMigrationsError> {
+            writeln!(output, "Running migration 00000000000000_create_players").unwrap();
+
+            Ok(())
+        }
+
+        fn fail_migrations (_connection: &PgConnection, _output: &mut dyn Write) -> Result<(), RunMigrationsError> {
+            Err(RunMigrationsError::EmptyMigration)
+        }
+
+        // Requires a live database, e.g. TEST_DATABASE_URL=postgres://localhost/test
+        let database_url = ::std::env::var("TEST_DATABASE_URL")
+            .expect("The TEST_DATABASE_URL variable must be set to run this test");
+        let connection = PgConnection::establish(&database_url).unwrap();
+
+        assert!(run_embedded_migrations(&connection, run_migrations).is_ok());
+        assert!(run_embedded_migrations(&connection, fail_migrations).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "pgsql")]
+    fn test_build_pool_builder () {
+        let options = PoolOptions {
+            connection_timeout: Some(Duration::from_secs(2)),
+            min_idle: Some(0),
+            test_on_check_out: None,
+        };
+
+        let manager = ConnectionManager::<LoggingConnection<PgConnection>>::new("postgres://localhost/test");
+        let pool = build_pool_builder(4, &options).build_unchecked(manager);
+
+        assert_eq!(4, pool.max_size());
+        assert_eq!(Some(0), pool.min_idle());
+        assert_eq!(Duration::from_secs(2), pool.connection_timeout());
+    }
+
+    #[test]
+    #[cfg(feature = "pgsql")]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    fn test_transaction_connection_rolls_back () {
+        // Requires a live database, e.g. TEST_DATABASE_URL=postgres://localhost/test
+        let database_url = ::std::env::var("TEST_DATABASE_URL")
+            .expect("The TEST_DATABASE_URL variable must be set to run this test");
+
+        let pool = initialize_database_pool(&database_url, 1).unwrap();
+        let connection = TransactionConnection(pool.get().unwrap());
+        ::diesel::sql_query("CREATE TEMPORARY TABLE lf_transaction_test (id INTEGER PRIMARY KEY)")
+            .execute(&*connection)
+            .unwrap();
+
+        // The second insert violates the primary key, so the first one is rolled back
+        let result = connection.run(|connection| {
+            ::diesel::sql_query("INSERT INTO lf_transaction_test VALUES (1)").execute(connection)?;
+            ::diesel::sql_query("INSERT INTO lf_transaction_test VALUES (1)").execute(connection)
+        });
+        assert_eq!(Err(Status::Conflict), result);
+
+        let count = ::diesel::select(sql::<::diesel::sql_types::BigInt>("(SELECT COUNT(*) FROM lf_transaction_test)"))
+            .get_result::<i64>(&*connection)
+            .unwrap();
+        assert_eq!(0, count);
+    }
+
+    #[test]
+    #[cfg(feature = "pgsql")]
+    fn test_with_application_name () {
+        assert_eq!("postgres://localhost/app?application_name=quux", with_application_name("postgres://localhost/app", "quux"));
+        assert_eq!("postgresql://localhost/app?sslmode=require&application_name=my%20app", with_application_name("postgresql://localhost/app?sslmode=require", "my app"));
+        assert_eq!("postgres://localhost/app?application_name=foo", with_application_name("postgres://localhost/app?application_name=foo", "quux"));
+        assert_eq!("host=localhost dbname=app", with_application_name("host=localhost dbname=app", "quux"));
+        assert_eq!("postgres://localhost/app?fallback_application_name=foo&application_name=quux", with_application_name("postgres://localhost/app?fallback_application_name=foo", "quux"));
+    }
+
+    #[test]
+    #[cfg(feature = "pgsql")]
+    fn test_pool_status () {
+        let status = pool_status(&build_lazy_pool(4));
+
+        assert_eq!(PoolStatus { connections: 0, idle_connections: 0, max_size: 4 }, status);
+    }
+
+    #[test]
+    #[cfg(all(feature = "pgsql", feature = "serialization"))]
+    fn test_pool_status_handler () {
+        use rocket::http::Method;
+        use rocket::local::Client;
+        use rocket::Route;
+
+        let routes = vec![Route::new(Method::Get, "/metrics/pool", pool_status_handler)];
+        let server = ::rocket::ignite()
+            .manage(build_lazy_pool(4))
+            .mount("/", routes);
+        let client = Client::untracked(server).unwrap();
+
+        let mut response = client.get("/metrics/pool").dispatch();
+        assert_eq!(Status::Ok, response.status());
+
+        let body: ::serde_json::Value = ::serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(::serde_json::json!({ "connections": 0, "idle_connections": 0, "max_size": 4 }), body);
+    }
+
+    #[test]
+    fn test_compute_backoff_delay () {
+        let base_delay = Duration::from_millis(500);
+
+        assert_eq!(Duration::from_millis(500), compute_backoff_delay(base_delay, 0));
+        assert_eq!(Duration::from_millis(1000), compute_backoff_delay(base_delay, 1));
+        assert_eq!(Duration::from_millis(4000), compute_backoff_delay(base_delay, 3));
+        assert_eq!(MAX_CONNECT_DELAY, compute_backoff_delay(base_delay, 10));
+        assert_eq!(MAX_CONNECT_DELAY, compute_backoff_delay(base_delay, 40));
+    }
+
+    #[test]
+    fn test_retry_with_backoff () {
+        let mut calls = 0;
+        let result = retry_with_backoff(3, Duration::from_millis(1), || {
+            calls += 1;
+
+            if calls < 3 { Err("Connection refused.".into()) } else { Ok(()) }
+        });
+
+        assert!(result.is_ok());
+        assert_eq!(3, calls);
+    }
+
+    #[test]
+    fn test_retry_with_backoff_when_exhausted () {
+        let mut calls = 0;
+        let result = retry_with_backoff(2, Duration::from_millis(1), || {
+            calls += 1;
+
+            Err("Connection refused.".into())
+        });
+
+        assert!(result.is_err());
+        assert_eq!(2, calls);
+    }
+
+    #[test]
+    #[cfg(feature = "pgsql")]
+    fn test_is_pool_oversized () {
+        assert!(!is_pool_oversized(4, 100));
+        assert!(!is_pool_oversized(50, 100));
+        assert!(is_pool_oversized(500, 100));
+    }
+
+    #[test]
+    #[cfg(feature = "pgsql")]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    fn test_check_max_connections_with_oversized_pool () {
+        // Requires a live database, e.g. TEST_DATABASE_URL=postgres://localhost/test
+        let database_url = ::std::env::var("TEST_DATABASE_URL")
+            .expect("The TEST_DATABASE_URL variable must be set to run this test");
+
+        assert_eq!(Some(true), check_max_connections(&database_url, 100_000));
+        assert_eq!(Some(false), check_max_connections(&database_url, 1));
+    }
+
+    #[test]
+    #[cfg(feature = "pgsql")]
+    fn test_check_max_connections_when_unreachable () {
+        use std::net::TcpListener;
+
+        // Nothing listens on a port just freed, so the connection is refused without any DNS lookup
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let database_url = format!("postgres://127.0.0.1:{}/test", port);
+
+        assert_eq!(None, check_max_connections(&database_url, 100_000));
+    }
+
+    #[test]
+    #[cfg(feature = "pgsql")]
+    fn test_format_query_log () {
+        let query = ::diesel::sql_query("SELECT 1");
+        let line = format_query_log(&query, Duration::from_millis(12));
+
+        assert!(line.starts_with("SELECT 1"));
+        assert!(line.ends_with("(12 ms)"));
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn test_logging_connection () {
+        let logger: &'static CapturingLogger = Box::leak(Box::new(CapturingLogger::new()));
+        let connection = LoggingConnection::<SqliteConnection>::establish(":memory:").unwrap()
+            .with_query_logging(true)
+            .with_logger(logger);
+
+        let one = ::diesel::select(::diesel::dsl::sql::<::diesel::sql_types::Integer>("1"));
+        assert_eq!(Ok(1), ::diesel::RunQueryDsl::get_result::<i32>(one, &connection));
+
+        let records = logger.get_records();
+        assert_eq!(1, records.len());
+        assert!(records[0].starts_with("DEBUG sql: SELECT 1"), "Unexpected log line: {}", records[0]);
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn test_logging_connection_when_disabled () {
+        let logger: &'static CapturingLogger = Box::leak(Box::new(CapturingLogger::new()));
+        let connection = LoggingConnection::<SqliteConnection>::establish(":memory:").unwrap()
+            .with_query_logging(false)
+            .with_logger(logger);
+
+        let one = ::diesel::select(::diesel::dsl::sql::<::diesel::sql_types::Integer>("1"));
+        assert_eq!(Ok(1), ::diesel::RunQueryDsl::get_result::<i32>(one, &connection));
+
+        assert!(logger.get_records().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "pgsql")]
+    fn test_named_pools_resolve_independently () {
+        let server = ::rocket::ignite();
+        let server = manage_named_pool::<Main>(server, build_lazy_pool(4));
+        let server = manage_named_pool::<Analytics>(server, build_lazy_pool(2));
+
+        let main_pool = server.state::<NamedPool<Main>>().expect("Main pool should be managed");
+        let analytics_pool = server.state::<NamedPool<Analytics>>().expect("Analytics pool should be managed");
+
+        assert_eq!("main", main_pool.get_name());
+        assert_eq!(4, main_pool.max_size());
+        assert_eq!("analytics", analytics_pool.get_name());
+        assert_eq!(2, analytics_pool.max_size());
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn test_acquire_connection_when_slow () {
+        let logger = CapturingLogger::new();
+        let pool = initialize_sqlite_pool(":memory:", 1).unwrap();
+
+        // The only connection of the pool is released by another thread, after a while
+        let busy_connection = pool.get().unwrap();
+        let releaser = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            drop(busy_connection);
+        });
+
+        let connection = acquire_connection_with(&pool, "test", 20, Some(&logger));
+        releaser.join().unwrap();
+        assert!(connection.is_ok());
+
+        let records = logger.get_records();
+        assert!(
+            records.iter().any(|record| record.starts_with("WARN database: Slow connection acquisition from the test pool: ")),
+            "The slow acquisition should be logged: {:?}", records
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn test_acquire_connection () {
+        let logger = CapturingLogger::new();
+        let pool = initialize_sqlite_pool(":memory:", 1).unwrap();
+
+        assert!(acquire_connection_with(&pool, "test", 1000, Some(&logger)).is_ok());
+
+        let records = logger.get_records();
+        assert_eq!(1, records.len());
+        assert!(records[0].starts_with("DEBUG database: Connection acquired from the test pool in "));
+    }
 }
@@ -2,16 +2,53 @@
 //!
 //! Provides methods to start the server and handle the application
 
-use config::{Config, MinimalConfig};
-#[cfg(feature = "pgsql")]
+use api::charset::JsonCharset;
+use api::client_errors::ClientErrorLogger;
+use api::replies::{build_not_modified_response, is_none_match_satisfied, not_found_catcher, service_unavailable_catcher, too_many_requests_catcher, NotFoundBodies};
+use api::request_id::RequestIdFairing;
+use build_info::BuildInfo;
+use logger::initialize_logger;
+use scheduler::Scheduler;
+use config::{describe_environment, has_serialization, Config, MinimalConfig, TlsPaths};
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
 use config::DefaultConfig;
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+use database::{get_connect_retry, initialize_pool_with_options, test_database_connection_with_retry, DatabaseBackend, LoggingConnection};
+#[cfg(feature = "pgsql")]
+use database::{check_max_connections, should_check_max_connections, with_application_name, PostgreSQLPool};
 #[cfg(feature = "pgsql")]
-use database::{initialize_database_pool, test_database_connection};
+use diesel::PgConnection;
+#[cfg(feature = "migrations")]
+use diesel::Connection;
+#[cfg(feature = "migrations")]
+use database::{run_embedded_migrations, EmbeddedMigrations, MigrationError};
+#[cfg(feature = "mysql")]
+use database::MySQLPool;
+#[cfg(feature = "mysql")]
+use diesel::mysql::MysqlConnection;
+#[cfg(feature = "sqlite")]
+use database::{get_sqlite_path, get_sqlite_pool_size, SqlitePool};
+#[cfg(feature = "sqlite")]
+use diesel::sqlite::SqliteConnection;
 use ErrorResult;
+use rocket::config as rocket_config;
+use rocket::config::ConfigError;
+use rocket::custom;
+use rocket::{handler, Data, Request};
+use rocket::http::{ContentType, Header, Method};
+use rocket::Response;
+use rocket::Rocket;
 use rocket::Route;
-use rocket::ignite;
+use std::env;
+use std::collections::hash_map::DefaultHasher;
+use std::error::Error;
+use std::hash::{Hash, Hasher};
+use std::io::Cursor;
+use std::panic::{self, AssertUnwindSafe};
 use std::process;
 use std::marker::PhantomData;
+use std::sync::mpsc;
+use std::thread;
 use config::EnvironmentConfigurable;
 
 /*   -------------------------------------------------------------
@@ -20,6 +57,11 @@ use config::EnvironmentConfigurable;
      Allow to define config and routes. Launch a server.
      - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
 
+/// A service launches a Rocket server with the configured routes.
+///
+/// HEAD requests are answered by the matching GET routes, with the same headers,
+/// the `Content-Length` of the GET body and no body, as Rocket handles them natively
+/// when no HEAD route is defined.
 pub trait Service {
     fn get_config(&self) -> &dyn Config;
 
@@ -29,8 +71,136 @@ pub trait Service {
 
     fn check_service_configuration(&self) -> ErrorResult<()>;
 
+    /// Allows to customize the Rocket configuration (limits, TLS, secret key) before launch.
+    ///
+    /// The configuration received is read from Rocket.toml and `ROCKET_*` environment
+    /// variables, so any value set here takes precedence over them.
+    fn configure_rocket(&self, config: rocket_config::Config) -> rocket_config::Config {
+        config
+    }
+
+    /// Allows to customize the 404 responses by path prefix, e.g. by mount point.
+    ///
+    /// When None, the default Rocket 404 catcher is kept.
+    fn get_not_found_bodies(&self) -> Option<NotFoundBodies> {
+        None
+    }
+
+    /// Gets the Rocket configurations to launch, one by listen target.
+    ///
+    /// Without listen target, the address and port of the configuration are applied
+    /// to the Rocket configuration, if any.
+    fn get_rocket_configs(&self) -> ErrorResult<Vec<rocket_config::Config>> {
+        let mut rocket_config = self.configure_rocket(read_rocket_config()?);
+
+        if let Some(tls_paths) = self.get_config().get_tls_paths() {
+            set_rocket_tls(&mut rocket_config, tls_paths)?;
+        }
+
+        let targets = self.get_config().get_listen_targets();
+        if targets.is_empty() {
+            if let Some(address) = self.get_config().get_address() {
+                rocket_config.set_address(address.to_string())?;
+            }
+
+            if let Some(port) = self.get_config().get_port() {
+                rocket_config.set_port(port);
+            }
+
+            return Ok(vec![rocket_config]);
+        }
+
+        targets
+            .iter()
+            .map(|target| -> ErrorResult<rocket_config::Config> {
+                let mut config = rocket_config.clone();
+                config.set_address(target.ip().to_string())?;
+                config.set_port(target.port());
+
+                Ok(config)
+            })
+            .collect()
+    }
+
+    /// Gets the path of the health route to mount, or None if it's disabled
+    /// or if the service already mounts a GET route at this path.
+    fn get_health_route_path(&self) -> Option<String> {
+        let path = self.get_config().get_health_endpoint()?;
+
+        if !self.find_builtin_route_conflicts().is_empty() {
+            return None;
+        }
+
+        Some(path.to_string())
+    }
+
+    /// Finds the service routes, once mounted under the entry point, overlapping
+    /// the built-in routes, as `METHOD /path` descriptions.
+    fn find_builtin_route_conflicts(&self) -> Vec<String> {
+        let health_path = match self.get_config().get_health_endpoint() {
+            Some(path) => path,
+            None => return Vec::new(),
+        };
+
+        let entry_point = self.get_config().get_versioned_entry_point();
+        let entry_point = entry_point.trim_end_matches('/');
+
+        self.get_routes()
+            .iter()
+            .filter(|route| route.method == Method::Get)
+            .map(|route| format!("{}{}", entry_point, route.uri.path()))
+            .filter(|path| path == health_path)
+            .map(|path| format!("{} {}", Method::Get, path))
+            .collect()
+    }
+
+    /// Builds the Rocket server, with the resolved configuration as managed state,
+    /// the request ID, client errors logger and JSON charset fairings attached, each enabled
+    /// by its environment variable, the 429 and 503 catchers sending a Retry-After header,
+    /// and the health route.
+    fn ignite_server(&self, rocket_config: rocket_config::Config) -> ErrorResult<Rocket> {
+        let mut server = custom(rocket_config)
+            .manage(self.get_config().resolve())
+            .attach(RequestIdFairing::parse_environment()?)
+            .attach(ClientErrorLogger::parse_environment()?)
+            .attach(JsonCharset::parse_environment()?)
+            .register(vec![service_unavailable_catcher(), too_many_requests_catcher()]);
+
+        if let Some(path) = self.get_health_route_path() {
+            server = server.mount("/", vec![Route::new(Method::Get, &path, HealthHandler::new())]);
+        }
+
+        if let Some(not_found_bodies) = self.get_not_found_bodies() {
+            return Ok(server
+                .manage(not_found_bodies)
+                .register(vec![not_found_catcher()]));
+        }
+
+        Ok(server)
+    }
+
+    /// Describes the routes as they are mounted, one `METHOD /path` by route.
+    fn dump_routes(&self) -> Vec<String> {
+        let entry_point = self.get_config().get_versioned_entry_point();
+        let entry_point = entry_point.trim_end_matches('/');
+
+        let mut routes: Vec<_> = self.get_routes()
+            .iter()
+            .map(|route| format!("{} {}{}", route.method, entry_point, route.uri))
+            .collect();
+
+        if let Some(path) = self.get_health_route_path() {
+            routes.push(format!("{} {}", Method::Get, path));
+        }
+
+        routes
+    }
+
     fn run (&mut self) -> ErrorResult<()> {
-        info!(target: "runner", "Server started.");
+        info!(target: "runner", "{}", format_banner(self.get_config().get_service_name(), &BuildInfo::current()));
+
+        warn_about_json_routes(self.get_routes());
+        check_builtin_route_conflicts(&self.find_builtin_route_conflicts(), should_fail_on_route_conflicts())?;
 
         {
             self.check_service_configuration()?
@@ -42,49 +212,446 @@ pub trait Service {
     }
 }
 
+/// Formats the line logged when the server starts, with the service name and the build.
+fn format_banner(service_name: &str, build_info: &BuildInfo) -> String {
+    format!("Server {} started ({}).", service_name, build_info)
+}
+
+/// Warns when routes produce or accept JSON but the crate has been built without the
+/// `serialization` feature, as the JSON responders of this crate aren't available.
+///
+/// Returns the number of routes the warning is about.
+pub fn warn_about_json_routes(routes: &[Route]) -> usize {
+    if has_serialization() {
+        return 0;
+    }
+
+    let json_routes = routes
+        .iter()
+        .filter(|route| route.format.as_ref().map_or(false, |format| format.is_json()))
+        .count();
+
+    if json_routes > 0 {
+        warn!(target: "runner", "{} routes use JSON, but limiting-factor has been built without the serialization feature.", json_routes);
+    }
+
+    json_routes
+}
+
+/// The body of the health route
+const HEALTH_BODY: &'static str = "ALIVE";
+
+/// Answers ALIVE to the requests to the health route.
+///
+/// As the body is constant, its ETag is computed once at startup, and the conditional
+/// requests are answered by a 304 Not Modified. The response is sent with `Cache-Control: no-cache`,
+/// so each probe still reaches the server and proves it's alive.
+#[derive(Clone)]
+struct HealthHandler {
+    etag: String,
+}
+
+impl HealthHandler {
+    fn new () -> Self {
+        let mut hasher = DefaultHasher::new();
+        HEALTH_BODY.hash(&mut hasher);
+
+        Self {
+            etag: format!("\"{:016x}\"", hasher.finish()),
+        }
+    }
+}
+
+impl handler::Handler for HealthHandler {
+    fn handle<'r>(&self, request: &'r Request, _data: Data) -> handler::Outcome<'r> {
+        let is_not_modified = request.headers()
+            .get_one("If-None-Match")
+            .map_or(false, |value| is_none_match_satisfied(value, &self.etag));
+
+        let mut response = if is_not_modified {
+            build_not_modified_response()
+        } else {
+            Response::build()
+                .header(ContentType::Plain)
+                .sized_body(Cursor::new(HEALTH_BODY))
+                .finalize()
+        };
+
+        response.set_header(Header::new("ETag", self.etag.clone()));
+        response.set_header(Header::new("Cache-Control", "no-cache"));
+
+        handler::Outcome::Success(response)
+    }
+}
+
+/// Reads the Rocket configuration from Rocket.toml and `ROCKET_*` environment variables,
+/// like `rocket::ignite` does.
+pub fn read_rocket_config() -> ErrorResult<rocket_config::Config> {
+    let config = match rocket_config::RocketConfig::read() {
+        Ok(config) => config,
+        Err(ConfigError::NotFound) => rocket_config::RocketConfig::active_default()?,
+        Err(ConfigError::IoError) => {
+            warn!(target: "runner", "Failed reading Rocket.toml. Using defaults.");
+
+            rocket_config::RocketConfig::active_default()?
+        },
+        Err(error) => return Err(Box::new(error)),
+    };
+
+    Ok(config.active().clone())
+}
+
+/// Configures Rocket to serve HTTPS with the certificate and private key.
+///
+/// Fails if the PEM files can't be read or parsed.
+#[cfg(feature = "tls")]
+pub fn set_rocket_tls(rocket_config: &mut rocket_config::Config, tls_paths: &TlsPaths) -> ErrorResult<()> {
+    rocket_config.set_tls(&tls_paths.cert_path, &tls_paths.key_path)?;
+
+    Ok(())
+}
+
+/// Fails, as serving HTTPS needs the `tls` feature.
+#[cfg(not(feature = "tls"))]
+pub fn set_rocket_tls(_rocket_config: &mut rocket_config::Config, _tls_paths: &TlsPaths) -> ErrorResult<()> {
+    Err("The tls feature is needed to serve HTTPS.".into())
+}
+
+/// Determines if the routes should be printed instead of launching the server,
+/// according the `LF_DUMP_ROUTES` environment variable.
+pub fn should_dump_routes() -> bool {
+    is_dump_routes_enabled(env::var("LF_DUMP_ROUTES").ok().as_deref())
+}
+
+/// Determines if a `LF_DUMP_ROUTES` value enables the routes dump: any value but empty or `0`.
+fn is_dump_routes_enabled(value: Option<&str>) -> bool {
+    value.map_or(false, |value| !value.is_empty() && value != "0")
+}
+
+/// Determines if the routes overlapping the built-in routes should prevent the server
+/// to start, according the `LF_STRICT_ROUTES` environment variable.
+pub fn should_fail_on_route_conflicts() -> bool {
+    env::var("LF_STRICT_ROUTES").is_ok()
+}
+
+/// Reports the routes overlapping the built-in routes, as the built-in routes
+/// aren't mounted in this case.
+///
+/// In strict mode, the conflicts are an error. Otherwise, a warning is logged.
+pub fn check_builtin_route_conflicts(conflicts: &[String], strict: bool) -> ErrorResult<()> {
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+
+    let message = format!("Routes overlap the built-in routes, which won't be mounted: {}", conflicts.join(", "));
+    if strict {
+        return Err(message.into());
+    }
+
+    warn!(target: "runner", "{}", message);
+
+    Ok(())
+}
+
+/// Runs the server setup, converting a panic (e.g. in a fairing attach callback)
+/// into an error, so the application can exit in a controlled way.
+///
+/// Only the setup is protected: the requests are served after, during the launch.
+pub fn catch_setup_panic<T, F>(setup: F) -> ErrorResult<T>
+    where F: FnOnce() -> ErrorResult<T>
+{
+    match panic::catch_unwind(AssertUnwindSafe(setup)) {
+        Ok(result) => result,
+        Err(payload) => {
+            let reason = payload.downcast_ref::<&str>()
+                .map(|reason| reason.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| String::from("unknown reason"));
+
+            Err(format!("Server setup panicked: {}", reason).into())
+        }
+    }
+}
+
+/// Launches the servers, each one in its own thread if there are several of them.
+///
+/// As a server launch only returns on failure, the first failure is returned.
+/// Rocket doesn't offer a way to stop a launched server, so the other servers
+/// keep running until the process exits: the caller should exit on error,
+/// as `Application::start` does, so a failure on one target stops them all.
+pub fn launch_servers(mut servers: Vec<Rocket>) -> ErrorResult<()> {
+    if servers.len() == 1 {
+        // Launch only returns if the server can't be started.
+        // The LaunchError panics when dropped unhandled, so it's formatted right away.
+        let error = servers.remove(0).launch();
+
+        return Err(error.to_string().into());
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    for server in servers {
+        let sender = sender.clone();
+        thread::spawn(move || {
+            let error = server.launch();
+            let _ = sender.send(error.to_string());
+        });
+    }
+    drop(sender);
+
+    match receiver.recv() {
+        Ok(error) => Err(error.into()),
+        Err(_) => Ok(()),
+    }
+}
+
 /*   -------------------------------------------------------------
      Default service
 
      Allow to define config and routes. Launch a server.
      - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
 
-/// The default service offers a pgsql database connection with Diesel and r2d2.
-#[cfg(feature = "pgsql")]
+/// The default service offers a database connection with Diesel and r2d2,
+/// to PostgreSQL, MySQL or SQLite according to the enabled features.
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
 pub struct DefaultService {
     pub config: DefaultConfig,
     pub routes: Vec<Route>,
+
+    /// A pool built by the application, used instead of building one from the configuration
+    #[cfg(feature = "pgsql")]
+    pub pool: Option<PostgreSQLPool>,
+
+    /// The migrations embedded in the application, applied at startup if `LF_RUN_MIGRATIONS` is set
+    #[cfg(feature = "migrations")]
+    pub migrations: Option<EmbeddedMigrations>,
 }
 
-#[cfg(feature = "pgsql")]
-impl Service for DefaultService {
-    fn get_config(&self) -> &dyn Config { &self.config }
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+impl DefaultService {
+    /// Builds a service using an already built pool, e.g. pointing to a test database.
+    ///
+    /// The pool isn't built from the configuration, and the connection to the database
+    /// isn't probed at startup.
+    #[cfg(feature = "pgsql")]
+    pub fn with_pool (config: DefaultConfig, routes: Vec<Route>, pool: PostgreSQLPool) -> Self {
+        Self {
+            config,
+            routes,
+            pool: Some(pool),
+            #[cfg(feature = "migrations")]
+            migrations: None,
+        }
+    }
 
-    fn get_routes(&self) -> &[Route] { self.routes.as_slice() }
+    /// Gives the migrations embedded in the application by the `embed_migrations!` macro,
+    /// to apply them at startup when the `LF_RUN_MIGRATIONS` variable is set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// embed_migrations!();
+    ///
+    /// let service = service.with_migrations(embedded_migrations::run_with_output);
+    /// ```
+    #[cfg(feature = "migrations")]
+    pub fn with_migrations (mut self, migrations: EmbeddedMigrations) -> Self {
+        self.migrations = Some(migrations);
 
-    fn launch_server(&mut self) -> ErrorResult<()> {
+        self
+    }
+
+    /// Applies the pending embedded migrations, if enabled by the configuration.
+    #[cfg(feature = "migrations")]
+    fn run_migrations(&self) -> ErrorResult<()> {
+        if !self.config.should_run_migrations() {
+            return Ok(());
+        }
+
+        let migrations = match self.migrations {
+            Some(migrations) => migrations,
+            None => {
+                warn!(target: "runner", "The LF_RUN_MIGRATIONS variable is set, but the application doesn't embed any migration.");
+
+                return Ok(());
+            },
+        };
+
+        match self.pool {
+            Some(ref pool) => run_embedded_migrations(&*pool.get()?, migrations)?,
+            None => run_embedded_migrations(&PgConnection::establish(self.config.get_database_url())?, migrations)?,
+        }
+
+        info!(target: "runner", "Database migrations applied.");
+
+        Ok(())
+    }
+
+    /// Warns the pending migrations can't be applied without the `migrations` feature.
+    #[cfg(not(feature = "migrations"))]
+    fn run_migrations(&self) -> ErrorResult<()> {
+        if self.config.should_run_migrations() {
+            warn!(target: "runner", "The LF_RUN_MIGRATIONS variable is set, but limiting-factor has been built without the migrations feature.");
+        }
+
+        Ok(())
+    }
+
+    /// Builds the Rocket servers to launch, one by listen target, sharing the same pool.
+    pub fn build_servers(&self) -> ErrorResult<Vec<Rocket>> {
         let config = self.get_config();
         let routes = self.get_routes();
 
-        let mut server = ignite();
+        let rocket_configs = self.get_rocket_configs()?;
+
+        catch_setup_panic(|| {
+            let pools = self.build_pools()?;
+
+            rocket_configs
+                .into_iter()
+                .map(|rocket_config| -> ErrorResult<Rocket> {
+                    let server = pools.manage(self.ignite_server(rocket_config)?);
+
+                    Ok(server.mount(&config.get_versioned_entry_point(), routes.to_vec()))
+                })
+                .collect()
+        })
+    }
+
+    /// Determines if a pool has been built by the application.
+    #[cfg(feature = "pgsql")]
+    fn has_injected_pool(&self) -> bool {
+        self.pool.is_some()
+    }
+
+    /// Determines if a pool has been built by the application, only offered for PostgreSQL.
+    #[cfg(not(feature = "pgsql"))]
+    fn has_injected_pool(&self) -> bool {
+        false
+    }
+
+    /// Builds the pool for the backend of the database URL, unless a pool has been injected.
+    fn build_pools(&self) -> ErrorResult<DatabasePools> {
+        let config = self.get_config();
+        let mut pools = DatabasePools::default();
+
+        #[cfg(feature = "pgsql")]
+        {
+            if let Some(ref pool) = self.pool {
+                pools.postgresql = Some(pool.clone());
 
-        if config.with_database() {
-            server = server.manage(
-                initialize_database_pool(config.get_database_url(), config.get_database_pool_size())?
-            );
+                return Ok(pools);
+            }
+        }
+
+        if !config.with_database() {
+            return Ok(pools);
+        }
+
+        let url = config.get_database_url();
+        let size = config.get_database_pool_size();
+        let options = self.config.get_database_pool_options();
+        let backend = DatabaseBackend::from_url(url)
+            .ok_or("No enabled database backend can connect to this database URL.")?;
+
+        match backend {
+            #[cfg(feature = "pgsql")]
+            DatabaseBackend::PostgreSQL => {
+                let url = with_application_name(url, config.get_service_name());
+                pools.postgresql = Some(initialize_pool_with_options::<LoggingConnection<PgConnection>>(&url, size, &options)?)
+            },
+
+            #[cfg(feature = "mysql")]
+            DatabaseBackend::MySQL => {
+                pools.mysql = Some(initialize_pool_with_options::<LoggingConnection<MysqlConnection>>(url, size, &options)?)
+            },
+
+            #[cfg(feature = "sqlite")]
+            DatabaseBackend::SQLite => {
+                let path = get_sqlite_path(url);
+                pools.sqlite = Some(initialize_pool_with_options::<LoggingConnection<SqliteConnection>>(path, get_sqlite_pool_size(path, size), &options)?)
+            },
+        }
+
+        Ok(pools)
+    }
+}
+
+/// The database pools shared by the servers of a service, one by backend.
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+#[derive(Default)]
+struct DatabasePools {
+    #[cfg(feature = "pgsql")]
+    postgresql: Option<PostgreSQLPool>,
+
+    #[cfg(feature = "mysql")]
+    mysql: Option<MySQLPool>,
+
+    #[cfg(feature = "sqlite")]
+    sqlite: Option<SqlitePool>,
+}
+
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+impl DatabasePools {
+    /// Registers the pools as managed state of the server.
+    fn manage(&self, mut server: Rocket) -> Rocket {
+        #[cfg(feature = "pgsql")]
+        {
+            if let Some(ref pool) = self.postgresql {
+                server = server.manage(pool.clone());
+            }
+        }
+
+        #[cfg(feature = "mysql")]
+        {
+            if let Some(ref pool) = self.mysql {
+                server = server.manage(pool.clone());
+            }
+        }
+
+        #[cfg(feature = "sqlite")]
+        {
+            if let Some(ref pool) = self.sqlite {
+                server = server.manage(pool.clone());
+            }
         }
 
         server
-            .mount(config.get_entry_point(), routes.to_vec())
-            .launch();
+    }
+}
 
-        Ok(())
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+impl Service for DefaultService {
+    fn get_config(&self) -> &dyn Config { &self.config }
+
+    fn get_routes(&self) -> &[Route] { self.routes.as_slice() }
+
+    /// Applies the pending migrations if enabled, then launches the servers.
+    ///
+    /// The migrations are applied here rather than when the configuration is checked,
+    /// so the `check-config` command doesn't change the database schema.
+    fn launch_server(&mut self) -> ErrorResult<()> {
+        if self.config.with_database() {
+            self.run_migrations()?;
+        }
+
+        let servers = self.build_servers()?;
+
+        launch_servers(servers)
     }
 
     fn check_service_configuration(&self) -> ErrorResult<()> {
         let config = self.get_config();
-        if config.with_database() {
-            test_database_connection(config.get_database_url())?;
+        if config.with_database() && !self.has_injected_pool() {
+            let retry = get_connect_retry();
+            test_database_connection_with_retry(config.get_database_url(), retry.attempts, retry.base_delay)?;
             info!(target: "runner", "Connection to database established.");
+
+            #[cfg(feature = "pgsql")]
+            {
+                if should_check_max_connections() {
+                    check_max_connections(config.get_database_url(), config.get_database_pool_size());
+                }
+            }
         }
 
         Ok(())
@@ -112,16 +679,90 @@ impl Service for MinimalService {
         let config = self.get_config();
         let routes = self.get_routes();
 
-        ignite()
-            .mount(config.get_entry_point(), routes.to_vec())
-            .launch();
+        let rocket_configs = self.get_rocket_configs()?;
 
-        Ok(())
+        let servers = catch_setup_panic(|| {
+            rocket_configs
+                .into_iter()
+                .map(|rocket_config| -> ErrorResult<Rocket> {
+                    Ok(self.ignite_server(rocket_config)?.mount(&config.get_versioned_entry_point(), routes.to_vec()))
+                })
+                .collect()
+        })?;
+
+        launch_servers(servers)
     }
 
     fn check_service_configuration(&self) -> ErrorResult<()> { Ok(()) }
 }
 
+/*   -------------------------------------------------------------
+     Cleanup hooks
+
+     :: Drop
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// Cleanup closures to run when the server stops, in the reverse order of their registration,
+/// e.g. to remove temporary files or to close resources.
+///
+/// The closures are run by `run`, or when the hooks are dropped.
+#[derive(Default)]
+pub struct CleanupHooks {
+    hooks: Vec<Box<dyn FnOnce() + Send>>,
+}
+
+impl CleanupHooks {
+    pub fn new () -> Self {
+        Self::default()
+    }
+
+    pub fn register<F> (&mut self, hook: F)
+        where F: FnOnce() + Send + 'static
+    {
+        self.hooks.push(Box::new(hook));
+    }
+
+    pub fn len (&self) -> usize {
+        self.hooks.len()
+    }
+
+    pub fn is_empty (&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Runs the registered closures, last registered first.
+    pub fn run (&mut self) {
+        while let Some(hook) = self.hooks.pop() {
+            hook();
+        }
+    }
+}
+
+impl Drop for CleanupHooks {
+    fn drop(&mut self) {
+        self.run();
+    }
+}
+
+/*   -------------------------------------------------------------
+     Commands
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A subcommand of the application, run instead of serving requests.
+pub type Command = Box<dyn FnMut(&dyn Service) -> ErrorResult<()>>;
+
+/// The command serving requests, run when the application is called without argument
+pub const SERVE_COMMAND: &'static str = "serve";
+
+/// The command checking the configuration of the service, e.g. the database connection
+pub const CHECK_CONFIG_COMMAND: &'static str = "check-config";
+
+/// The command printing the mounted routes
+pub const DUMP_ROUTES_COMMAND: &'static str = "dump-routes";
+
+/// The command describing the environment variables read by the library, called as `env --describe`
+pub const ENV_COMMAND: &'static str = "env";
+
 /*   -------------------------------------------------------------
      Base application as concrete implementation
 
@@ -140,46 +781,244 @@ pub struct Application<U>
 {
     service: Box<dyn Service>,
     config_type: PhantomData<U>,
+    scheduler: Option<Scheduler>,
+    cleanup_hooks: CleanupHooks,
+    commands: Vec<(String, Command)>,
 }
 
 impl<U> Application<U>
     where U: Config + EnvironmentConfigurable
 {
     pub fn new (config: U, routes: Vec<Route>) -> Self {
+        Self::from_service(config.into_service(routes))
+    }
+
+    /// Builds the application from an already built service.
+    pub fn from_service (service: Box<dyn Service>) -> Self {
         Application {
-            service: config.into_service(routes),
+            service,
             config_type: PhantomData,
+            scheduler: None,
+            cleanup_hooks: CleanupHooks::new(),
+            commands: Vec::new(),
         }
     }
 
+    /// Runs the tasks of the scheduler alongside the server.
+    pub fn with_scheduler (mut self, scheduler: Scheduler) -> Self {
+        self.scheduler = Some(scheduler);
+
+        self
+    }
+
+    /// Registers a closure to run when the server stops.
+    ///
+    /// The closures run in the reverse order of their registration.
+    pub fn on_shutdown<F> (mut self, hook: F) -> Self
+        where F: FnOnce() + Send + 'static
+    {
+        self.cleanup_hooks.register(hook);
+
+        self
+    }
+
+    /// Registers a subcommand, run instead of serving requests when the application
+    /// is called with its name as first argument, e.g. `migrate`.
+    ///
+    /// The built-in `serve`, `check-config`, `dump-routes` and `env` commands can't be replaced.
+    pub fn with_command<F> (mut self, name: &str, command: F) -> Self
+        where F: FnMut(&dyn Service) -> ErrorResult<()> + 'static
+    {
+        self.commands.push((name.to_string(), Box::new(command)));
+
+        self
+    }
+
+    /// Runs the subcommand named by the first argument, if any.
+    ///
+    /// Returns true if a subcommand has been run, false if the requests should be served.
+    /// An argument which isn't a known command, like a flag of the application, doesn't prevent
+    /// to serve the requests.
+    pub fn dispatch (&mut self, args: &[String]) -> ErrorResult<bool> {
+        let name = match args.first() {
+            None => return Ok(false),
+            Some(name) => name.as_str(),
+        };
+
+        match name {
+            SERVE_COMMAND => Ok(false),
+
+            CHECK_CONFIG_COMMAND => {
+                self.service.check_service_configuration()?;
+                info!(target: "runner", "The configuration is valid.");
+
+                Ok(true)
+            },
+
+            DUMP_ROUTES_COMMAND => {
+                for route in self.service.dump_routes() {
+                    println!("{}", route);
+                }
+
+                Ok(true)
+            },
+
+            ENV_COMMAND => {
+                match args.get(1).map(|arg| arg.as_str()) {
+                    Some("--describe") => {
+                        println!("{}", describe_environment());
+
+                        Ok(true)
+                    },
+                    _ => Err(format!("Usage: {} --describe", ENV_COMMAND).into()),
+                }
+            },
+
+            _ => {
+                let service = &*self.service;
+                let command = self.commands
+                    .iter_mut()
+                    .find(|(command_name, _)| command_name == name)
+                    .map(|(_, command)| command);
+
+                match command {
+                    Some(command) => {
+                        command(service)?;
+
+                        Ok(true)
+                    },
+                    None => {
+                        debug!(target: "runner", "{} isn't a command, the requests will be served.", name);
+
+                        Ok(false)
+                    },
+                }
+            },
+        }
+    }
+
+    /// Runs the application: starts the scheduler, runs the service until it stops,
+    /// then stops the scheduler and runs the cleanup hooks.
+    ///
+    /// Contrary to `start`, the error is returned to the caller instead of exiting the process.
+    pub fn run (&mut self) -> ErrorResult<()> {
+        let scheduler = self.scheduler.take().map(Scheduler::start);
+
+        let result = self.service.run();
+
+        if let Some(scheduler) = scheduler {
+            scheduler.shutdown();
+        }
+
+        self.cleanup_hooks.run();
+
+        result
+    }
+
     /// Starts the application
     ///
     /// # Exit codes
     ///
     /// The software will exit with the following error codes:
     ///
-    ///   - 0: Graceful exit (currently not in use, as the application never stops)
+    ///   - 0: Graceful exit (currently only used after printing the routes, as the application never stops)
     ///   - 1: Error during the application run (e.g. routes conflict or Rocket fairings issues)
-    ///   - 2: Error parsing the configuration (e.g. no database URL has been defined)
+    ///   - 2: Error parsing the configuration (e.g. no database URL has been defined),
+    ///        or applying the database migrations
+    ///
+    /// If the `LF_DUMP_ROUTES` environment variable is set, the mounted routes are printed
+    /// instead of launching the server.
+    ///
+    /// If the first argument is a command, like `check-config`, `dump-routes`, `env --describe` or a command
+    /// registered with `with_command`, the command is run instead of launching the server.
     pub fn start (&mut self) {
+        if should_dump_routes() {
+            for route in self.service.dump_routes() {
+                println!("{}", route);
+            }
+
+            process::exit(0);
+        }
+
+        let args: Vec<String> = env::args().skip(1).collect();
+        match self.dispatch(&args) {
+            Ok(true) => process::exit(0),
+            Ok(false) => {},
+            Err(error) => {
+                error!(target: "runner", "{}", error);
+                process::exit(1);
+            },
+        }
+
         info!(target: "runner", "Server initialized.");
 
-        if let Err(error) = self.service.run() {
+        // process::exit doesn't run the destructors, so run() runs the cleanup hooks first
+        if let Err(error) = self.run() {
             error!(target: "runner", "{}", error);
-            process::exit(1);
+            process::exit(get_run_exit_code(error.as_ref()));
         }
 
         process::exit(0);
     }
 
+    /// Starts the application with a configuration parsed from the environment.
+    ///
+    /// The logger is installed first, according the `LF_LOG_FORMAT` variable,
+    /// unless a logger has already been installed.
     pub fn start_application (routes: Vec<Route>) {
-        let config = <U>::parse_environment().unwrap_or_else(|_error| {
-            process::exit(2);
+        Self::start_application_with_logger(routes, || initialize_logger().map(|_| ()));
+    }
+
+    /// Starts the application with a configuration parsed from the environment,
+    /// after installing the logger with the specified closure, e.g. to use a tracing subscriber.
+    pub fn start_application_with_logger<F> (routes: Vec<Route>, initialize_logger: F)
+        where F: FnOnce() -> ErrorResult<()>
+    {
+        let config = Self::prepare_configuration(initialize_logger).unwrap_or_else(|exit_code| {
+            process::exit(exit_code);
         });
 
         let mut app = Application::new(config, routes);
         app.start();
     }
+
+    /// Installs the logger, so the configuration errors are logged, then parses the configuration.
+    ///
+    /// Returns the exit code on failure.
+    fn prepare_configuration<F> (initialize_logger: F) -> Result<U, i32>
+        where F: FnOnce() -> ErrorResult<()>
+    {
+        Self::prepare_configuration_with(initialize_logger, <U>::parse_environment)
+    }
+
+    /// Installs the logger, then builds the configuration with the specified closure.
+    ///
+    /// The parse step is given as a closure, so the tests can use a stub configuration.
+    fn prepare_configuration_with<F, P> (initialize_logger: F, parse: P) -> Result<U, i32>
+        where F: FnOnce() -> ErrorResult<()>,
+              P: FnOnce() -> ErrorResult<U>
+    {
+        if let Err(error) = initialize_logger() {
+            eprintln!("{}", error);
+            return Err(2);
+        }
+
+        parse().map_err(|_error| 2)
+    }
+}
+
+/// Gets the exit code when the application run fails: 2 when the database migrations
+/// can't be applied, 1 otherwise.
+#[cfg_attr(not(feature = "migrations"), allow(unused_variables))]
+fn get_run_exit_code(error: &(dyn Error + 'static)) -> i32 {
+    #[cfg(feature = "migrations")]
+    {
+        if error.is::<MigrationError>() {
+            return 2;
+        }
+    }
+
+    1
 }
 
 /*   -------------------------------------------------------------
@@ -214,14 +1053,49 @@ impl<U> Application<U>
 /// ```
 ///
 /// The default configuration will be used and the server started.
-#[cfg(feature = "pgsql")]
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
 pub struct DefaultApplication {}
 
-#[cfg(feature = "pgsql")]
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
 impl DefaultApplication {
     pub fn start_application (routes: Vec<Route>) {
         Application::<DefaultConfig>::start_application(routes);
     }
+
+    /// Starts the application with an already built pool, instead of building it
+    /// from the `DATABASE_URL` variable.
+    #[cfg(feature = "pgsql")]
+    pub fn start_application_with_pool (routes: Vec<Route>, pool: PostgreSQLPool) {
+        let config = Self::prepare_configuration();
+
+        let service = DefaultService::with_pool(config, routes, pool);
+        Application::<DefaultConfig>::from_service(Box::new(service)).start();
+    }
+
+    /// Starts the application, applying the pending embedded migrations at startup
+    /// when the `LF_RUN_MIGRATIONS` variable is set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// embed_migrations!();
+    ///
+    /// DefaultApplication::start_application_with_migrations(routes, embedded_migrations::run_with_output);
+    /// ```
+    #[cfg(feature = "migrations")]
+    pub fn start_application_with_migrations (routes: Vec<Route>, migrations: EmbeddedMigrations) {
+        let service = Self::prepare_configuration()
+            .into_service_with(routes, |service| service.with_migrations(migrations));
+
+        Application::<DefaultConfig>::from_service(service).start();
+    }
+
+    /// Installs the logger, then parses the configuration, exiting the process on failure.
+    #[cfg(feature = "pgsql")]
+    fn prepare_configuration () -> DefaultConfig {
+        Application::<DefaultConfig>::prepare_configuration(|| initialize_logger().map(|_| ()))
+            .unwrap_or_else(|exit_code| process::exit(exit_code))
+    }
 }
 
 /*   -------------------------------------------------------------
@@ -238,3 +1112,522 @@ impl MinimalApplication {
         Application::<MinimalConfig>::start_application(routes);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::get_default_service_name;
+    use rocket::config::Limits;
+    use rocket::fairing::AdHoc;
+    use rocket::handler::Outcome;
+    use rocket::http::{ContentType, Status};
+    use rocket::local::Client;
+    use rocket::response::Body;
+    use std::io::Read;
+    use std::net::{IpAddr, SocketAddr};
+
+    fn status_handler<'r>(request: &'r Request, _data: Data) -> Outcome<'r> {
+        Outcome::from(request, "ALIVE")
+    }
+
+    #[test]
+    fn test_head_request_on_get_route () {
+        let routes = vec![Route::new(Method::Get, "/status", status_handler)];
+        let client = Client::untracked(::rocket::ignite().mount("/", routes)).unwrap();
+
+        let mut response = client.head("/status").dispatch();
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(Some(ContentType::Plain), response.content_type());
+
+        match response.body() {
+            Some(Body::Sized(mut body, size)) => {
+                let mut content = String::new();
+                body.read_to_string(&mut content).unwrap();
+
+                assert_eq!(5, size, "Content-Length should be the one of the GET body");
+                assert!(content.is_empty(), "HEAD response shouldn't have a body");
+            }
+            _ => panic!("HEAD response should have a sized empty body"),
+        }
+    }
+
+    struct TestConfig {
+        listen_targets: Vec<SocketAddr>,
+        address: Option<IpAddr>,
+        port: Option<u16>,
+        health_endpoint: Option<&'static str>,
+    }
+
+    impl Config for TestConfig {
+        fn get_database_url(&self) -> &str { "" }
+
+        fn get_entry_point(&self) -> &str { "/" }
+
+        fn get_database_pool_size(&self) -> u32 { 0 }
+
+        fn with_database(&self) -> bool { false }
+
+        fn into_service(self, _routes: Vec<Route>) -> Box<dyn Service> {
+            unreachable!()
+        }
+
+        fn get_listen_targets(&self) -> &[SocketAddr] { &self.listen_targets }
+
+        fn get_address(&self) -> Option<IpAddr> { self.address }
+
+        fn get_port(&self) -> Option<u16> { self.port }
+
+        fn get_health_endpoint(&self) -> Option<&str> { self.health_endpoint }
+    }
+
+    struct LimitedService {
+        config: TestConfig,
+        routes: Vec<Route>,
+    }
+
+    impl LimitedService {
+        fn new (listen_targets: Vec<SocketAddr>) -> Self {
+            Self {
+                config: TestConfig { listen_targets, address: None, port: None, health_endpoint: None },
+                routes: Vec::new(),
+            }
+        }
+    }
+
+    impl Service for LimitedService {
+        fn get_config(&self) -> &dyn Config { &self.config }
+
+        fn get_routes(&self) -> &[Route] { &self.routes }
+
+        fn launch_server(&mut self) -> ErrorResult<()> { Ok(()) }
+
+        fn check_service_configuration(&self) -> ErrorResult<()> { Ok(()) }
+
+        fn configure_rocket(&self, mut config: rocket_config::Config) -> rocket_config::Config {
+            config.set_limits(Limits::new().limit("json", 2048));
+
+            config
+        }
+    }
+
+    #[test]
+    fn test_configure_rocket () {
+        let service = LimitedService::new(Vec::new());
+
+        let rocket_config = service.get_rocket_configs().unwrap().remove(0);
+        let server = service.ignite_server(rocket_config).unwrap();
+        assert_eq!(Some(2048), server.config().limits.get("json"));
+    }
+
+    #[test]
+    fn test_get_rocket_configs_with_listen_targets () {
+        let service = LimitedService::new(vec![
+            "127.0.0.1:8001".parse().unwrap(),
+            "[::1]:8002".parse().unwrap(),
+        ]);
+
+        let rocket_configs = service.get_rocket_configs().unwrap();
+        assert_eq!(2, rocket_configs.len());
+
+        assert_eq!("127.0.0.1", rocket_configs[0].address);
+        assert_eq!(8001, rocket_configs[0].port);
+        assert_eq!("::1", rocket_configs[1].address);
+        assert_eq!(8002, rocket_configs[1].port);
+
+        // Custom settings from the hook apply to every target
+        assert_eq!(Some(2048), rocket_configs[1].limits.get("json"));
+    }
+
+    /// Sends a GET request to a server, retrying until it accepts the connection.
+    fn request_launched_server (target: &SocketAddr, path: &str) -> String {
+        use std::io::Write;
+        use std::net::TcpStream;
+        use std::time::Duration;
+
+        for _ in 0..100 {
+            if let Ok(mut stream) = TcpStream::connect(target) {
+                write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path).unwrap();
+
+                let mut response = String::new();
+                stream.read_to_string(&mut response).unwrap();
+
+                return response;
+            }
+
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        panic!("The server listening on {} hasn't started", target);
+    }
+
+    #[test]
+    fn test_launch_servers_with_listen_targets () {
+        use std::net::TcpListener;
+
+        // Both ports are reserved before being freed, so the system can't give the same one twice
+        let listeners: Vec<TcpListener> = (0..2).map(|_| TcpListener::bind("127.0.0.1:0").unwrap()).collect();
+        let targets: Vec<SocketAddr> = listeners.iter().map(|listener| listener.local_addr().unwrap()).collect();
+        drop(listeners);
+
+        let mut service = LimitedService::new(targets.clone());
+        service.routes = vec![Route::new(Method::Get, "/status", status_handler)];
+        let servers = service.build_servers().unwrap();
+
+        thread::spawn(move || {
+            let _ = launch_servers(servers);
+        });
+
+        for target in targets.iter() {
+            let response = request_launched_server(target, "/status");
+
+            assert!(response.starts_with("HTTP/1.1 200 OK"), "Unexpected response from {}: {}", target, response);
+            assert!(response.ends_with("ALIVE"), "Unexpected response from {}: {}", target, response);
+        }
+    }
+
+    #[test]
+    fn test_get_rocket_configs_with_port () {
+        let service = LimitedService {
+            config: TestConfig { listen_targets: Vec::new(), address: None, port: Some(8003), health_endpoint: None },
+            routes: Vec::new(),
+        };
+
+        let rocket_configs = service.get_rocket_configs().unwrap();
+        assert_eq!(1, rocket_configs.len());
+        assert_eq!(8003, rocket_configs[0].port);
+    }
+
+    #[test]
+    fn test_get_rocket_configs_with_address_and_port () {
+        let service = LimitedService {
+            config: TestConfig {
+                listen_targets: Vec::new(),
+                address: Some("::1".parse().unwrap()),
+                port: Some(8004),
+                health_endpoint: None,
+            },
+            routes: Vec::new(),
+        };
+
+        let rocket_configs = service.get_rocket_configs().unwrap();
+        assert_eq!("::1", rocket_configs[0].address);
+        assert_eq!(8004, rocket_configs[0].port);
+    }
+
+    #[test]
+    fn test_set_rocket_tls_with_missing_files () {
+        let mut rocket_config = read_rocket_config().unwrap();
+        let tls_paths = TlsPaths {
+            cert_path: String::from("/nonexistent/cert.pem"),
+            key_path: String::from("/nonexistent/key.pem"),
+        };
+
+        assert!(set_rocket_tls(&mut rocket_config, &tls_paths).is_err());
+    }
+
+    #[test]
+    fn test_health_route () {
+        let mut service = LimitedService::new(Vec::new());
+        service.config.health_endpoint = Some("/status");
+
+        let rocket_config = service.get_rocket_configs().unwrap().remove(0);
+        let client = Client::untracked(service.ignite_server(rocket_config).unwrap()).unwrap();
+
+        let mut response = client.get("/status").dispatch();
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(Some(String::from("ALIVE")), response.body_string());
+    }
+
+    #[test]
+    fn test_health_route_conditional_request () {
+        let mut service = LimitedService::new(Vec::new());
+        service.config.health_endpoint = Some("/status");
+
+        let rocket_config = service.get_rocket_configs().unwrap().remove(0);
+        let client = Client::untracked(service.ignite_server(rocket_config).unwrap()).unwrap();
+
+        let response = client.get("/status").dispatch();
+        let etag = response.headers().get_one("ETag").unwrap().to_string();
+        assert_eq!(Some("no-cache"), response.headers().get_one("Cache-Control"));
+
+        let mut response = client.get("/status").header(Header::new("If-None-Match", etag.clone())).dispatch();
+        assert_eq!(Status::NotModified, response.status());
+        assert_eq!(Some(etag.as_str()), response.headers().get_one("ETag"));
+        assert!(response.body().is_none());
+
+        let response = client.get("/status").header(Header::new("If-None-Match", "\"quux\"")).dispatch();
+        assert_eq!(Status::Ok, response.status());
+    }
+
+    #[test]
+    fn test_health_route_when_already_mounted () {
+        let mut service = LimitedService::new(Vec::new());
+        service.config.health_endpoint = Some("/status");
+        service.routes.push(Route::new(Method::Get, "/status", status_handler));
+
+        assert_eq!(None, service.get_health_route_path());
+        assert_eq!(vec!["GET /status"], service.dump_routes());
+    }
+
+    #[test]
+    fn test_find_builtin_route_conflicts () {
+        let mut service = LimitedService::new(Vec::new());
+        service.config.health_endpoint = Some("/status");
+        service.routes.push(Route::new(Method::Get, "/players", status_handler));
+        assert!(service.find_builtin_route_conflicts().is_empty());
+
+        service.routes.push(Route::new(Method::Post, "/status", status_handler));
+        assert!(service.find_builtin_route_conflicts().is_empty(), "Only GET routes overlap the health route");
+
+        service.routes.push(Route::new(Method::Get, "/status", status_handler));
+        assert_eq!(vec!["GET /status"], service.find_builtin_route_conflicts());
+    }
+
+    #[test]
+    fn test_check_builtin_route_conflicts () {
+        let conflicts = vec![String::from("GET /status")];
+
+        assert!(check_builtin_route_conflicts(&[], true).is_ok());
+        assert!(check_builtin_route_conflicts(&conflicts, false).is_ok(), "Conflicts should only be warned about");
+
+        let error = check_builtin_route_conflicts(&conflicts, true).unwrap_err();
+        assert!(error.to_string().contains("GET /status"));
+    }
+
+    #[test]
+    fn test_dump_routes () {
+        let mut service = LimitedService::new(Vec::new());
+        service.routes.push(Route::new(Method::Get, "/status", status_handler));
+        service.routes.push(Route::new(Method::Post, "/players", status_handler));
+
+        assert_eq!(vec!["GET /status", "POST /players"], service.dump_routes());
+    }
+
+    #[test]
+    #[cfg(feature = "pgsql")]
+    fn test_default_service_with_pool () {
+        use diesel::r2d2::{ConnectionManager, Pool};
+        use rocket::State;
+
+        fn get_pool_size<'r>(request: &'r Request, _data: Data) -> Outcome<'r> {
+            match request.guard::<State<PostgreSQLPool>>() {
+                ::rocket::Outcome::Success(pool) => Outcome::from(request, pool.max_size().to_string()),
+                _ => Outcome::Failure(Status::ServiceUnavailable),
+            }
+        }
+
+        let config = DefaultConfig::for_tests("postgres://unreachable.invalid/test", "/api", true);
+
+        let manager = ConnectionManager::<LoggingConnection<PgConnection>>::new("postgres://localhost/test");
+        let pool = Pool::builder().max_size(2).min_idle(Some(0)).build_unchecked(manager);
+
+        let routes = vec![Route::new(Method::Get, "/pool-size", get_pool_size)];
+        let service = DefaultService::with_pool(config, routes, pool);
+
+        // The injected pool is used as is, the database isn't probed
+        assert!(service.check_service_configuration().is_ok());
+
+        let server = service.build_servers().unwrap().remove(0);
+        let client = Client::untracked(server).unwrap();
+        let mut response = client.get("/api/pool-size").dispatch();
+
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(Some(String::from("2")), response.body_string());
+    }
+
+    #[test]
+    fn test_format_banner () {
+        let build_info = BuildInfo { version: "1.2.3", git_sha: None, build_timestamp: None };
+        assert_eq!("Server quux started (limiting-factor 1.2.3).", format_banner("quux", &build_info));
+
+        // Without override, the default service name is used
+        let service = LimitedService::new(Vec::new());
+        let banner = format_banner(service.get_config().get_service_name(), &build_info);
+        assert_eq!(format!("Server {} started (limiting-factor 1.2.3).", get_default_service_name()), banner);
+    }
+
+    #[test]
+    fn test_is_dump_routes_enabled () {
+        assert!(is_dump_routes_enabled(Some("1")), "The routes should be printed instead of serving them");
+        assert!(!is_dump_routes_enabled(Some("0")));
+        assert!(!is_dump_routes_enabled(Some("")));
+        assert!(!is_dump_routes_enabled(None));
+    }
+
+    #[test]
+    fn test_cleanup_hooks_run_in_reverse_order () {
+        use std::sync::{Arc, Mutex};
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut hooks = CleanupHooks::new();
+
+        let first_calls = calls.clone();
+        hooks.register(move || first_calls.lock().unwrap().push("close pool"));
+        let second_calls = calls.clone();
+        hooks.register(move || second_calls.lock().unwrap().push("remove temporary files"));
+
+        drop(hooks);
+
+        assert_eq!(vec!["remove temporary files", "close pool"], *calls.lock().unwrap());
+    }
+
+    #[test]
+    fn test_cleanup_hooks_run_once () {
+        let mut hooks = CleanupHooks::new();
+        hooks.register(|| {});
+
+        hooks.run();
+        assert!(hooks.is_empty());
+    }
+
+    #[test]
+    fn test_application_run_returns_result () {
+        use std::sync::{Arc, Mutex};
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let hook_calls = calls.clone();
+
+        let mut application = Application::<MinimalConfig>::from_service(Box::new(LimitedService::new(Vec::new())))
+            .on_shutdown(move || hook_calls.lock().unwrap().push("close pool"));
+
+        assert!(application.run().is_ok());
+        assert_eq!(vec!["close pool"], *calls.lock().unwrap());
+    }
+
+    #[test]
+    fn test_get_run_exit_code () {
+        let error: Box<dyn Error> = "Can't launch the server.".into();
+
+        assert_eq!(1, get_run_exit_code(error.as_ref()));
+    }
+
+    #[test]
+    #[cfg(feature = "migrations")]
+    fn test_get_run_exit_code_for_migrations () {
+        let error: Box<dyn Error> = Box::new(MigrationError(String::from("quux")));
+
+        assert_eq!(2, get_run_exit_code(error.as_ref()));
+    }
+
+    #[test]
+    fn test_dispatch_check_config () {
+        let mut application = Application::<MinimalConfig>::from_service(Box::new(LimitedService::new(Vec::new())));
+
+        let args = vec![String::from(CHECK_CONFIG_COMMAND)];
+        assert!(application.dispatch(&args).unwrap(), "The configuration should be checked without serving");
+    }
+
+    #[test]
+    fn test_dispatch_serve () {
+        let mut application = Application::<MinimalConfig>::from_service(Box::new(LimitedService::new(Vec::new())));
+
+        assert!(!application.dispatch(&[]).unwrap());
+        assert!(!application.dispatch(&[String::from(SERVE_COMMAND)]).unwrap());
+    }
+
+    #[test]
+    fn test_dispatch_env () {
+        let mut application = Application::<MinimalConfig>::from_service(Box::new(LimitedService::new(Vec::new())));
+
+        let args = vec![String::from(ENV_COMMAND), String::from("--describe")];
+        assert!(application.dispatch(&args).unwrap(), "The environment should be described without serving");
+
+        assert!(application.dispatch(&[String::from(ENV_COMMAND)]).is_err());
+    }
+
+    #[test]
+    fn test_dispatch_custom_command () {
+        use std::rc::Rc;
+        use std::cell::Cell;
+
+        let calls = Rc::new(Cell::new(0));
+        let command_calls = calls.clone();
+
+        let mut application = Application::<MinimalConfig>::from_service(Box::new(LimitedService::new(Vec::new())))
+            .with_command("migrate", move |_service| {
+                command_calls.set(command_calls.get() + 1);
+
+                Ok(())
+            });
+
+        assert!(application.dispatch(&[String::from("migrate")]).unwrap());
+        assert_eq!(1, calls.get());
+
+        // The other arguments belong to the application
+        assert!(!application.dispatch(&[String::from("unknown")]).unwrap());
+        assert!(!application.dispatch(&[String::from("--port"), String::from("8080")]).unwrap());
+        assert_eq!(1, calls.get());
+    }
+
+    #[test]
+    fn test_prepare_configuration_installs_logger_first () {
+        use std::cell::RefCell;
+
+        let steps = RefCell::new(Vec::new());
+        let config = Application::<MinimalConfig>::prepare_configuration_with(
+            || {
+                steps.borrow_mut().push("logger");
+
+                Ok(())
+            },
+            || {
+                steps.borrow_mut().push("parse");
+
+                Ok(MinimalConfig::for_tests("/quux"))
+            },
+        );
+
+        assert_eq!(vec!["logger", "parse"], *steps.borrow());
+        assert_eq!("/quux", config.unwrap().get_entry_point());
+    }
+
+    #[test]
+    fn test_prepare_configuration_when_logger_fails () {
+        use std::cell::Cell;
+
+        let parsed = Cell::new(false);
+        let config = Application::<MinimalConfig>::prepare_configuration_with(
+            || Err("No logger.".into()),
+            || {
+                parsed.set(true);
+
+                Ok(MinimalConfig::for_tests("/"))
+            },
+        );
+
+        assert_eq!(Some(2), config.err());
+        assert!(!parsed.get(), "The configuration shouldn't be parsed without logger");
+    }
+
+    #[test]
+    fn test_prepare_configuration_when_parse_fails () {
+        let config = Application::<MinimalConfig>::prepare_configuration_with(
+            || Ok(()),
+            || Err("The DATABASE_URL variable must be set.".into()),
+        );
+
+        assert_eq!(Some(2), config.err());
+    }
+
+    #[test]
+    fn test_warn_about_json_routes () {
+        let mut route = Route::new(Method::Post, "/players", status_handler);
+        route.format = Some(::rocket::http::MediaType::JSON);
+
+        let expected = if has_serialization() { 0 } else { 1 };
+        assert_eq!(expected, warn_about_json_routes(&[route]));
+    }
+
+    #[test]
+    fn test_catch_setup_panic () {
+        let result = catch_setup_panic(|| {
+            let server = ::rocket::ignite()
+                .attach(AdHoc::on_attach("Broken fairing", |_| panic!("quux")));
+
+            Ok(server)
+        });
+
+        let error = result.err().expect("A panic during setup should be an error");
+        assert_eq!("Server setup panicked: quux", error.to_string());
+    }
+}
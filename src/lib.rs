@@ -32,31 +32,57 @@
 //! Replacing `DefaultApplication` by `MinimalApplication` allows to use a lighter version
 //! of the library without Diesel dependencies or database use.
 
-#[cfg(feature = "pgsql")]
+#[cfg(feature = "anyhow")]
+extern crate anyhow;
+extern crate base64;
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
+#[cfg_attr(test, macro_use)]
 extern crate diesel;
+#[cfg(feature = "migrations")]
+extern crate diesel_migrations;
 extern crate dotenv;
+extern crate env_logger;
+#[cfg(feature = "serialization")]
+extern crate flate2;
+#[cfg(feature = "cursor-signing")]
+extern crate hmac;
+#[cfg(feature = "jwt")]
+extern crate jsonwebtoken;
 #[macro_use]
 extern crate log;
-#[cfg(feature = "pgsql")]
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
 extern crate r2d2;
 extern crate rocket;
 extern crate rocket_contrib;
 #[cfg(feature = "serialization")]
 extern crate serde;
+#[cfg(feature = "serialization")]
+extern crate serde_json;
+#[cfg(feature = "serialization")]
+extern crate serde_urlencoded;
+#[cfg(feature = "cursor-signing")]
+extern crate sha2;
+extern crate ulid;
+extern crate uuid;
+#[cfg(feature = "validation")]
+extern crate validator;
 
 /*   -------------------------------------------------------------
      Public modules offered by this crate
      - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
 
 pub mod api;
+pub mod build_info;
 pub mod config;
 pub mod kernel;
+pub mod logger;
+pub mod scheduler;
 
 /*   -------------------------------------------------------------
      Optional public features modules offered by this crate
      - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
 
-#[cfg(feature = "pgsql")]
+#[cfg(any(feature = "pgsql", feature = "mysql", feature = "sqlite"))]
 pub mod database;
 
 /*   -------------------------------------------------------------
@@ -0,0 +1,316 @@
+//! # Logger
+//!
+//! This module installs the logger, before the configuration is parsed,
+//! so the configuration errors and the startup banner are logged.
+//!
+//! By default, the log lines are human-readable, filtered by the `RUST_LOG` variable
+//! like with env_logger, else by the `LF_LOG_LEVEL` variable. They can also be emitted as structured JSON, one object by line,
+//! for log aggregation.
+//!
+//! A logger already installed, e.g. by the application, is kept. As Rocket can't install
+//! its own logger then, its messages go through the installed one.
+
+use config::get_default_service_name;
+use ErrorResult;
+use log::LevelFilter;
+#[cfg(feature = "serialization")]
+use log::{Log, Metadata, Record};
+#[cfg(feature = "serialization")]
+use serde_json;
+use std::env;
+#[cfg(feature = "serialization")]
+use std::io::{self, Write};
+#[cfg(test)]
+use std::sync::Mutex;
+use std::sync::OnceLock;
+#[cfg(feature = "serialization")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/*   -------------------------------------------------------------
+     Log format
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The formats available for the log lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines, as printed by env_logger
+    Text,
+
+    /// A JSON object by line
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse (name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "text" => Some(LogFormat::Text),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/*   -------------------------------------------------------------
+     Service name
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// The name of the service added to the log records, when set by the configuration
+static SERVICE_NAME: OnceLock<String> = OnceLock::new();
+
+/// Sets the service name added to the log records, returning false if it has already been set.
+pub fn set_service_name (name: String) -> bool {
+    SERVICE_NAME.set(name).is_ok()
+}
+
+/// Gets the service name added to the log records.
+///
+/// The records logged before the configuration is parsed use the default service name.
+pub fn get_service_name () -> &'static str {
+    SERVICE_NAME.get()
+        .map(|name| name.as_str())
+        .unwrap_or_else(get_default_service_name)
+}
+
+/*   -------------------------------------------------------------
+     JSON logger
+
+     :: Log
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A logger writing each record as a JSON object to stderr.
+#[cfg(feature = "serialization")]
+pub struct JsonLogger {
+    level: LevelFilter,
+}
+
+#[cfg(feature = "serialization")]
+impl JsonLogger {
+    pub fn new (level: LevelFilter) -> Self {
+        Self {
+            level,
+        }
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let _ = writeln!(io::stderr(), "{}", format_record(record));
+        }
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
+
+/// Formats a log record as a JSON object with timestamp, service, level, target and message fields.
+///
+/// The timestamp is the number of seconds since the Unix epoch.
+#[cfg(feature = "serialization")]
+pub fn format_record(record: &Record) -> String {
+    format_record_for_service(record, get_service_name())
+}
+
+/// Formats a log record as a JSON object, for the specified service.
+#[cfg(feature = "serialization")]
+pub fn format_record_for_service(record: &Record, service_name: &str) -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs_f64())
+        .unwrap_or(0.0);
+
+    serde_json::json!({
+        "timestamp": timestamp,
+        "service": service_name,
+        "level": record.level().to_string(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    }).to_string()
+}
+
+/*   -------------------------------------------------------------
+     Logger setup
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// Reads the `LF_LOG_LEVEL` environment variable, by default `info`.
+fn read_log_level() -> ErrorResult<LevelFilter> {
+    parse_log_level(env::var("LF_LOG_LEVEL").ok().as_deref())
+}
+
+fn parse_log_level(value: Option<&str>) -> ErrorResult<LevelFilter> {
+    match value {
+        None => Ok(LevelFilter::Info),
+        Some(value) => value.parse().map_err(|_| {
+            format!("The LF_LOG_LEVEL variable must be off, error, warn, info, debug or trace, not {}.", value).into()
+        }),
+    }
+}
+
+/// Installs the logger according the `LF_LOG_FORMAT` environment variable,
+/// `text` (by default) or `json`, and the `LF_LOG_LEVEL` one.
+///
+/// Returns true if the logger has been installed, false if a logger was already installed.
+pub fn initialize_logger() -> ErrorResult<bool> {
+    let format = match env::var("LF_LOG_FORMAT") {
+        Ok(variable) => LogFormat::parse(&variable)
+            .ok_or_else(|| format!("The LF_LOG_FORMAT variable must be text or json, not {}.", variable))?,
+        Err(_) => LogFormat::Text,
+    };
+    let level = read_log_level()?;
+
+    match format {
+        LogFormat::Text => Ok(install_text_logger(level)),
+        LogFormat::Json => install_json_logger(level),
+    }
+}
+
+/// Installs a human-readable logger, filtered by the `RUST_LOG` variable if set,
+/// else by the specified level.
+fn install_text_logger(level: LevelFilter) -> bool {
+    let env = ::env_logger::Env::default().default_filter_or(level.to_string());
+
+    ::env_logger::Builder::from_env(env).try_init().is_ok()
+}
+
+#[cfg(feature = "serialization")]
+fn install_json_logger(level: LevelFilter) -> ErrorResult<bool> {
+    let is_installed = ::log::set_boxed_logger(Box::new(JsonLogger::new(level))).is_ok();
+    if is_installed {
+        ::log::set_max_level(level);
+    }
+
+    Ok(is_installed)
+}
+
+#[cfg(not(feature = "serialization"))]
+fn install_json_logger(_level: LevelFilter) -> ErrorResult<bool> {
+    Err("The JSON log format requires the serialization feature.".into())
+}
+
+/*   -------------------------------------------------------------
+     Capturing logger
+
+     :: Log
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A logger keeping the records in memory, so the tests can check what is logged.
+///
+/// It isn't installed as global logger, as the tests run in parallel and Rocket
+/// installs its own logger: the code to test should log to an injected `Log`.
+#[cfg(test)]
+pub struct CapturingLogger {
+    records: Mutex<Vec<String>>,
+}
+
+#[cfg(test)]
+impl CapturingLogger {
+    pub fn new () -> Self {
+        Self {
+            records: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Gets the captured records, formatted as `LEVEL target: message`.
+    pub fn get_records (&self) -> Vec<String> {
+        self.records.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl ::log::Log for CapturingLogger {
+    fn enabled(&self, _metadata: &::log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &::log::Record) {
+        let line = format!("{} {}: {}", record.level(), record.target(), record.args());
+        self.records.lock().unwrap().push(line);
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "serialization")]
+    use log::Level;
+
+    #[test]
+    fn test_capturing_logger () {
+        use log::Log;
+
+        let logger = CapturingLogger::new();
+        logger.log(&::log::Record::builder()
+            .args(format_args!("Server started."))
+            .level(::log::Level::Info)
+            .target("runner")
+            .build());
+
+        assert_eq!(vec!["INFO runner: Server started."], logger.get_records());
+    }
+
+    #[test]
+    fn test_parse_log_format () {
+        assert_eq!(Some(LogFormat::Text), LogFormat::parse("text"));
+        assert_eq!(Some(LogFormat::Json), LogFormat::parse("JSON"));
+        assert_eq!(None, LogFormat::parse("logfmt"));
+    }
+
+    #[test]
+    fn test_parse_log_level () {
+        assert_eq!(LevelFilter::Info, parse_log_level(None).unwrap());
+        assert_eq!(LevelFilter::Debug, parse_log_level(Some("debug")).unwrap());
+        assert_eq!(LevelFilter::Off, parse_log_level(Some("OFF")).unwrap());
+        assert!(parse_log_level(Some("verbose")).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_json_logger_level () {
+        let debug = Metadata::builder().level(Level::Debug).target("database").build();
+        let trace = Metadata::builder().level(Level::Trace).target("database").build();
+
+        assert!(!JsonLogger::new(LevelFilter::Info).enabled(&debug));
+
+        let logger = JsonLogger::new(parse_log_level(Some("debug")).unwrap());
+        assert!(logger.enabled(&debug));
+        assert!(!logger.enabled(&trace));
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_format_record () {
+        let line = format_record(&Record::builder()
+            .args(format_args!("Server started."))
+            .level(Level::Info)
+            .target("runner")
+            .build());
+
+        let value: serde_json::Value = serde_json::from_str(&line).expect("The record should be formatted as JSON");
+        assert_eq!("INFO", value["level"]);
+        assert_eq!("runner", value["target"]);
+        assert_eq!("Server started.", value["message"]);
+        assert!(value["timestamp"].is_number());
+    }
+
+    #[test]
+    #[cfg(feature = "serialization")]
+    fn test_format_record_for_service () {
+        let record = Record::builder()
+            .args(format_args!("Server started."))
+            .level(Level::Info)
+            .target("runner")
+            .build();
+
+        let value: serde_json::Value = serde_json::from_str(&format_record_for_service(&record, "quux")).unwrap();
+        assert_eq!("quux", value["service"]);
+        assert_eq!("runner", value["target"]);
+    }
+}
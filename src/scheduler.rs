@@ -0,0 +1,185 @@
+//! # Background tasks scheduler
+//!
+//! This module allows to run periodic jobs, like a cache refresh or a cleanup,
+//! in the same process as the server.
+//!
+//! Each task runs in its own thread, at a fixed interval. There is no cron syntax.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/*   -------------------------------------------------------------
+     Scheduler
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A periodic job
+struct Task {
+    name: String,
+    interval: Duration,
+    job: Box<dyn Fn() + Send + 'static>,
+}
+
+/// A scheduler to register periodic tasks, then start them.
+///
+/// # Examples
+///
+/// ```
+/// let scheduler = Scheduler::new()
+///     .every("sessions cleanup", Duration::from_secs(3600), || {
+///         delete_expired_sessions();
+///     });
+///
+/// Application::<DefaultConfig>::new(config, routes)
+///     .with_scheduler(scheduler)
+///     .start();
+/// ```
+#[derive(Default)]
+pub struct Scheduler {
+    tasks: Vec<Task>,
+}
+
+impl Scheduler {
+    pub fn new () -> Self {
+        Self::default()
+    }
+
+    /// Registers a job to run every `interval`, the first run being after one interval.
+    pub fn every<F> (mut self, name: &str, interval: Duration, job: F) -> Self
+        where F: Fn() + Send + 'static
+    {
+        self.tasks.push(Task {
+            name: name.to_string(),
+            interval,
+            job: Box::new(job),
+        });
+
+        self
+    }
+
+    pub fn len (&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty (&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Starts a thread for each task.
+    pub fn start (self) -> SchedulerHandle {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+
+        let threads = self.tasks
+            .into_iter()
+            .map(|task| {
+                let stop = stop.clone();
+
+                thread::spawn(move || run_task(task, stop))
+            })
+            .collect();
+
+        SchedulerHandle {
+            stop,
+            threads,
+        }
+    }
+}
+
+/// Runs the task at its interval, until the stop flag is set.
+fn run_task (task: Task, stop: Arc<(Mutex<bool>, Condvar)>) {
+    let (ref is_stopped, ref condition) = *stop;
+    let mut next_run = Instant::now() + task.interval;
+
+    loop {
+        {
+            let mut stopped = is_stopped.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+            loop {
+                if *stopped {
+                    return;
+                }
+
+                let now = Instant::now();
+                if now >= next_run {
+                    break;
+                }
+
+                stopped = condition.wait_timeout(stopped, next_run - now)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .0;
+            }
+        }
+
+        if panic::catch_unwind(AssertUnwindSafe(|| (task.job)())).is_err() {
+            error!(target: "scheduler", "The task {} panicked.", task.name);
+        }
+
+        next_run += task.interval;
+    }
+}
+
+/*   -------------------------------------------------------------
+     Scheduler handle
+     - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - */
+
+/// A handle to the running tasks, allowing to stop them.
+pub struct SchedulerHandle {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl SchedulerHandle {
+    /// Stops the tasks, waiting for the running jobs to finish.
+    pub fn shutdown (self) {
+        {
+            let (ref is_stopped, ref condition) = *self.stop;
+            *is_stopped.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = true;
+            condition.notify_all();
+        }
+
+        for thread in self.threads {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_task_runs_until_shutdown () {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let task_counter = counter.clone();
+
+        let handle = Scheduler::new()
+            .every("counter", Duration::from_millis(10), move || {
+                task_counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .start();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while counter.load(Ordering::SeqCst) == 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        handle.shutdown();
+
+        let runs = counter.load(Ordering::SeqCst);
+        assert!(runs >= 1, "The task should have run at least once before shutdown");
+
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(runs, counter.load(Ordering::SeqCst), "The task shouldn't run after shutdown");
+    }
+
+    #[test]
+    fn test_shutdown_before_first_run () {
+        let handle = Scheduler::new()
+            .every("never", Duration::from_secs(3600), || panic!("The task shouldn't run"))
+            .start();
+
+        handle.shutdown();
+    }
+}